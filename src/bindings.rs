@@ -0,0 +1,325 @@
+//! Keyboard bindings of the windowed viewer, loaded from a TOML or JSON file
+//! with `--bindings`.
+//!
+//! Keys are named like winit's `KeyCode` variants and mapped to an
+//! [`Action`]. A `[keys]` table replaces the whole default map, which
+//! `--print-bindings` writes out as a starting point, e.g.
+//!
+//! ```toml
+//! [keys]
+//! KeyD = "increase-move-speed"
+//! KeyA = "decrease-move-speed"
+//! Space = "pause"
+//! F12 = "screenshot"
+//!
+//! [steps]
+//! moveSpeed = 5.0
+//! speedFactor = 1.5
+//! ```
+
+use std::{collections::BTreeMap, fmt, ops::RangeInclusive, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ConfigError, DiffuseSettings, SpeciesSettings};
+
+/// Everything a key can do in the viewer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    IncreaseMoveSpeed,
+    DecreaseMoveSpeed,
+    IncreaseTurnSpeed,
+    DecreaseTurnSpeed,
+    IncreaseSensorAngle,
+    DecreaseSensorAngle,
+    IncreaseSensorOffset,
+    DecreaseSensorOffset,
+    IncreaseSensorSize,
+    DecreaseSensorSize,
+    IncreaseRepellentSensitivity,
+    DecreaseRepellentSensitivity,
+    IncreaseDiffuseRate,
+    DecreaseDiffuseRate,
+    IncreaseDecayRate,
+    DecreaseDecayRate,
+    IncreaseDepositIntensity,
+    DecreaseDepositIntensity,
+    /// Multiplies the simulation speed by `steps.speedFactor`
+    SpeedUp,
+    SlowDown,
+    Pause,
+    /// Runs one step while paused
+    SingleStep,
+    /// Sleeps for 20 ms, to see the fixed timestep catch up
+    Stall,
+    SaveCheckpoint,
+    Screenshot,
+    NextPreset,
+    PreviousPreset,
+    /// Shows or hides the parameter overlay
+    ToggleHud,
+    /// Switches the left mouse button between painting and a gravity well
+    ToggleGravityWell,
+    /// Paints into every trail layer, then each species' in turn
+    CycleBrushSpecies,
+}
+
+impl Action {
+    /// The parameter an `Increase*` or `Decrease*` action changes, and
+    /// whether it goes up (1) or down (-1).
+    pub fn adjustment(self) -> Option<(Parameter, f32)> {
+        use Action::*;
+        let (parameter, sign) = match self {
+            IncreaseMoveSpeed => (Parameter::MoveSpeed, 1.0),
+            DecreaseMoveSpeed => (Parameter::MoveSpeed, -1.0),
+            IncreaseTurnSpeed => (Parameter::TurnSpeed, 1.0),
+            DecreaseTurnSpeed => (Parameter::TurnSpeed, -1.0),
+            IncreaseSensorAngle => (Parameter::SensorAngle, 1.0),
+            DecreaseSensorAngle => (Parameter::SensorAngle, -1.0),
+            IncreaseSensorOffset => (Parameter::SensorOffset, 1.0),
+            DecreaseSensorOffset => (Parameter::SensorOffset, -1.0),
+            IncreaseSensorSize => (Parameter::SensorSize, 1.0),
+            DecreaseSensorSize => (Parameter::SensorSize, -1.0),
+            IncreaseRepellentSensitivity => (Parameter::RepellentSensitivity, 1.0),
+            DecreaseRepellentSensitivity => (Parameter::RepellentSensitivity, -1.0),
+            IncreaseDiffuseRate => (Parameter::DiffuseRate, 1.0),
+            DecreaseDiffuseRate => (Parameter::DiffuseRate, -1.0),
+            IncreaseDecayRate => (Parameter::DecayRate, 1.0),
+            DecreaseDecayRate => (Parameter::DecayRate, -1.0),
+            IncreaseDepositIntensity => (Parameter::DepositIntensity, 1.0),
+            DecreaseDepositIntensity => (Parameter::DepositIntensity, -1.0),
+            _ => return None,
+        };
+        Some((parameter, sign))
+    }
+}
+
+/// A species or diffusion setting keys can step up and down.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parameter {
+    MoveSpeed,
+    TurnSpeed,
+    SensorAngle,
+    SensorOffset,
+    SensorSize,
+    RepellentSensitivity,
+    DiffuseRate,
+    DecayRate,
+    DepositIntensity,
+}
+
+impl Parameter {
+    /// Values [`crate::Config::validate`] accepts. Keys never turn
+    /// `turnSpeed` positive, though a config file may.
+    fn range(self) -> RangeInclusive<f32> {
+        match self {
+            Parameter::TurnSpeed => f32::MIN..=0.0,
+            Parameter::RepellentSensitivity => f32::MIN..=f32::MAX,
+            Parameter::SensorAngle => 0.0..=180.0,
+            Parameter::SensorSize => 0.0..=16.0,
+            _ => 0.0..=f32::MAX,
+        }
+    }
+
+    /// Adds `amount` to this parameter of every species, or to the diffusion
+    /// setting, staying within what a config file may set. Returns the new
+    /// value, of the first species for species settings.
+    pub fn adjust(
+        self,
+        amount: f32,
+        species: &mut [SpeciesSettings],
+        diffuse: &mut DiffuseSettings,
+    ) -> f32 {
+        let range = self.range();
+        let step = |value: &mut f32| {
+            *value = (*value + amount).clamp(*range.start(), *range.end());
+            *value
+        };
+        let field: fn(&mut SpeciesSettings) -> &mut f32 = match self {
+            Parameter::DiffuseRate => return step(&mut diffuse.diffuse_rate),
+            Parameter::DecayRate => return step(&mut diffuse.decay_rate),
+            Parameter::DepositIntensity => return step(&mut diffuse.deposit_intensity),
+            Parameter::MoveSpeed => |species| &mut species.moveSpeed,
+            Parameter::TurnSpeed => |species| &mut species.turnSpeed,
+            Parameter::SensorAngle => |species| &mut species.sensorAngleDegrees,
+            Parameter::SensorOffset => |species| &mut species.sensorOffsetDst,
+            Parameter::SensorSize => |species| &mut species.sensorSize,
+            Parameter::RepellentSensitivity => |species| &mut species.repellentSensitivity,
+        };
+        for species in species.iter_mut() {
+            step(field(species));
+        }
+        species.first_mut().map_or(0.0, |species| *field(species))
+    }
+}
+
+impl fmt::Display for Parameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Parameter::MoveSpeed => "move speed",
+            Parameter::TurnSpeed => "turn speed",
+            Parameter::SensorAngle => "sensor angle",
+            Parameter::SensorOffset => "sensor offset",
+            Parameter::SensorSize => "sensor size",
+            Parameter::RepellentSensitivity => "repellent sensitivity",
+            Parameter::DiffuseRate => "diffuse rate",
+            Parameter::DecayRate => "decay rate",
+            Parameter::DepositIntensity => "deposit intensity",
+        })
+    }
+}
+
+/// How far each press of an `Increase*` or `Decrease*` key moves its
+/// parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct StepSizes {
+    pub move_speed: f32,
+    pub turn_speed: f32,
+    pub sensor_angle_degrees: f32,
+    pub sensor_offset_dst: f32,
+    pub sensor_size: f32,
+    pub repellent_sensitivity: f32,
+    pub diffuse_rate: f32,
+    pub decay_rate: f32,
+    pub deposit_intensity: f32,
+    /// What [`Action::SpeedUp`] multiplies and [`Action::SlowDown`] divides
+    /// the simulation speed by.
+    pub speed_factor: f32,
+}
+
+impl Default for StepSizes {
+    fn default() -> Self {
+        Self {
+            move_speed: 1.0,
+            turn_speed: 1.0,
+            sensor_angle_degrees: 5.0,
+            sensor_offset_dst: 1.0,
+            sensor_size: 1.0,
+            repellent_sensitivity: 0.5,
+            diffuse_rate: 1.0,
+            decay_rate: 0.05,
+            deposit_intensity: 0.001,
+            speed_factor: 2.0,
+        }
+    }
+}
+
+impl StepSizes {
+    pub fn get(&self, parameter: Parameter) -> f32 {
+        match parameter {
+            Parameter::MoveSpeed => self.move_speed,
+            Parameter::TurnSpeed => self.turn_speed,
+            Parameter::SensorAngle => self.sensor_angle_degrees,
+            Parameter::SensorOffset => self.sensor_offset_dst,
+            Parameter::SensorSize => self.sensor_size,
+            Parameter::RepellentSensitivity => self.repellent_sensitivity,
+            Parameter::DiffuseRate => self.diffuse_rate,
+            Parameter::DecayRate => self.decay_rate,
+            Parameter::DepositIntensity => self.deposit_intensity,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bindings {
+    /// winit `KeyCode` names, e.g. `KeyD`, `F5` or `BracketLeft`.
+    pub keys: BTreeMap<String, Action>,
+    pub steps: StepSizes,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        use Action::*;
+        let keys = [
+            ("KeyD", IncreaseMoveSpeed),
+            ("KeyA", DecreaseMoveSpeed),
+            ("KeyQ", IncreaseTurnSpeed),
+            ("KeyE", DecreaseTurnSpeed),
+            ("KeyR", IncreaseSensorAngle),
+            ("KeyF", DecreaseSensorAngle),
+            ("KeyW", IncreaseSensorOffset),
+            ("KeyS", DecreaseSensorOffset),
+            ("KeyX", IncreaseSensorSize),
+            ("KeyZ", DecreaseSensorSize),
+            ("KeyV", IncreaseRepellentSensitivity),
+            ("KeyC", DecreaseRepellentSensitivity),
+            ("Digit2", IncreaseDiffuseRate),
+            ("Digit1", DecreaseDiffuseRate),
+            ("Digit4", IncreaseDecayRate),
+            ("Digit3", DecreaseDecayRate),
+            ("Digit6", IncreaseDepositIntensity),
+            ("Digit5", DecreaseDepositIntensity),
+            ("BracketRight", SpeedUp),
+            ("BracketLeft", SlowDown),
+            ("Space", Pause),
+            ("Period", SingleStep),
+            ("KeyL", Stall),
+            ("F5", SaveCheckpoint),
+            ("F12", Screenshot),
+            ("PageDown", NextPreset),
+            ("PageUp", PreviousPreset),
+            ("F1", ToggleHud),
+            ("KeyG", ToggleGravityWell),
+            ("Tab", CycleBrushSpecies),
+        ];
+        Self {
+            keys: keys
+                .into_iter()
+                .map(|(key, action)| (key.to_owned(), action))
+                .collect(),
+            steps: StepSizes::default(),
+        }
+    }
+}
+
+impl Bindings {
+    /// Reads `path` as JSON if it has a `.json` extension and as TOML
+    /// otherwise, then checks the step sizes with [`Bindings::validate`].
+    /// Key names are left for the viewer to check.
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path)?;
+        let bindings: Bindings = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => {
+                serde_json::from_str(&text).map_err(|e| ConfigError::Parse(e.to_string()))?
+            }
+            _ => toml::from_str(&text).map_err(|e| ConfigError::Parse(e.to_string()))?,
+        };
+        bindings.validate()?;
+        Ok(bindings)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let steps = &self.steps;
+        for (field, step) in [
+            ("steps.moveSpeed", steps.move_speed),
+            ("steps.turnSpeed", steps.turn_speed),
+            ("steps.sensorAngleDegrees", steps.sensor_angle_degrees),
+            ("steps.sensorOffsetDst", steps.sensor_offset_dst),
+            ("steps.sensorSize", steps.sensor_size),
+            ("steps.repellentSensitivity", steps.repellent_sensitivity),
+            ("steps.diffuseRate", steps.diffuse_rate),
+            ("steps.decayRate", steps.decay_rate),
+            ("steps.depositIntensity", steps.deposit_intensity),
+        ] {
+            crate::config::check(field, step, 0.0..=f64::MAX, "a finite number >= 0")?;
+        }
+        crate::config::check(
+            "steps.speedFactor",
+            steps.speed_factor,
+            1.0..=f64::MAX,
+            "a finite number >= 1",
+        )
+    }
+
+    pub fn action(&self, key: &str) -> Option<Action> {
+        self.keys.get(key).copied()
+    }
+
+    /// The bindings as a TOML file [`Bindings::load`] reads back.
+    pub fn to_toml(&self) -> String {
+        toml::to_string(self).expect("bindings always serialize")
+    }
+}
@@ -0,0 +1,122 @@
+//! Mouse brushes that edit a running simulation.
+//!
+//! A [`Brush`] is one dab of a tool at a point on the trail map, applied
+//! between steps by the passes in `brush.wgsl` or by
+//! [`crate::cpu::CpuSimulation::brush`]. The viewer sends one per frame while
+//! a button is held, scaling `amount` by the time since the last frame.
+
+use crate::{cpu::triple32, food::ALL_SPECIES, BrushParams, ShaderParams};
+
+/// Matches the `TOOL_*` constants in `brush.wgsl`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BrushTool {
+    /// Adds `amount` to the trail under the brush
+    #[default]
+    Paint = 0,
+    /// Takes `amount` (up to 1) of the trail under the brush away, in every
+    /// layer
+    Erase = 1,
+    /// Relocates `amount` agents, taken in turn from the whole population, to
+    /// random places and headings under the brush. Fractions of an agent are
+    /// carried over to the next brush, see [`Relocations`].
+    ///
+    /// This stands in for spawning new agents at the cursor: the agent buffer
+    /// and every bind group over it are sized once at startup, so the
+    /// population can't grow. It only gathers under the brush.
+    Relocate = 2,
+    /// Pulls agents under the brush up to `amount` texels towards its centre,
+    /// most strongly near the middle. Agents are not pulled into walls.
+    Attract = 3,
+}
+
+/// One application of `tool` to the disk of `radius` around (`x`, `y`), in
+/// trail map texels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Brush {
+    pub tool: BrushTool,
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+    pub amount: f32,
+    /// The trail layer [`BrushTool::Paint`] paints into, or
+    /// [`ALL_SPECIES`]. Painting every layer evenly cancels out for species
+    /// that avoid each other's trails.
+    pub species: u32,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            tool: BrushTool::Paint,
+            x: 0.0,
+            y: 0.0,
+            radius: 16.0,
+            amount: 1.0,
+            species: ALL_SPECIES,
+        }
+    }
+}
+
+/// Where successive [`BrushTool::Relocate`] brushes have got to in the
+/// population, and the part of an agent the last one left over, banked like
+/// the part frames of a recording.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Relocations {
+    next: u32,
+    carry: f32,
+}
+
+impl Relocations {
+    /// Banks `amount` more agents and returns the first and number of the
+    /// whole ones now due, never more than the population.
+    fn take(&mut self, amount: f32, num_agents: u32) -> (u32, u32) {
+        self.carry += amount.max(0.0);
+        let whole = self.carry.floor();
+        self.carry -= whole;
+        let count = (whole as u32).min(num_agents);
+        let first = self.next;
+        self.next = (self.next + count) % num_agents.max(1);
+        (first, count)
+    }
+}
+
+impl Brush {
+    /// Whether the brush works on the agents rather than the trail map.
+    pub fn moves_agents(&self) -> bool {
+        matches!(self.tool, BrushTool::Relocate | BrushTool::Attract)
+    }
+
+    /// The shader parameters for this brush on a simulation currently running
+    /// with `shader_params`. A [`BrushTool::Relocate`] brush takes its agents
+    /// from `relocations`; the seed and frame pick where they go.
+    pub fn params(
+        &self,
+        shader_params: &ShaderParams,
+        relocations: &mut Relocations,
+    ) -> BrushParams {
+        let num_agents = shader_params.numAgents as u32;
+        let (first_relocated, num_relocated) = if self.tool == BrushTool::Relocate {
+            relocations.take(self.amount, num_agents)
+        } else {
+            (0, 0)
+        };
+        BrushParams {
+            tool: self.tool as u32,
+            x: self.x,
+            y: self.y,
+            radius: self.radius,
+            amount: self.amount,
+            species: self.species,
+            firstRelocated: first_relocated,
+            numRelocated: num_relocated,
+            numAgents: num_agents,
+            seed: triple32(
+                shader_params
+                    .frame
+                    .wrapping_add(triple32(shader_params.seed)),
+            ),
+            width: shader_params.width,
+            height: shader_params.height,
+        }
+    }
+}
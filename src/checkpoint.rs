@@ -0,0 +1,222 @@
+//! Snapshots of the whole simulation state, so a run can be stopped and picked
+//! up again exactly where it was.
+//!
+//! A checkpoint file is a fixed header followed by the raw `Pod` data, in the
+//! machine's (little-endian on every supported target) byte order:
+//!
+//! | bytes | contents |
+//! |---|---|
+//! | 8 | [`MAGIC`] |
+//! | 4 | format [`VERSION`] |
+//! | 16 | width, height, agent count, species count |
+//! | 40 | [`ShaderParams`], including the RNG seed and step counter |
+//! | 40 per species | [`SpeciesSettings`] |
+//! | 16 per agent | [`Agent`] |
+//! | 4 per texel per species, twice | [`TrailMap::trail`] then [`TrailMap::deposited`] |
+//! | 4 per texel | [`crate::FoodMap::food`] left |
+//! | 4 per texel | [`crate::RepellentMap::values`], painting included |
+
+use std::{
+    fmt,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    cpu::TrailMap, Agent, BoundaryMode, Config, ShaderParams, SpeciesSettings, MAX_SPECIES,
+};
+
+pub const MAGIC: [u8; 8] = *b"SLIMECKP";
+/// Bumped whenever the layout of the file or of any struct in it changes.
+pub const VERSION: u32 = 4;
+
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    pub shader_params: ShaderParams,
+    pub species: Vec<SpeciesSettings>,
+    pub agents: Vec<Agent>,
+    pub trail_map: TrailMap,
+    /// The food left on each texel; where it started comes from the config.
+    pub food: Vec<f32>,
+    /// The repellent on each texel, as it may have been painted over.
+    pub repellent: Vec<f32>,
+}
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    NotACheckpoint,
+    UnsupportedVersion(u32),
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(e) => write!(f, "could not read checkpoint: {}", e),
+            CheckpointError::NotACheckpoint => write!(f, "not a checkpoint file"),
+            CheckpointError::UnsupportedVersion(version) => write!(
+                f,
+                "checkpoint version {} is not supported, expected {}",
+                version, VERSION
+            ),
+            CheckpointError::Corrupt(reason) => write!(f, "corrupt checkpoint: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(e: io::Error) -> Self {
+        CheckpointError::Io(e)
+    }
+}
+
+impl Checkpoint {
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+        self.write_to(&mut file)?;
+        file.flush()
+    }
+
+    pub fn load(path: &Path) -> Result<Self, CheckpointError> {
+        let file = std::fs::File::open(path)?;
+        let len = file.metadata()?.len();
+        Self::read_from(io::BufReader::new(file), len)
+    }
+
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        let trail_map = &self.trail_map;
+        writer.write_all(&MAGIC)?;
+        writer.write_all(bytemuck::bytes_of(&VERSION))?;
+        writer.write_all(bytemuck::cast_slice(&[
+            trail_map.width,
+            trail_map.height,
+            self.agents.len() as u32,
+            self.species.len() as u32,
+        ]))?;
+        writer.write_all(bytemuck::bytes_of(&self.shader_params))?;
+        writer.write_all(bytemuck::cast_slice(&self.species))?;
+        writer.write_all(bytemuck::cast_slice(&self.agents))?;
+        writer.write_all(bytemuck::cast_slice(&trail_map.trail))?;
+        writer.write_all(bytemuck::cast_slice(&trail_map.deposited))?;
+        writer.write_all(bytemuck::cast_slice(&self.food))?;
+        writer.write_all(bytemuck::cast_slice(&self.repellent))?;
+        Ok(())
+    }
+
+    /// Reads a checkpoint of `len` bytes. The header must account for exactly
+    /// that many, so a damaged one can't ask for more memory than the file
+    /// could fill.
+    pub fn read_from(mut reader: impl Read, len: u64) -> Result<Self, CheckpointError> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(CheckpointError::NotACheckpoint);
+        }
+        let [version]: [u32; 1] = read_pod(&mut reader)?;
+        if version != VERSION {
+            return Err(CheckpointError::UnsupportedVersion(version));
+        }
+        let [width, height, num_agents, num_species]: [u32; 4] = read_pod(&mut reader)?;
+        if !(1..=MAX_SPECIES as u32).contains(&num_species) {
+            return Err(CheckpointError::Corrupt("species count out of range"));
+        }
+        // The sizes `Config::validate` accepts
+        if width == 0 || height == 0 || num_agents == 0 {
+            return Err(CheckpointError::Corrupt("empty trail map or no agents"));
+        }
+        if data_len(width, height, num_agents, num_species) != Some(len) {
+            return Err(CheckpointError::Corrupt(
+                "file length does not match the header",
+            ));
+        }
+        let [shader_params]: [ShaderParams; 1] = read_pod(&mut reader)?;
+        if shader_params.width != width as f32
+            || shader_params.height != height as f32
+            || shader_params.numAgents != num_agents as f32
+        {
+            return Err(CheckpointError::Corrupt(
+                "shader parameters do not match the header",
+            ));
+        }
+        if BoundaryMode::from_u32(shader_params.boundaryMode).is_none() {
+            return Err(CheckpointError::Corrupt("unknown boundary mode"));
+        }
+        let species = read_vec(&mut reader, num_species as usize)?;
+        let agents: Vec<Agent> = read_vec(&mut reader, num_agents as usize)?;
+        if agents.iter().any(|agent| agent.speciesIndex >= num_species) {
+            return Err(CheckpointError::Corrupt("agent with an unknown species"));
+        }
+        let map_texels = (width as usize)
+            .checked_mul(height as usize)
+            .ok_or(CheckpointError::Corrupt("trail map too large"))?;
+        let texels = map_texels
+            .checked_mul(num_species as usize)
+            .ok_or(CheckpointError::Corrupt("trail map too large"))?;
+        let trail_map = TrailMap {
+            width,
+            height,
+            layers: num_species,
+            trail: read_vec(&mut reader, texels)?,
+            deposited: read_vec(&mut reader, texels)?,
+        };
+        Ok(Self {
+            shader_params,
+            species,
+            agents,
+            trail_map,
+            food: read_vec(&mut reader, map_texels)?,
+            repellent: read_vec(&mut reader, map_texels)?,
+        })
+    }
+
+    /// Makes `config` describe the checkpointed simulation, so a
+    /// [`crate::Simulation`] built from it can [`crate::Simulation::restore`]
+    /// this checkpoint. Settings the checkpoint does not hold, like the render
+    /// scale, are left alone.
+    pub fn apply_to(&self, config: &mut Config) {
+        let params = &self.shader_params;
+        config.simulation.width = self.trail_map.width;
+        config.simulation.height = self.trail_map.height;
+        config.simulation.num_agents = self.agents.len() as u32;
+        config.simulation.seed = Some(params.seed);
+        if let Some(boundary) = BoundaryMode::from_u32(params.boundaryMode) {
+            config.simulation.boundary = boundary;
+        }
+        config.species = self.species.clone();
+        config.diffuse.diffuse_rate = params.diffuseRate;
+        config.diffuse.decay_rate = params.decayRate;
+        config.diffuse.deposit_intensity = params.depositIntensity;
+    }
+}
+
+/// The length of a checkpoint file with this header, or `None` if it
+/// overflows.
+fn data_len(width: u32, height: u32, num_agents: u32, num_species: u32) -> Option<u64> {
+    let header = MAGIC.len() + 4 + 16 + std::mem::size_of::<ShaderParams>();
+    let texels = u64::from(width) * u64::from(height);
+    // Trail and deposited per species, then food and repellent
+    let maps = texels.checked_mul(2 * u64::from(num_species) + 2)?;
+    (header as u64
+        + u64::from(num_species) * std::mem::size_of::<SpeciesSettings>() as u64
+        + u64::from(num_agents) * std::mem::size_of::<Agent>() as u64)
+        .checked_add(maps.checked_mul(std::mem::size_of::<f32>() as u64)?)
+}
+
+fn read_pod<T: bytemuck::Pod, const N: usize>(reader: impl Read) -> io::Result<[T; N]> {
+    let mut values = [T::zeroed(); N];
+    read_into(reader, &mut values)?;
+    Ok(values)
+}
+
+fn read_vec<T: bytemuck::Pod>(reader: impl Read, len: usize) -> io::Result<Vec<T>> {
+    let mut values = vec![T::zeroed(); len];
+    read_into(reader, &mut values)?;
+    Ok(values)
+}
+
+fn read_into<T: bytemuck::Pod>(mut reader: impl Read, values: &mut [T]) -> io::Result<()> {
+    reader.read_exact(bytemuck::cast_slice_mut(values))
+}
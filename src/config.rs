@@ -0,0 +1,624 @@
+//! Simulation parameters loaded from a TOML or JSON file.
+//!
+//! Every section and key is optional; anything left out keeps the built-in
+//! default. Keys use the same camelCase names as the WGSL structs, e.g.
+//!
+//! ```toml
+//! [simulation]
+//! width = 1920
+//! height = 1080
+//! numAgents = 1000000
+//! scale = 2.0
+//! seed = 42
+//! stepRate = 120.0
+//! maxSubsteps = 8
+//! speed = 1.5
+//! boundary = "wrap"
+//!
+//! [spawn]
+//! mode = "ring-inward"
+//! radius = 400.0
+//!
+//! # One table per species, up to `MAX_SPECIES`
+//! [[species]]
+//! moveSpeed = 80.0
+//! sensorAngleDegrees = 45.0
+//!
+//! [[species]]
+//! colourR = 1.0
+//! colourG = 0.2
+//! colourB = 0.2
+//!
+//! [diffuse]
+//! decayRate = 0.5
+//!
+//! [obstacles]
+//! image = "maze.png"
+//! colourB = 0.6
+//!
+//! [food]
+//! depletion = 0.5
+//!
+//! [[food.sources]]
+//! x = 300.0
+//! y = 200.0
+//! radius = 12.0
+//!
+//! [[repellent.circles]]
+//! x = 900.0
+//! y = 500.0
+//! radius = 80.0
+//! ```
+
+use std::{
+    fmt,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    timestep::{MAX_SPEED, MIN_SPEED},
+    FoodSettings, ObstacleMask, ObstacleSettings, RepellentSettings, SpawnMode, SpawnSettings,
+    Spawner, SpeciesSettings, MAX_SPECIES,
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub simulation: SimulationConfig,
+    pub spawn: SpawnSettings,
+    pub species: Vec<SpeciesSettings>,
+    pub diffuse: DiffuseSettings,
+    pub obstacles: ObstacleSettings,
+    pub food: FoodSettings,
+    pub repellent: RepellentSettings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            simulation: SimulationConfig::default(),
+            spawn: SpawnSettings::default(),
+            species: vec![SpeciesSettings::default()],
+            diffuse: DiffuseSettings::default(),
+            obstacles: ObstacleSettings::default(),
+            food: FoodSettings::default(),
+            repellent: RepellentSettings::default(),
+        }
+    }
+}
+
+/// Sizes that are fixed once a [`crate::Simulation`] has been created.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct SimulationConfig {
+    pub width: u32,
+    pub height: u32,
+    pub num_agents: u32,
+    /// How many trail map texels each pixel of the colour pass covers, so 2.0
+    /// renders a `width / 2 x height / 2` image.
+    pub scale: f32,
+    /// Seed for agent placement and the per-step RNG; a random one is drawn
+    /// if this is `None`.
+    pub seed: Option<u32>,
+    /// Simulation steps per second of wall-clock time, see
+    /// [`crate::FixedTimestep`].
+    pub step_rate: f32,
+    /// Most steps run for one rendered frame; a frame slower than this many
+    /// steps slows the simulation down instead.
+    pub max_substeps: u32,
+    /// Multiplier on the `delta` each step feeds to the shaders.
+    pub speed: f32,
+    pub boundary: BoundaryMode,
+}
+
+impl Default for SimulationConfig {
+    fn default() -> Self {
+        Self {
+            width: 3840,
+            height: 2160,
+            num_agents: 1 << 23,
+            scale: 1.0,
+            seed: None,
+            step_rate: 60.0,
+            max_substeps: 8,
+            speed: 1.0,
+            boundary: BoundaryMode::Clamp,
+        }
+    }
+}
+
+impl SimulationConfig {
+    /// Size of the image the colour pass renders the trail map into.
+    pub fn render_size(&self) -> (u32, u32) {
+        crate::render::render_size(self.width, self.height, self.scale)
+    }
+
+    /// Checks the trail map, the colour pass target and the `obstacles` mask
+    /// fit in a texture on a device with `limits`.
+    pub fn check_texture_limits(
+        &self,
+        limits: &wgpu::Limits,
+        obstacles: &ObstacleMask,
+    ) -> Result<(), ConfigError> {
+        check_texture_size(
+            ("simulation.width", "simulation.height"),
+            (self.width, self.height),
+            limits,
+        )?;
+        check_texture_size(
+            ("render width", "render height"),
+            self.render_size(),
+            limits,
+        )?;
+        check_texture_size(
+            ("obstacles.image width", "obstacles.image height"),
+            (obstacles.width, obstacles.height),
+            limits,
+        )?;
+        Ok(())
+    }
+
+    /// Checks everything [`crate::Simulation`] allocates and dispatches fits
+    /// within `limits`.
+    pub fn check_limits(
+        &self,
+        limits: &wgpu::Limits,
+        obstacles: &ObstacleMask,
+        spawner: &Spawner,
+    ) -> Result<(), ConfigError> {
+        self.check_texture_limits(limits, obstacles)?;
+
+        // Only the GPU simulation uploads the spawn image
+        let luminance = spawner.luminance();
+        check_texture_size(
+            ("spawn.image width", "spawn.image height"),
+            (luminance.width, luminance.height),
+            limits,
+        )?;
+
+        // The agent buffer may be bound in several slices, but it is still one
+        // buffer
+        let agent_bytes = u64::from(self.num_agents) * std::mem::size_of::<crate::Agent>() as u64;
+        check_limit(
+            "agent buffer size",
+            agent_bytes,
+            "max_buffer_size",
+            limits.max_buffer_size,
+        )?;
+
+        // Rgba32Float, read back a row at a time by `Simulation::read_trail_map`
+        let trail_bytes = u64::from(crate::simulation::padded_bytes_per_row(self.width * 16))
+            * u64::from(self.height);
+        check_limit(
+            "trail map size",
+            trail_bytes,
+            "max_buffer_size",
+            limits.max_buffer_size,
+        )?;
+
+        // The food and repellent buffers hold one f32 per texel, each bound whole
+        let texel_bytes = u64::from(self.width) * u64::from(self.height) * 4;
+        check_limit(
+            "food and repellent buffer size",
+            texel_bytes,
+            "max_storage_buffer_binding_size",
+            limits.max_storage_buffer_binding_size.into(),
+        )?;
+        Ok(())
+    }
+}
+
+/// What happens to agents that step off the map. Matches the `BOUNDARY_*`
+/// constants in `slime.wgsl` and `diffuse.wgsl`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum BoundaryMode {
+    /// Leave on one side and come back on the other; sensing and diffusion
+    /// wrap around too
+    Wrap = 0,
+    /// Bounce off the edges like a mirror
+    Reflect = 1,
+    /// Stop at the edge and turn to a random heading
+    #[default]
+    Clamp = 2,
+    /// Die at the edge and respawn in the spawn region
+    Respawn = 3,
+}
+
+impl BoundaryMode {
+    /// Inverse of `mode as u32`.
+    pub fn from_u32(mode: u32) -> Option<Self> {
+        match mode {
+            0 => Some(BoundaryMode::Wrap),
+            1 => Some(BoundaryMode::Reflect),
+            2 => Some(BoundaryMode::Clamp),
+            3 => Some(BoundaryMode::Respawn),
+            _ => None,
+        }
+    }
+}
+
+/// Trail field parameters used by the `update` and `diffuse` passes.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct DiffuseSettings {
+    pub diffuse_rate: f32,
+    pub decay_rate: f32,
+    /// Trail deposited by each agent per step, before scaling by `delta`.
+    pub deposit_intensity: f32,
+}
+
+impl Default for DiffuseSettings {
+    fn default() -> Self {
+        Self {
+            diffuse_rate: 10.0,
+            decay_rate: 0.25,
+            deposit_intensity: 0.009,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(std::io::Error),
+    Parse(String),
+    Image {
+        path: PathBuf,
+        error: String,
+    },
+    Missing {
+        field: &'static str,
+        reason: &'static str,
+    },
+    OutOfRange {
+        field: String,
+        value: String,
+        expected: String,
+    },
+    ExceedsLimit {
+        what: &'static str,
+        value: u64,
+        limit_name: &'static str,
+        limit: u64,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "could not read config: {}", e),
+            ConfigError::Parse(e) => write!(f, "could not parse config: {}", e),
+            ConfigError::Image { path, error } => {
+                write!(f, "could not load {}: {}", path.display(), error)
+            }
+            ConfigError::Missing { field, reason } => {
+                write!(f, "{} must be set {}", field, reason)
+            }
+            ConfigError::OutOfRange {
+                field,
+                value,
+                expected,
+            } => write!(
+                f,
+                "{} = {} is out of range, expected {}",
+                field, value, expected
+            ),
+            ConfigError::ExceedsLimit {
+                what,
+                value,
+                limit_name,
+                limit,
+            } => write!(
+                f,
+                "{} = {} exceeds the adapter's {} of {}",
+                what, value, limit_name, limit
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(e: std::io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl Config {
+    /// Reads `path` as JSON if it has a `.json` extension and as TOML
+    /// otherwise, then checks every value with [`Config::validate`].
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = std::fs::read_to_string(path)?;
+        let config: Config = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => {
+                serde_json::from_str(&text).map_err(|e| ConfigError::Parse(e.to_string()))?
+            }
+            _ => toml::from_str(&text).map_err(|e| ConfigError::Parse(e.to_string()))?,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        const NON_NEGATIVE: &str = "a finite number >= 0";
+        const UNIT: &str = "0 to 1";
+
+        let simulation = &self.simulation;
+        check(
+            "simulation.width",
+            simulation.width,
+            1.0..=f64::MAX,
+            "at least 1",
+        )?;
+        check(
+            "simulation.height",
+            simulation.height,
+            1.0..=f64::MAX,
+            "at least 1",
+        )?;
+        check(
+            "simulation.numAgents",
+            simulation.num_agents,
+            1.0..=f64::MAX,
+            "at least 1",
+        )?;
+        check(
+            "simulation.scale",
+            simulation.scale,
+            0.25..=16.0,
+            "0.25 to 16",
+        )?;
+
+        check(
+            "simulation.stepRate",
+            simulation.step_rate,
+            1.0..=10000.0,
+            "1 to 10000",
+        )?;
+        check(
+            "simulation.maxSubsteps",
+            simulation.max_substeps,
+            1.0..=1000.0,
+            "1 to 1000",
+        )?;
+        check(
+            "simulation.speed",
+            simulation.speed,
+            MIN_SPEED.into()..=MAX_SPEED.into(),
+            &format!("1/{} to {}", 1.0 / MIN_SPEED, MAX_SPEED),
+        )?;
+
+        check(
+            "spawn.radius",
+            self.spawn.radius,
+            0.0..=f64::MAX,
+            NON_NEGATIVE,
+        )?;
+        if self.spawn.mode == SpawnMode::Image && self.spawn.image.is_none() {
+            return Err(ConfigError::Missing {
+                field: "spawn.image",
+                reason: "when spawn.mode is \"image\"",
+            });
+        }
+
+        check(
+            "species",
+            self.species.len() as u32,
+            1.0..=MAX_SPECIES as f64,
+            &format!("1 to {} entries", MAX_SPECIES),
+        )?;
+        for (i, species) in self.species.iter().enumerate() {
+            let field = |name: &str| format!("species[{}].{}", i, name);
+            check(
+                &field("moveSpeed"),
+                species.moveSpeed,
+                0.0..=f64::MAX,
+                NON_NEGATIVE,
+            )?;
+            check(
+                &field("turnSpeed"),
+                species.turnSpeed,
+                f64::MIN..=f64::MAX,
+                "a finite number",
+            )?;
+            check(
+                &field("sensorAngleDegrees"),
+                species.sensorAngleDegrees,
+                0.0..=180.0,
+                "0 to 180",
+            )?;
+            check(
+                &field("sensorOffsetDst"),
+                species.sensorOffsetDst,
+                0.0..=f64::MAX,
+                NON_NEGATIVE,
+            )?;
+            check(
+                &field("sensorSize"),
+                species.sensorSize,
+                0.0..=16.0,
+                "0 to 16",
+            )?;
+            check(&field("colourR"), species.colourR, 0.0..=1.0, UNIT)?;
+            check(&field("colourG"), species.colourG, 0.0..=1.0, UNIT)?;
+            check(&field("colourB"), species.colourB, 0.0..=1.0, UNIT)?;
+            check(&field("colourA"), species.colourA, 0.0..=1.0, UNIT)?;
+            check(
+                &field("repellentSensitivity"),
+                species.repellentSensitivity,
+                f64::MIN..=f64::MAX,
+                "a finite number",
+            )?;
+        }
+
+        let diffuse = &self.diffuse;
+        check(
+            "diffuse.diffuseRate",
+            diffuse.diffuse_rate,
+            0.0..=f64::MAX,
+            NON_NEGATIVE,
+        )?;
+        check(
+            "diffuse.decayRate",
+            diffuse.decay_rate,
+            0.0..=f64::MAX,
+            NON_NEGATIVE,
+        )?;
+        check(
+            "diffuse.depositIntensity",
+            diffuse.deposit_intensity,
+            0.0..=f64::MAX,
+            NON_NEGATIVE,
+        )?;
+
+        let obstacles = &self.obstacles;
+        check("obstacles.colourR", obstacles.colour_r, 0.0..=1.0, UNIT)?;
+        check("obstacles.colourG", obstacles.colour_g, 0.0..=1.0, UNIT)?;
+        check("obstacles.colourB", obstacles.colour_b, 0.0..=1.0, UNIT)?;
+        check("obstacles.colourA", obstacles.colour_a, 0.0..=1.0, UNIT)?;
+
+        let food = &self.food;
+        check("food.amount", food.amount, 0.0..=f64::MAX, NON_NEGATIVE)?;
+        check(
+            "food.depletion",
+            food.depletion,
+            0.0..=f64::MAX,
+            NON_NEGATIVE,
+        )?;
+        if let Some(species) = food.species {
+            check(
+                "food.species",
+                species,
+                0.0..=(self.species.len() - 1) as f64,
+                "the index of a species",
+            )?;
+        }
+        for (i, source) in food.sources.iter().enumerate() {
+            let field = |name: &str| format!("food.sources[{}].{}", i, name);
+            check(
+                &field("x"),
+                source.x,
+                f64::MIN..=f64::MAX,
+                "a finite number",
+            )?;
+            check(
+                &field("y"),
+                source.y,
+                f64::MIN..=f64::MAX,
+                "a finite number",
+            )?;
+            check(
+                &field("radius"),
+                source.radius,
+                0.0..=f64::MAX,
+                NON_NEGATIVE,
+            )?;
+            check(
+                &field("strength"),
+                source.strength,
+                0.0..=f64::MAX,
+                NON_NEGATIVE,
+            )?;
+        }
+
+        let repellent = &self.repellent;
+        check(
+            "repellent.strength",
+            repellent.strength,
+            f64::MIN..=f64::MAX,
+            "a finite number",
+        )?;
+        for (i, circle) in repellent.circles.iter().enumerate() {
+            let field = |name: &str| format!("repellent.circles[{}].{}", i, name);
+            check(
+                &field("x"),
+                circle.x,
+                f64::MIN..=f64::MAX,
+                "a finite number",
+            )?;
+            check(
+                &field("y"),
+                circle.y,
+                f64::MIN..=f64::MAX,
+                "a finite number",
+            )?;
+            check(
+                &field("radius"),
+                circle.radius,
+                0.0..=f64::MAX,
+                NON_NEGATIVE,
+            )?;
+            check(
+                &field("strength"),
+                circle.strength,
+                f64::MIN..=f64::MAX,
+                "a finite number",
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Fails unless `value` is finite and within `range`.
+pub(crate) fn check(
+    field: &str,
+    value: impl Into<f64> + Copy + fmt::Display,
+    range: RangeInclusive<f64>,
+    expected: &str,
+) -> Result<(), ConfigError> {
+    let number = value.into();
+    if number.is_finite() && range.contains(&number) {
+        Ok(())
+    } else {
+        Err(ConfigError::OutOfRange {
+            field: field.to_owned(),
+            value: value.to_string(),
+            expected: expected.to_owned(),
+        })
+    }
+}
+
+/// Fails if either side of a `width x height` texture is larger than
+/// `max_texture_dimension_2d`.
+fn check_texture_size(
+    (width_name, height_name): (&'static str, &'static str),
+    (width, height): (u32, u32),
+    limits: &wgpu::Limits,
+) -> Result<(), ConfigError> {
+    let max_dimension = limits.max_texture_dimension_2d.into();
+    check_limit(
+        width_name,
+        width.into(),
+        "max_texture_dimension_2d",
+        max_dimension,
+    )?;
+    check_limit(
+        height_name,
+        height.into(),
+        "max_texture_dimension_2d",
+        max_dimension,
+    )
+}
+
+/// Fails if `value` is larger than the device limit `limit_name`.
+fn check_limit(
+    what: &'static str,
+    value: u64,
+    limit_name: &'static str,
+    limit: u64,
+) -> Result<(), ConfigError> {
+    if value <= limit {
+        Ok(())
+    } else {
+        Err(ConfigError::ExceedsLimit {
+            what,
+            value,
+            limit_name,
+            limit,
+        })
+    }
+}
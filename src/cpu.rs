@@ -0,0 +1,876 @@
+//! CPU reference implementation of the `update` (slime.wgsl) and `diffuse`
+//! (diffuse.wgsl) kernels, and of the brushes in brush.wgsl.
+//!
+//! The maths follows the shaders line for line, including the places where
+//! they read the agent's angle before it is steered, so a GPU trail map can be
+//! compared against [`CpuSimulation`] after the same number of steps.
+
+use rayon::prelude::*;
+
+use crate::{
+    brush::{BrushTool, Relocations},
+    checkpoint::Checkpoint,
+    food::{disk_texels, ALL_SPECIES},
+    Agent, BoundaryMode, Brush, BrushParams, Config, DiffuseSettings, FoodMap, ObstacleMask,
+    RepellentMap, ShaderParams, Spawner, SpeciesSettings,
+};
+
+/// Agents handed to each rayon task by [`update_agents_parallel`].
+pub const AGENT_CHUNK_SIZE: usize = 16384;
+
+const PI: f32 = std::f32::consts::PI;
+const PI_OVER_180: f32 = PI / 180.0;
+const TWO_PI: f32 = std::f32::consts::TAU;
+
+/// The trail texture as two float grids, matching the channels the shaders use:
+/// `trail` is the red channel that agents sense, `deposited` is the blue
+/// channel `update` writes `trail + deposit` into and `diffuse` blurs. Both
+/// hold `layers` (one per species) `width x height` grids back to back.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TrailMap {
+    pub width: u32,
+    pub height: u32,
+    pub layers: u32,
+    pub trail: Vec<f32>,
+    pub deposited: Vec<f32>,
+}
+
+impl TrailMap {
+    pub fn new(width: u32, height: u32, layers: u32) -> Self {
+        let len = (width * height * layers) as usize;
+        Self {
+            width,
+            height,
+            layers,
+            trail: vec![0.0; len],
+            deposited: vec![0.0; len],
+        }
+    }
+
+    /// The texels of the `Rgba32Float` trail texture this map stands for:
+    /// red is `trail`, blue is `deposited`, green is 0 and alpha is 1.
+    pub fn to_texels(&self) -> Vec<[f32; 4]> {
+        self.trail
+            .iter()
+            .zip(&self.deposited)
+            .map(|(&trail, &deposited)| [trail, 0.0, deposited, 1.0])
+            .collect()
+    }
+
+    fn index(&self, x: i32, y: i32, layer: u32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            None
+        } else {
+            let layer_start = layer as usize * (self.width * self.height) as usize;
+            Some(layer_start + y as usize * self.width as usize + x as usize)
+        }
+    }
+
+    /// Out-of-bounds loads read as zero, like a robust-access storage texture.
+    fn load_trail(&self, x: i32, y: i32, layer: u32) -> f32 {
+        self.index(x, y, layer).map_or(0.0, |i| self.trail[i])
+    }
+}
+
+pub fn triple32(x: u32) -> u32 {
+    let mut y = x;
+    y ^= y >> 17;
+    y = y.wrapping_mul(0xed5ad4bb);
+    y ^= y >> 11;
+    y = y.wrapping_mul(0xac4c1b51);
+    y ^= y >> 15;
+    y = y.wrapping_mul(0x31848bab);
+    y ^= y >> 14;
+    y
+}
+
+pub fn scale_to_range01(state: u32) -> f32 {
+    state as f32 / 4294967295.0
+}
+
+/// WGSL `sign`, which unlike [`f32::signum`] returns 0 for 0.
+fn sign(x: f32) -> f32 {
+    if x > 0.0 {
+        1.0
+    } else if x < 0.0 {
+        -1.0
+    } else {
+        0.0
+    }
+}
+
+/// What `update` reads besides the agents and the trail map.
+#[derive(Clone, Copy)]
+pub struct Surroundings<'a> {
+    /// Only used by [`BoundaryMode::Respawn`].
+    pub spawner: &'a Spawner,
+    pub obstacles: &'a ObstacleMask,
+    pub repellent: &'a RepellentMap,
+}
+
+/// Sums the trail around one sensor, less the repellent there. With
+/// [`BoundaryMode::Wrap`] the samples wrap around the map, otherwise anything
+/// off the map reads as zero.
+pub fn sense(
+    agent: &Agent,
+    settings: &SpeciesSettings,
+    sensor_angle_offset: f32,
+    boundary: BoundaryMode,
+    repellent: &RepellentMap,
+    trail_map: &TrailMap,
+) -> f32 {
+    let sensor_angle = agent.angle + sensor_angle_offset;
+    let sensor_pos_x = agent.posX + sensor_angle.cos() * settings.sensorOffsetDst;
+    let sensor_pos_y = agent.posY + sensor_angle.sin() * settings.sensorOffsetDst;
+    let sensor_centre_x = sensor_pos_x as i32;
+    let sensor_centre_y = sensor_pos_y as i32;
+    let sensor_size = settings.sensorSize as i32;
+
+    let mut sum = 0.0;
+    for offset_x in -sensor_size..=sensor_size {
+        for offset_y in -sensor_size..=sensor_size {
+            let mut sample_x = sensor_centre_x + offset_x;
+            let mut sample_y = sensor_centre_y + offset_y;
+            if boundary == BoundaryMode::Wrap {
+                sample_x = sample_x.rem_euclid(trail_map.width as i32);
+                sample_y = sample_y.rem_euclid(trail_map.height as i32);
+            }
+            // Attracted to the agent's own species, repelled by every other one
+            for layer in 0..trail_map.layers {
+                let weight = if layer == agent.speciesIndex {
+                    1.0
+                } else {
+                    -1.0
+                };
+                sum += weight * trail_map.load_trail(sample_x, sample_y, layer);
+            }
+            // and pushed away by the repellent
+            sum -= settings.repellentSensitivity * repellent.at(sample_x, sample_y);
+        }
+    }
+    sum
+}
+
+/// Mirrors `wrapCoordinate` in `slime.wgsl`.
+fn wrap_coordinate(value: f32, size: f32) -> f32 {
+    let wrapped = value - (value / size).floor() * size;
+    // Tiny negative values round up to `size`
+    if wrapped >= size {
+        0.0
+    } else {
+        wrapped
+    }
+}
+
+/// Mirrors `reflectCoordinate` in `slime.wgsl`.
+fn reflect_coordinate(value: f32, size: f32) -> f32 {
+    if value < 0.0 {
+        -value
+    } else if value >= size {
+        2.0 * size - value
+    } else {
+        value
+    }
+}
+
+/// Mirrors `clampToMap` in `slime.wgsl`, one coordinate at a time.
+fn clamp_to_map(value: f32, size: f32) -> f32 {
+    (size - 1.0).min(0.0f32.max(value))
+}
+
+/// One invocation of `slime.wgsl::update` for the agent at index `id`, which
+/// uses the entry of `species` selected by its `speciesIndex`.
+pub fn update_agent(
+    id: u32,
+    agent: &mut Agent,
+    species: &[SpeciesSettings],
+    params: &ShaderParams,
+    surroundings: Surroundings,
+    trail_map: &mut TrailMap,
+) {
+    if let Some(cell) = steer_and_move(id, agent, species, params, surroundings, trail_map) {
+        deposit(cell, params, trail_map);
+    }
+}
+
+/// Everything `update` does apart from the trail write: senses, steers and
+/// moves `agent`, returning the cell it now deposits into, if it is on the map.
+fn steer_and_move(
+    id: u32,
+    agent: &mut Agent,
+    species: &[SpeciesSettings],
+    params: &ShaderParams,
+    surroundings: Surroundings,
+    trail_map: &TrailMap,
+) -> Option<usize> {
+    let Surroundings {
+        spawner,
+        obstacles,
+        repellent,
+    } = surroundings;
+    let original = *agent;
+    let settings = &species[original.speciesIndex as usize];
+    let boundary = BoundaryMode::from_u32(params.boundaryMode).unwrap_or_default();
+    let mut random = triple32(
+        ((original.posY * params.width + original.posX) as u32).wrapping_add(triple32(
+            id.wrapping_add(triple32(params.frame.wrapping_add(triple32(params.seed)))),
+        )),
+    );
+
+    // Steer based on sensory data
+    let sensor_angle_rad = settings.sensorAngleDegrees * PI_OVER_180;
+    let sense = |offset| sense(&original, settings, offset, boundary, repellent, trail_map);
+    let weight_forward = sense(0.0);
+    let weight_left = sense(sensor_angle_rad);
+    let weight_right = sense(-sensor_angle_rad);
+
+    let random_steer_strength = scale_to_range01(random);
+    let turn_speed = settings.turnSpeed * TWO_PI;
+
+    let should_turn_randomly =
+        ((sign(weight_left - weight_forward) + sign(weight_right - weight_forward)) / 2.0)
+            .clamp(0.0, 1.0);
+    let should_turn_normally =
+        ((sign(weight_forward - weight_left) - sign(weight_forward - weight_right)) / 2.0).abs();
+    agent.angle +=
+        (random_steer_strength - 0.5) * 2.0 * turn_speed * params.delta * should_turn_randomly;
+    agent.angle += should_turn_normally
+        * sign(weight_left - weight_right)
+        * (random_steer_strength * turn_speed * params.delta);
+
+    // The shader moves along the heading it had before steering
+    let mut new_x = original.posX + original.angle.cos() * params.delta * settings.moveSpeed;
+    let mut new_y = original.posY + original.angle.sin() * params.delta * settings.moveSpeed;
+
+    // Keep the agent on the map
+    let (width, height) = (params.width, params.height);
+    if new_x < 0.0 || new_y < 0.0 || new_x >= width || new_y >= height {
+        match boundary {
+            BoundaryMode::Wrap => {
+                new_x = wrap_coordinate(new_x, width);
+                new_y = wrap_coordinate(new_y, height);
+            }
+            BoundaryMode::Reflect => {
+                if new_x < 0.0 || new_x >= width {
+                    agent.angle = PI - agent.angle;
+                }
+                if new_y < 0.0 || new_y >= height {
+                    agent.angle = -agent.angle;
+                }
+                new_x = clamp_to_map(reflect_coordinate(new_x, width), width);
+                new_y = clamp_to_map(reflect_coordinate(new_y, height), height);
+            }
+            BoundaryMode::Respawn => {
+                let respawned = spawner.place_agent(triple32(random), original.speciesIndex);
+                agent.angle = respawned.angle;
+                // Spawn regions may reach past the edges
+                new_x = clamp_to_map(respawned.posX, width);
+                new_y = clamp_to_map(respawned.posY, height);
+            }
+            BoundaryMode::Clamp => {
+                random = triple32(random);
+                agent.angle = scale_to_range01(random) * TWO_PI;
+                new_x = clamp_to_map(new_x, width);
+                new_y = clamp_to_map(new_y, height);
+            }
+        }
+    }
+
+    // Walls block the way: stay put and turn somewhere else. Agents that
+    // started inside a wall may walk out of it.
+    let is_wall =
+        |x: f32, y: f32| obstacles.is_wall(x as i32, y as i32, trail_map.width, trail_map.height);
+    if is_wall(new_x, new_y) && !is_wall(original.posX, original.posY) {
+        random = triple32(random);
+        agent.angle = scale_to_range01(random) * TWO_PI;
+        new_x = original.posX;
+        new_y = original.posY;
+    }
+    agent.posX = new_x;
+    agent.posY = new_y;
+
+    trail_map.index(new_x as i32, new_y as i32, original.speciesIndex)
+}
+
+/// Concurrent deposits on the same cell all write the same value on the GPU,
+/// so they do not accumulate within a step and can be applied in any order.
+fn deposit(cell: usize, params: &ShaderParams, trail_map: &mut TrailMap) {
+    let trail_intensity = params.depositIntensity * params.delta * 10.0;
+    trail_map.deposited[cell] = trail_map.trail[cell] + trail_intensity;
+}
+
+/// The `update` dispatch: every agent senses the trail left by the previous
+/// step and deposits into [`TrailMap::deposited`].
+pub fn update_agents(
+    agents: &mut [Agent],
+    species: &[SpeciesSettings],
+    params: &ShaderParams,
+    surroundings: Surroundings,
+    trail_map: &mut TrailMap,
+) {
+    let num_agents = (params.numAgents as usize).min(agents.len());
+    for (id, agent) in agents[..num_agents].iter_mut().enumerate() {
+        update_agent(id as u32, agent, species, params, surroundings, trail_map);
+    }
+}
+
+/// [`update_agents`] spread over the rayon thread pool.
+///
+/// Agents are updated in chunks of [`AGENT_CHUNK_SIZE`], each of which only
+/// reads the trail and records the cells it deposits into. The deposits are
+/// applied once every chunk has finished, so the result matches the
+/// sequential version exactly.
+pub fn update_agents_parallel(
+    agents: &mut [Agent],
+    species: &[SpeciesSettings],
+    params: &ShaderParams,
+    surroundings: Surroundings,
+    trail_map: &mut TrailMap,
+) {
+    let num_agents = (params.numAgents as usize).min(agents.len());
+    let sensed: &TrailMap = trail_map;
+    let deposits: Vec<Vec<usize>> = agents[..num_agents]
+        .par_chunks_mut(AGENT_CHUNK_SIZE)
+        .enumerate()
+        .map(|(chunk_index, chunk)| {
+            let first_id = chunk_index * AGENT_CHUNK_SIZE;
+            chunk
+                .iter_mut()
+                .enumerate()
+                .filter_map(|(i, agent)| {
+                    steer_and_move(
+                        (first_id + i) as u32,
+                        agent,
+                        species,
+                        params,
+                        surroundings,
+                        sensed,
+                    )
+                })
+                .collect()
+        })
+        .collect();
+
+    for cell in deposits.into_iter().flatten() {
+        deposit(cell, params, trail_map);
+    }
+}
+
+/// The `diffuse` dispatch: a 3x3 blur of the deposited channel of each layer,
+/// wrapping around the edges with [`BoundaryMode::Wrap`] and clamped to them
+/// otherwise, blended in by `diffuseRate * delta` and decayed by
+/// `exp(-decayRate * delta * 5)`. Walls are emptied and blur as if they held
+/// the value of the texel being diffused, so no trail leaks into them. Then
+/// `food` is fed in. Both channels of `trail_map` are replaced with the
+/// result, which is what the copy from the pong to the ping texture does on
+/// the GPU.
+pub fn diffuse(
+    params: &ShaderParams,
+    obstacles: &ObstacleMask,
+    food: &FoodMap,
+    trail_map: &mut TrailMap,
+) {
+    let width = trail_map.width as usize;
+    let mut result = vec![0.0; trail_map.deposited.len()];
+    for (row_index, row) in result.chunks_mut(width).enumerate() {
+        diffuse_row(params, obstacles, food, trail_map, row_index, row);
+    }
+
+    trail_map.trail.copy_from_slice(&result);
+    trail_map.deposited = result;
+}
+
+/// [`diffuse`] with rows spread over the rayon thread pool.
+pub fn diffuse_parallel(
+    params: &ShaderParams,
+    obstacles: &ObstacleMask,
+    food: &FoodMap,
+    trail_map: &mut TrailMap,
+) {
+    let width = trail_map.width as usize;
+    let mut result = vec![0.0; trail_map.deposited.len()];
+    let source: &TrailMap = trail_map;
+    result
+        .par_chunks_mut(width)
+        .enumerate()
+        .for_each(|(row_index, row)| diffuse_row(params, obstacles, food, source, row_index, row));
+
+    trail_map.trail.copy_from_slice(&result);
+    trail_map.deposited = result;
+}
+
+/// Diffuses row `row_index` counting across all layers, so layers never blur
+/// into each other.
+fn diffuse_row(
+    params: &ShaderParams,
+    obstacles: &ObstacleMask,
+    food: &FoodMap,
+    trail_map: &TrailMap,
+    row_index: usize,
+    row: &mut [f32],
+) {
+    let width = trail_map.width as i32;
+    let height = trail_map.height as i32;
+    let layer = (row_index / height as usize) as u32;
+    let layer_start = layer as usize * (width * height) as usize;
+    let y = (row_index % height as usize) as i32;
+    let delta = params.delta;
+    let diffuse_weight = (params.diffuseRate * delta).clamp(0.0, 1.0);
+    let decay_factor = (-params.decayRate * delta * 5.0).exp();
+    let wrap = params.boundaryMode == BoundaryMode::Wrap as u32;
+    let food_row = &food.food[(y * width) as usize..((y + 1) * width) as usize];
+    let feeds = food.feeds(layer);
+
+    let source = &trail_map.deposited[layer_start..layer_start + (width * height) as usize];
+    let is_wall = |x: i32, y: i32| obstacles.is_wall(x, y, width as u32, height as u32);
+    for (x, out) in (0..width).zip(row.iter_mut()) {
+        // Nothing is kept inside walls
+        if is_wall(x, y) {
+            *out = 0.0;
+            continue;
+        }
+        let original = source[(y * width + x) as usize];
+        // 3x3 blur
+        let mut sum = 0.0;
+        for offset_x in -1..=1 {
+            for offset_y in -1..=1 {
+                let (sample_x, sample_y) = if wrap {
+                    (
+                        (x + offset_x + width) % width,
+                        (y + offset_y + height) % height,
+                    )
+                } else {
+                    (
+                        (width - 1).min(0.max(x + offset_x)),
+                        (height - 1).min(0.max(y + offset_y)),
+                    )
+                };
+                // Walls reflect the trail back instead of soaking it up
+                sum += if is_wall(sample_x, sample_y) {
+                    original
+                } else {
+                    source[(sample_y * width + sample_x) as usize]
+                };
+            }
+        }
+        let blurred = sum / 9.0;
+        let blurred = original - (original * diffuse_weight) + (blurred * diffuse_weight);
+        let mut fed = blurred * decay_factor;
+        if feeds {
+            fed += food_row[x as usize] * food.params.amount * delta * 10.0;
+        }
+        *out = fed.max(0.0);
+    }
+}
+
+/// The `consume` dispatch, run before [`diffuse`]: takes
+/// `depletion * delta` food from every texel an agent deposited on during
+/// this step, in any layer.
+pub fn consume(params: &ShaderParams, food: &mut FoodMap, trail_map: &TrailMap) {
+    let texels = (trail_map.width * trail_map.height) as usize;
+    let eaten = food.params.depletion * params.delta;
+    for (i, food) in food.food.iter_mut().enumerate() {
+        let visited = (0..trail_map.layers as usize).any(|layer| {
+            let cell = layer * texels + i;
+            trail_map.deposited[cell] > trail_map.trail[cell]
+        });
+        if *food > 0.0 && visited {
+            *food = (*food - eaten).max(0.0);
+        }
+    }
+}
+
+/// The `paint` dispatch: paints or erases the trail under the brush, in both
+/// channels. Does nothing for brushes that move agents.
+pub fn brush_trail(params: &BrushParams, trail_map: &mut TrailMap) {
+    let texels = (trail_map.width * trail_map.height) as usize;
+    for (column, row) in disk_texels(
+        params.x,
+        params.y,
+        params.radius,
+        trail_map.width,
+        trail_map.height,
+    ) {
+        for layer in 0..trail_map.layers {
+            let cell = layer as usize * texels + (row * trail_map.width + column) as usize;
+            let (trail, deposited) = (&mut trail_map.trail[cell], &mut trail_map.deposited[cell]);
+            if params.tool == BrushTool::Erase as u32 {
+                let kept = 1.0 - params.amount.clamp(0.0, 1.0);
+                *trail *= kept;
+                *deposited *= kept;
+            } else if params.tool == BrushTool::Paint as u32
+                && (params.species == ALL_SPECIES || params.species == layer)
+            {
+                *trail = (*trail + params.amount).max(0.0);
+                *deposited = (*deposited + params.amount).max(0.0);
+            }
+        }
+    }
+}
+
+/// The `moveAgents` dispatch: moves `numRelocated` agents from `firstRelocated`
+/// on under the brush, or pulls the agents under it towards its centre unless
+/// that takes them into a wall.
+pub fn brush_agents(params: &BrushParams, obstacles: &ObstacleMask, agents: &mut [Agent]) {
+    let num_agents = params.numAgents.min(agents.len() as u32);
+    for (id, agent) in agents[..num_agents as usize].iter_mut().enumerate() {
+        let id = id as u32;
+        if params.tool == BrushTool::Relocate as u32 {
+            let turn = (id + num_agents - params.firstRelocated) % num_agents;
+            if turn >= params.numRelocated {
+                continue;
+            }
+            let mut state = triple32(id) ^ params.seed;
+            let mut random01 = || {
+                state = triple32(state);
+                scale_to_range01(state)
+            };
+            let r = params.radius * random01().sqrt();
+            let theta = random01() * TWO_PI;
+            agent.posX = clamp_to_map(params.x + theta.cos() * r, params.width);
+            agent.posY = clamp_to_map(params.y + theta.sin() * r, params.height);
+            agent.angle = random01() * TWO_PI;
+        } else if params.tool == BrushTool::Attract as u32 {
+            let (dx, dy) = (params.x - agent.posX, params.y - agent.posY);
+            let distance = (dx * dx + dy * dy).sqrt();
+            if distance <= 0.0 || distance > params.radius {
+                continue;
+            }
+            let pull = distance.min(params.amount * (1.0 - distance / params.radius));
+            let pulled_x = agent.posX + dx / distance * pull;
+            let pulled_y = agent.posY + dy / distance * pull;
+            let is_wall = |x: f32, y: f32| {
+                obstacles.is_wall(
+                    x as i32,
+                    y as i32,
+                    params.width as u32,
+                    params.height as u32,
+                )
+            };
+            if is_wall(pulled_x, pulled_y) && !is_wall(agent.posX, agent.posY) {
+                continue;
+            }
+            agent.posX = pulled_x;
+            agent.posY = pulled_y;
+        }
+    }
+}
+
+/// A CPU counterpart to [`crate::Simulation`] that owns its agents and trail
+/// map as plain vectors and steps them on all cores.
+pub struct CpuSimulation {
+    agents: Vec<Agent>,
+    trail_map: TrailMap,
+    shader_param_data: ShaderParams,
+    species_param_data: Vec<SpeciesSettings>,
+    spawner: Spawner,
+    obstacles: ObstacleMask,
+    food: FoodMap,
+    repellent: RepellentMap,
+    /// The agents [`BrushTool::Relocate`] takes next.
+    relocations: Relocations,
+}
+
+impl CpuSimulation {
+    /// Uses `config` for everything except the population, which is taken
+    /// from `agents` as is, and the RNG seed, which comes from `spawner`.
+    /// Agents killed by [`BoundaryMode::Respawn`] are placed by `spawner` too.
+    /// `food` and `repellent` must cover a map of the configured size.
+    pub fn new(
+        agents: Vec<Agent>,
+        config: &Config,
+        spawner: &Spawner,
+        obstacles: &ObstacleMask,
+        food: &FoodMap,
+        repellent: &RepellentMap,
+    ) -> Self {
+        let width = config.simulation.width;
+        let height = config.simulation.height;
+        Self {
+            shader_param_data: ShaderParams {
+                numAgents: agents.len() as _,
+                seed: spawner.seed(),
+                ..ShaderParams::from_config(config)
+            },
+            agents,
+            trail_map: TrailMap::new(width, height, config.species.len() as u32),
+            species_param_data: config.species.clone(),
+            spawner: spawner.clone(),
+            obstacles: obstacles.clone(),
+            food: food.clone(),
+            repellent: repellent.clone(),
+            relocations: Relocations::default(),
+        }
+    }
+
+    pub fn agents(&self) -> &[Agent] {
+        &self.agents
+    }
+
+    pub fn trail_map(&self) -> &TrailMap {
+        &self.trail_map
+    }
+
+    pub fn species(&self) -> &[SpeciesSettings] {
+        &self.species_param_data
+    }
+
+    pub fn obstacles(&self) -> &ObstacleMask {
+        &self.obstacles
+    }
+
+    /// The food left after the most recent [`CpuSimulation::step`].
+    pub fn food(&self) -> &FoodMap {
+        &self.food
+    }
+
+    pub fn repellent(&self) -> &RepellentMap {
+        &self.repellent
+    }
+
+    /// See [`RepellentMap::paint`].
+    pub fn paint_repellent(&mut self, x: f32, y: f32, radius: f32, amount: f32) {
+        self.repellent.paint(x, y, radius, amount);
+    }
+
+    /// See [`crate::Simulation::brush`].
+    pub fn brush(&mut self, brush: &Brush) {
+        let params = brush.params(&self.shader_param_data, &mut self.relocations);
+        if brush.moves_agents() {
+            brush_agents(&params, &self.obstacles, &mut self.agents);
+        } else {
+            brush_trail(&params, &mut self.trail_map);
+        }
+    }
+
+    /// `species` must have as many entries as the simulation was created with,
+    /// as there is one trail layer per species.
+    pub fn set_species(&mut self, species: &[SpeciesSettings]) {
+        assert_eq!(species.len(), self.species_param_data.len());
+        self.species_param_data.copy_from_slice(species);
+    }
+
+    pub fn diffuse(&self) -> DiffuseSettings {
+        DiffuseSettings {
+            diffuse_rate: self.shader_param_data.diffuseRate,
+            decay_rate: self.shader_param_data.decayRate,
+            deposit_intensity: self.shader_param_data.depositIntensity,
+        }
+    }
+
+    pub fn set_diffuse(&mut self, diffuse: DiffuseSettings) {
+        self.shader_param_data.diffuseRate = diffuse.diffuse_rate;
+        self.shader_param_data.decayRate = diffuse.decay_rate;
+        self.shader_param_data.depositIntensity = diffuse.deposit_intensity;
+    }
+
+    pub fn shader_params(&self) -> &ShaderParams {
+        &self.shader_param_data
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            shader_params: self.shader_param_data,
+            species: self.species_param_data.clone(),
+            agents: self.agents.clone(),
+            trail_map: self.trail_map.clone(),
+            food: self.food.food.clone(),
+            repellent: self.repellent.values.clone(),
+        }
+    }
+
+    /// See [`crate::Simulation::restore`].
+    pub fn restore(&mut self, checkpoint: &Checkpoint) {
+        assert_eq!(checkpoint.agents.len(), self.agents.len());
+        assert_eq!(
+            (
+                checkpoint.trail_map.width,
+                checkpoint.trail_map.height,
+                checkpoint.trail_map.layers
+            ),
+            (
+                self.trail_map.width,
+                self.trail_map.height,
+                self.trail_map.layers
+            )
+        );
+        assert_eq!(checkpoint.food.len(), self.food.food.len());
+        assert_eq!(checkpoint.repellent.len(), self.repellent.values.len());
+        self.agents.clone_from(&checkpoint.agents);
+        self.trail_map.clone_from(&checkpoint.trail_map);
+        self.food.food.clone_from(&checkpoint.food);
+        self.repellent.values.clone_from(&checkpoint.repellent);
+        self.set_species(&checkpoint.species);
+        self.shader_param_data = checkpoint.shader_params;
+    }
+
+    /// Same order of operations as [`crate::Simulation::step`].
+    pub fn step(&mut self, dt: f32) {
+        self.shader_param_data.delta = dt;
+
+        update_agents_parallel(
+            &mut self.agents,
+            &self.species_param_data,
+            &self.shader_param_data,
+            Surroundings {
+                spawner: &self.spawner,
+                obstacles: &self.obstacles,
+                repellent: &self.repellent,
+            },
+            &mut self.trail_map,
+        );
+        if self.food.params.depletion > 0.0 {
+            consume(&self.shader_param_data, &mut self.food, &self.trail_map);
+        }
+        diffuse_parallel(
+            &self.shader_param_data,
+            &self.obstacles,
+            &self.food,
+            &mut self.trail_map,
+        );
+        self.shader_param_data.frame = self.shader_param_data.frame.wrapping_add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two species on a 4x4 map, with `delta = 0.5` blurring fully and
+    /// depositing 10 per step.
+    fn config() -> Config {
+        let mut config = Config::default();
+        config.simulation.width = 4;
+        config.simulation.height = 4;
+        config.simulation.seed = Some(1);
+        config.diffuse.diffuse_rate = 2.0;
+        config.diffuse.decay_rate = 0.0;
+        config.diffuse.deposit_intensity = 2.0;
+        config.species = vec![
+            SpeciesSettings {
+                sensorOffsetDst: 1.0,
+                sensorSize: 0.0,
+                ..SpeciesSettings::default()
+            };
+            2
+        ];
+        config
+    }
+
+    fn params(config: &Config) -> ShaderParams {
+        ShaderParams {
+            delta: 0.5,
+            ..ShaderParams::from_config(config)
+        }
+    }
+
+    fn cell(x: usize, y: usize, layer: usize) -> usize {
+        layer * 16 + y * 4 + x
+    }
+
+    #[test]
+    fn sense_weighs_own_and_other_species() {
+        let config = config();
+        let mut repellent = RepellentMap::new(&config).unwrap();
+        let mut trail_map = TrailMap::new(4, 4, 2);
+        // The sensor one texel ahead of the agent lands on (2, 1)
+        trail_map.trail[cell(2, 1, 0)] = 3.0;
+        trail_map.trail[cell(2, 1, 1)] = 1.0;
+        trail_map.trail[cell(0, 1, 0)] = 5.0;
+        let agent = Agent {
+            posX: 1.5,
+            posY: 1.5,
+            angle: 0.0,
+            speciesIndex: 0,
+        };
+        let settings = &config.species[0];
+        let boundary = BoundaryMode::Clamp;
+        assert_eq!(
+            sense(&agent, settings, 0.0, boundary, &repellent, &trail_map),
+            2.0
+        );
+        let other = Agent {
+            speciesIndex: 1,
+            ..agent
+        };
+        assert_eq!(
+            sense(&other, settings, 0.0, boundary, &repellent, &trail_map),
+            -2.0
+        );
+
+        repellent.paint(2.5, 1.5, 0.0, 0.5);
+        assert_eq!(
+            sense(&agent, settings, 0.0, boundary, &repellent, &trail_map),
+            1.5
+        );
+
+        // A 3x3 sensor around (2, 1) doesn't reach (0, 1)
+        let wide = SpeciesSettings {
+            sensorSize: 1.0,
+            ..*settings
+        };
+        trail_map.trail[cell(3, 2, 0)] = 0.25;
+        assert_eq!(
+            sense(&agent, &wide, 0.0, boundary, &repellent, &trail_map),
+            1.75
+        );
+    }
+
+    #[test]
+    fn deposit_replaces_rather_than_accumulates() {
+        let config = config();
+        let params = params(&config);
+        let mut trail_map = TrailMap::new(4, 4, 2);
+        trail_map.trail[5] = 0.25;
+        deposit(5, &params, &mut trail_map);
+        assert_eq!(trail_map.deposited[5], 10.25);
+        deposit(5, &params, &mut trail_map);
+        assert_eq!(trail_map.deposited[5], 10.25);
+        assert_eq!(trail_map.trail[5], 0.25);
+    }
+
+    #[test]
+    fn diffuse_spreads_a_point_over_its_neighbours() {
+        let config = config();
+        let food = FoodMap::new(&config).unwrap();
+        let mut trail_map = TrailMap::new(4, 4, 2);
+        trail_map.deposited[cell(1, 1, 0)] = 9.0;
+        diffuse(
+            &params(&config),
+            &ObstacleMask::open(),
+            &food,
+            &mut trail_map,
+        );
+        for y in 0..4 {
+            for x in 0..4 {
+                let expected = if x <= 2 && y <= 2 { 1.0 } else { 0.0 };
+                assert_eq!(trail_map.trail[cell(x, y, 0)], expected, "({}, {})", x, y);
+            }
+        }
+        // Layers don't blur into each other
+        assert!(trail_map.trail[16..].iter().all(|&trail| trail == 0.0));
+        assert_eq!(trail_map.trail, trail_map.deposited);
+    }
+
+    #[test]
+    fn diffuse_only_decays_a_uniform_map() {
+        let mut config = config();
+        config.diffuse.decay_rate = 0.2;
+        let food = FoodMap::new(&config).unwrap();
+        for boundary in [BoundaryMode::Clamp, BoundaryMode::Wrap] {
+            config.simulation.boundary = boundary;
+            let mut trail_map = TrailMap::new(4, 4, 2);
+            trail_map.deposited.fill(2.0);
+            diffuse(
+                &params(&config),
+                &ObstacleMask::open(),
+                &food,
+                &mut trail_map,
+            );
+            let expected = 2.0 * (-0.2f32 * 0.5 * 5.0).exp();
+            for &trail in &trail_map.trail {
+                assert!((trail - expected).abs() < 1e-6, "{:?}", boundary);
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Short animated loops for sharing: GIF, or APNG for full colour. Animated
+//! WebP is not supported, as the `image` crate only encodes still WebP images.
+//!
+//! Frames come from the headless colour pass, are downsampled to a small
+//! width and can be crossfaded so the last frame flows back into the first.
+
+use std::{fs::File, io::BufWriter, path::Path, time::Duration};
+
+use image::{
+    codecs::gif::{GifEncoder, Repeat},
+    Delay, Frame, ImageError, Rgba, RgbaImage,
+};
+
+/// NeuQuant sampling speed for the GIF palette, 1 (best) to 30 (fastest).
+const GIF_QUANTISE_SPEED: i32 = 10;
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(std::io::Error),
+    Encode(String),
+    /// The extension of a path [`save_animation`] can't write, e.g. `webp`.
+    UnsupportedFormat(String),
+}
+
+impl std::fmt::Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::Io(e) => write!(f, "{}", e),
+            ExportError::Encode(e) => write!(f, "could not encode animation: {}", e),
+            ExportError::UnsupportedFormat(extension) => write!(
+                f,
+                "cannot export .{} animations, expected .gif, .png or .apng",
+                extension
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<std::io::Error> for ExportError {
+    fn from(e: std::io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+impl From<ImageError> for ExportError {
+    fn from(e: ImageError) -> Self {
+        ExportError::Encode(e.to_string())
+    }
+}
+
+impl From<png::EncodingError> for ExportError {
+    fn from(e: png::EncodingError) -> Self {
+        ExportError::Encode(e.to_string())
+    }
+}
+
+/// Scales `frame` down to `width` pixels wide, keeping its aspect ratio, by
+/// averaging the block of pixels under each new one. Frames already narrower
+/// are left alone.
+pub fn downsample(frame: &RgbaImage, width: u32) -> RgbaImage {
+    if frame.width() <= width {
+        return frame.clone();
+    }
+    let height = (frame.height() as u64 * width as u64 / frame.width() as u64).max(1) as u32;
+    // The pixels of a `from`-long row or column under the `i`th of `to`
+    let block = |i: u32, to: u32, from: u32| {
+        let start = i as u64 * from as u64 / to as u64;
+        let end = (i as u64 + 1) * from as u64 / to as u64;
+        start as u32..end as u32
+    };
+    RgbaImage::from_fn(width, height, |x, y| {
+        let mut sum = [0u64; 4];
+        let mut count = 0;
+        for source_y in block(y, height, frame.height()) {
+            for source_x in block(x, width, frame.width()) {
+                let pixel = frame.get_pixel(source_x, source_y);
+                for (sum, channel) in sum.iter_mut().zip(pixel.0) {
+                    *sum += channel as u64;
+                }
+                count += 1;
+            }
+        }
+        Rgba(sum.map(|sum| ((sum + count / 2) / count) as u8))
+    })
+}
+
+/// Turns `frames` into a seamless loop `fade` frames shorter: the first
+/// `fade` frames are dropped and instead blended into the last `fade`, so the
+/// last frame leads straight back into the (new) first one.
+///
+/// `frames` must hold at least `2 * fade` frames.
+pub fn crossfade_loop(mut frames: Vec<RgbaImage>, fade: usize) -> Vec<RgbaImage> {
+    assert!(frames.len() >= 2 * fade, "too few frames to crossfade");
+    let intro: Vec<RgbaImage> = frames.drain(..fade).collect();
+    let tail_start = frames.len() - fade;
+    for (i, (frame, target)) in frames[tail_start..].iter_mut().zip(&intro).enumerate() {
+        let t = (i + 1) as f32 / (fade + 1) as f32;
+        for (pixel, target) in frame.pixels_mut().zip(target.pixels()) {
+            for (channel, target) in pixel.0.iter_mut().zip(target.0) {
+                *channel = (*channel as f32 * (1.0 - t) + target as f32 * t).round() as u8;
+            }
+        }
+    }
+    frames
+}
+
+/// Writes `frames` as an endlessly looping animation with `delay` between
+/// frames: a palette-quantised GIF for `.gif` or a full-colour APNG for
+/// `.png` and `.apng`.
+pub fn save_animation(
+    path: &Path,
+    frames: &[RgbaImage],
+    delay: Duration,
+) -> Result<(), ExportError> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    if !matches!(extension.as_str(), "gif" | "png" | "apng") {
+        return Err(ExportError::UnsupportedFormat(extension));
+    }
+    let file = BufWriter::new(File::create(path)?);
+    match extension.as_str() {
+        "gif" => {
+            let mut encoder = GifEncoder::new_with_speed(file, GIF_QUANTISE_SPEED);
+            encoder.set_repeat(Repeat::Infinite)?;
+            let delay = Delay::from_saturating_duration(delay);
+            encoder.encode_frames(
+                frames
+                    .iter()
+                    .map(|frame| Frame::from_parts(frame.clone(), 0, 0, delay)),
+            )?;
+        }
+        _ => {
+            let (width, height) = frames.first().map_or((1, 1), |frame| frame.dimensions());
+            let mut encoder = png::Encoder::new(file, width, height);
+            encoder.set_color(png::ColorType::Rgba);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder.set_animated(frames.len() as u32, 0)?;
+            let delay_ms = delay.as_millis().min(u16::MAX as u128) as u16;
+            encoder.set_frame_delay(delay_ms, 1000)?;
+            let mut writer = encoder.write_header()?;
+            for frame in frames {
+                writer.write_image_data(frame.as_raw())?;
+            }
+            writer.finish()?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,152 @@
+//! Food sources that feed chemoattractant into the trail every step, for
+//! transport-network experiments like linking cities on a map.
+//!
+//! The food left on each texel is kept in a map the size of the trail map.
+//! The `diffuse` pass adds `food * amount` to the trail there, at the same
+//! scale agents deposit at. With `depletion` set, the `consume` pass first
+//! takes food away from every texel an agent deposited on during the step,
+//! so sources that are fed on dry up.
+
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{spawn::LuminanceMap, Config, ConfigError, FoodParams};
+
+/// [`FoodParams::species`] for food that feeds every trail layer.
+pub const ALL_SPECIES: u32 = u32::MAX;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct FoodSettings {
+    /// Image whose luminance is the food on each texel, stretched over the
+    /// whole map.
+    pub image: Option<PathBuf>,
+    /// Round blobs of food over `image`; where they overlap, the most food
+    /// wins.
+    pub sources: Vec<FoodSource>,
+    /// Trail deposited per step by a texel holding 1 food, before scaling by
+    /// `delta` like `depositIntensity`.
+    pub amount: f32,
+    /// Food taken from a texel per second while agents are on it; 0 never
+    /// runs out.
+    pub depletion: f32,
+    /// Only feed this species' trail layer. Every layer is fed if unset, in
+    /// which case species that are repelled by each other's trails cancel
+    /// each other's food out.
+    pub species: Option<u32>,
+}
+
+impl Default for FoodSettings {
+    fn default() -> Self {
+        Self {
+            image: None,
+            sources: Vec::new(),
+            amount: 1.0,
+            depletion: 0.0,
+            species: None,
+        }
+    }
+}
+
+/// A disk of food centred on (`x`, `y`) in trail map texels.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct FoodSource {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+    /// Food on each texel of the disk.
+    pub strength: f32,
+}
+
+impl Default for FoodSource {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            radius: 8.0,
+            strength: 1.0,
+        }
+    }
+}
+
+/// The food left on each texel of the trail map, row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FoodMap {
+    pub width: u32,
+    pub height: u32,
+    pub food: Vec<f32>,
+    pub params: FoodParams,
+}
+
+impl FoodMap {
+    /// Lays out the `[food]` section of `config` over a trail map of the
+    /// configured size, loading the image if there is one.
+    pub fn new(config: &Config) -> Result<Self, ConfigError> {
+        let settings = &config.food;
+        let width = config.simulation.width;
+        let height = config.simulation.height;
+        let mut food = vec![0.0; (width * height) as usize];
+        if let Some(path) = &settings.image {
+            let image = LuminanceMap::load(path)?;
+            for (i, food) in food.iter_mut().enumerate() {
+                let x = i as u32 % width * image.width / width;
+                let y = i as u32 / width * image.height / height;
+                *food = image.values[(y * image.width + x) as usize];
+            }
+        }
+        for source in &settings.sources {
+            for (x, y) in disk_texels(source.x, source.y, source.radius, width, height) {
+                let food = &mut food[(y * width + x) as usize];
+                *food = food.max(source.strength);
+            }
+        }
+        Ok(Self {
+            width,
+            height,
+            food,
+            params: FoodParams {
+                amount: settings.amount,
+                depletion: settings.depletion,
+                species: settings.species.unwrap_or(ALL_SPECIES),
+            },
+        })
+    }
+
+    /// Total food left on the map.
+    pub fn total(&self) -> f32 {
+        self.food.iter().sum()
+    }
+
+    /// Whether food on the map is fed into trail layer `layer`.
+    pub fn feeds(&self, layer: u32) -> bool {
+        self.params.species == ALL_SPECIES || self.params.species == layer
+    }
+}
+
+/// The texels of a `width x height` map whose centres lie within `radius` of
+/// (`x`, `y`).
+pub(crate) fn disk_texels(
+    x: f32,
+    y: f32,
+    radius: f32,
+    width: u32,
+    height: u32,
+) -> impl Iterator<Item = (u32, u32)> {
+    let columns = texel_range(x, radius, width);
+    texel_range(y, radius, height)
+        .flat_map(move |row| columns.clone().map(move |column| (column, row)))
+        .filter(move |&(column, row)| {
+            let dx = column as f32 + 0.5 - x;
+            let dy = row as f32 + 0.5 - y;
+            dx * dx + dy * dy <= radius * radius
+        })
+}
+
+/// Texels of a `size` long row or column that `centre ± radius` touches.
+fn texel_range(centre: f32, radius: f32, size: u32) -> std::ops::Range<u32> {
+    let start = (centre - radius).floor().clamp(0.0, size as f32) as u32;
+    let end = (centre + radius).ceil().clamp(0.0, size as f32) as u32;
+    start..end
+}
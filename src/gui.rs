@@ -0,0 +1,330 @@
+//! The egui parameter overlay of the windowed viewer, drawn over the scaled
+//! trail map.
+
+use std::{collections::VecDeque, ops::RangeInclusive, path::Path};
+
+use slime_webgpu::{DiffuseSettings, Preset, SpeciesSettings};
+use winit::{event::WindowEvent, window::Window};
+
+/// Frame times kept for the FPS graph.
+const FRAME_HISTORY: usize = 240;
+/// Frame time at the top of the graph, in seconds.
+const GRAPH_CEILING: f32 = 1.0 / 20.0;
+
+/// What the panel edits. The viewer copies these out of the simulation before
+/// drawing and writes back whatever changed.
+#[derive(Clone, PartialEq)]
+pub struct Controls {
+    pub species: Vec<SpeciesSettings>,
+    pub diffuse: DiffuseSettings,
+    pub paused: bool,
+}
+
+pub struct Gui {
+    context: egui::Context,
+    state: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+    visible: bool,
+    frame_times: VecDeque<f32>,
+    preset_path: String,
+    /// Outcome of the last preset load or save.
+    message: Option<String>,
+}
+
+impl Gui {
+    /// Starts hidden, see [`Gui::toggle`].
+    pub fn new(
+        window: &Window,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+        preset_path: &Path,
+    ) -> Self {
+        let context = egui::Context::default();
+        let state = egui_winit::State::new(
+            context.clone(),
+            egui::ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            None,
+            Some(device.limits().max_texture_dimension_2d as usize),
+        );
+        let renderer = egui_wgpu::Renderer::new(device, format, Default::default());
+        Self {
+            context,
+            state,
+            renderer,
+            visible: false,
+            frame_times: VecDeque::with_capacity(FRAME_HISTORY),
+            preset_path: preset_path.display().to_string(),
+            message: None,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    /// Adds a frame that took `seconds` to the FPS graph.
+    pub fn record_frame(&mut self, seconds: f32) {
+        if self.frame_times.len() == FRAME_HISTORY {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(seconds);
+    }
+
+    /// Passes `event` on to egui while the overlay is shown. Returns whether
+    /// egui used it and the viewer should leave it alone; releases are never
+    /// held back, so brushes and keys can't get stuck down.
+    pub fn on_window_event(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        if !self.visible {
+            return false;
+        }
+        let consumed = self.state.on_window_event(window, event).consumed;
+        consumed
+            && match event {
+                WindowEvent::KeyboardInput { event, .. } => event.state.is_pressed(),
+                WindowEvent::MouseInput { state, .. } => state.is_pressed(),
+                WindowEvent::MouseWheel { .. } => true,
+                _ => false,
+            }
+    }
+
+    /// Lays out the panel, letting it edit `controls`, and draws it on top of
+    /// `view`. Does nothing while the overlay is hidden.
+    pub fn draw(
+        &mut self,
+        window: &Window,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        controls: &mut Controls,
+    ) {
+        if !self.visible {
+            return;
+        }
+        let input = self.state.take_egui_input(window);
+        let context = self.context.clone();
+        let output = context.run(input, |context| self.panel(context, controls));
+        self.state
+            .handle_platform_output(window, output.platform_output);
+
+        let primitives = context.tessellate(output.shapes, output.pixels_per_point);
+        let size = window.inner_size();
+        let screen = egui_wgpu::ScreenDescriptor {
+            size_in_pixels: [size.width, size.height],
+            pixels_per_point: output.pixels_per_point,
+        };
+        for (id, delta) in &output.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        let commands = self
+            .renderer
+            .update_buffers(device, queue, encoder, &primitives, &screen);
+        queue.submit(commands);
+        {
+            let gui_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("gui_pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                    depth_slice: None,
+                })],
+                depth_stencil_attachment: None,
+                ..Default::default()
+            });
+            self.renderer
+                .render(&mut gui_pass.forget_lifetime(), &primitives, &screen);
+        }
+        for id in &output.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+    }
+
+    fn panel(&mut self, context: &egui::Context, controls: &mut Controls) {
+        egui::Window::new("Slime")
+            .default_pos([16.0, 16.0])
+            .show(context, |ui| {
+                self.fps_graph(ui);
+                ui.checkbox(&mut controls.paused, "Paused");
+
+                ui.collapsing("Diffusion", |ui| diffuse_sliders(ui, &mut controls.diffuse));
+                for (i, species) in controls.species.iter_mut().enumerate() {
+                    ui.collapsing(format!("Species {}", i), |ui| species_sliders(ui, species));
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("Preset");
+                    ui.text_edit_singleline(&mut self.preset_path);
+                });
+                ui.horizontal(|ui| {
+                    if ui.button("Load").clicked() {
+                        self.load_preset(controls);
+                    }
+                    if ui.button("Save").clicked() {
+                        self.save_preset(controls);
+                    }
+                });
+                if let Some(message) = &self.message {
+                    ui.label(message);
+                }
+            });
+    }
+
+    fn fps_graph(&self, ui: &mut egui::Ui) {
+        let average = self.frame_times.iter().sum::<f32>() / self.frame_times.len().max(1) as f32;
+        if average > 0.0 {
+            ui.label(format!(
+                "{:.0} fps ({:.1} ms)",
+                1.0 / average,
+                average * 1000.0
+            ));
+        }
+        let (rect, _) = ui.allocate_exact_size(
+            egui::vec2(ui.available_width().max(FRAME_HISTORY as f32), 48.0),
+            egui::Sense::hover(),
+        );
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+        let step = rect.width() / (FRAME_HISTORY - 1) as f32;
+        let points = self
+            .frame_times
+            .iter()
+            .enumerate()
+            .map(|(i, &seconds)| {
+                let height = (seconds / GRAPH_CEILING).min(1.0) * rect.height();
+                egui::pos2(rect.left() + i as f32 * step, rect.bottom() - height)
+            })
+            .collect();
+        painter.add(egui::Shape::line(
+            points,
+            egui::Stroke::new(1.0, ui.visuals().text_color()),
+        ));
+    }
+
+    /// Keeps the number of species, see [`Preset::species_for`].
+    fn load_preset(&mut self, controls: &mut Controls) {
+        let path = Path::new(&self.preset_path);
+        self.message = Some(match Preset::load(path) {
+            Ok(preset) => {
+                controls.species = preset.species_for(controls.species.len());
+                controls.diffuse = preset.diffuse;
+                format!("loaded {}", path.display())
+            }
+            Err(e) => format!("error: {}: {}", path.display(), e),
+        });
+    }
+
+    fn save_preset(&mut self, controls: &Controls) {
+        let path = Path::new(&self.preset_path);
+        let preset = Preset {
+            species: controls.species.clone(),
+            diffuse: controls.diffuse,
+        };
+        self.message = Some(match preset.save(path) {
+            Ok(()) => format!("saved {}", path.display()),
+            Err(e) => format!("error: {}: {}", path.display(), e),
+        });
+    }
+}
+
+/// A slider over the usual range of a setting. Config files and keys may take
+/// it further, so only dragging the slider is clamped; drawing it leaves
+/// values outside the range alone.
+fn slider<'a>(value: &'a mut f32, range: RangeInclusive<f32>, text: &str) -> egui::Slider<'a> {
+    egui::Slider::new(value, range)
+        .clamping(egui::SliderClamping::Edits)
+        .text(text)
+}
+
+fn diffuse_sliders(ui: &mut egui::Ui, diffuse: &mut DiffuseSettings) {
+    ui.add(slider(
+        &mut diffuse.diffuse_rate,
+        0.0..=60.0,
+        "diffuse rate",
+    ));
+    ui.add(slider(&mut diffuse.decay_rate, 0.0..=5.0, "decay rate"));
+    ui.add(
+        slider(
+            &mut diffuse.deposit_intensity,
+            0.0..=0.1,
+            "deposit intensity",
+        )
+        .logarithmic(true),
+    );
+}
+
+fn species_sliders(ui: &mut egui::Ui, species: &mut SpeciesSettings) {
+    ui.add(slider(&mut species.moveSpeed, 0.0..=500.0, "move speed"));
+    ui.add(slider(&mut species.turnSpeed, -50.0..=50.0, "turn speed"));
+    ui.add(slider(
+        &mut species.sensorAngleDegrees,
+        0.0..=180.0,
+        "sensor angle",
+    ));
+    ui.add(slider(
+        &mut species.sensorOffsetDst,
+        0.0..=200.0,
+        "sensor offset",
+    ));
+    // Each step senses (2 * size + 1)^2 texels three times
+    ui.add(slider(&mut species.sensorSize, 0.0..=4.0, "sensor size").step_by(1.0));
+    ui.add(slider(
+        &mut species.repellentSensitivity,
+        -10.0..=10.0,
+        "repellent sensitivity",
+    ));
+    let mut colour = [
+        species.colourR,
+        species.colourG,
+        species.colourB,
+        species.colourA,
+    ];
+    ui.horizontal(|ui| {
+        ui.color_edit_button_rgba_unmultiplied(&mut colour);
+        ui.label("colour");
+    });
+    [
+        species.colourR,
+        species.colourG,
+        species.colourB,
+        species.colourA,
+    ] = colour;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn out_of_range_settings_survive_a_frame() {
+        let mut species = SpeciesSettings {
+            moveSpeed: 900.0,
+            turnSpeed: -80.0,
+            sensorSize: 8.0,
+            repellentSensitivity: 25.0,
+            ..SpeciesSettings::default()
+        };
+        let mut diffuse = DiffuseSettings {
+            diffuse_rate: 100.0,
+            decay_rate: 9.0,
+            deposit_intensity: 0.5,
+        };
+        let (species_before, diffuse_before) = (species, diffuse);
+
+        let context = egui::Context::default();
+        let _ = context.run(egui::RawInput::default(), |context| {
+            egui::CentralPanel::default().show(context, |ui| {
+                species_sliders(ui, &mut species);
+                diffuse_sliders(ui, &mut diffuse);
+            });
+        });
+        assert_eq!(species, species_before);
+        assert_eq!(diffuse, diffuse_before);
+    }
+}
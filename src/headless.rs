@@ -0,0 +1,171 @@
+use crate::{
+    render::{self, TrailRenderer},
+    simulation::{map_read, padded_bytes_per_row},
+    Simulation,
+};
+
+/// Format of the offscreen colour target, chosen to match the sRGB swapchain
+/// formats the windowed viewer usually ends up with.
+pub const FRAME_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+/// Requests a device without a surface, so it works on machines with no
+/// display. `WGPU_ADAPTER_NAME` selects a specific adapter (e.g. `llvmpipe`
+/// for lavapipe); otherwise the default adapter is used, falling back to a
+/// software adapter if there is no hardware one.
+pub async fn request_device() -> (wgpu::Device, wgpu::Queue) {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::VULKAN,
+        ..Default::default()
+    });
+    let adapter = match wgpu::util::initialize_adapter_from_env_or_default(&instance, None).await {
+        Ok(adapter) => adapter,
+        Err(_) => instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                compatible_surface: None,
+                force_fallback_adapter: true,
+            })
+            .await
+            .expect("no Vulkan adapter found (is lavapipe installed?)"),
+    };
+    log::info!("headless adapter: {:?}", adapter.get_info());
+    adapter
+        .request_device(&Simulation::device_descriptor(&adapter))
+        .await
+        .unwrap()
+}
+
+/// An offscreen colour target that is read back to the CPU after each draw.
+pub struct OffscreenTarget {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    readback_buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+}
+
+impl OffscreenTarget {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Headless Frame Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FRAME_FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[FRAME_FORMAT],
+        });
+        let view = texture.create_view(&Default::default());
+
+        let padded_bytes_per_row = padded_bytes_per_row(width * 4);
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Headless Readback Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            texture,
+            view,
+            readback_buffer,
+            width,
+            height,
+            padded_bytes_per_row,
+        }
+    }
+
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn view(&self) -> &wgpu::TextureView {
+        &self.view
+    }
+
+    /// Records `draw` into the target and blocks until it has been read back.
+    pub fn capture(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        draw: impl FnOnce(&mut wgpu::CommandEncoder, &wgpu::TextureView),
+    ) -> std::io::Result<image::RgbaImage> {
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Headless Encoder"),
+        });
+        draw(&mut encoder, &self.view);
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &self.readback_buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let buffer_slice = self.readback_buffer.slice(..);
+        map_read(device, buffer_slice)?;
+
+        let mut pixels = Vec::with_capacity((self.width * self.height * 4) as usize);
+        {
+            let mapped = buffer_slice.get_mapped_range();
+            for row in mapped.chunks(self.padded_bytes_per_row as usize) {
+                pixels.extend_from_slice(&row[..(self.width * 4) as usize]);
+            }
+        }
+        self.readback_buffer.unmap();
+
+        Ok(image::RgbaImage::from_raw(self.width, self.height, pixels).unwrap())
+    }
+}
+
+/// Runs the colour pass into an [`OffscreenTarget`] of
+/// [`render::render_size`].
+pub struct FrameCapture {
+    trail_renderer: TrailRenderer,
+    target: OffscreenTarget,
+}
+
+impl FrameCapture {
+    pub fn new(simulation: &Simulation, scale: f32) -> Self {
+        let (width, height) = render::render_size(simulation.width(), simulation.height(), scale);
+        Self {
+            trail_renderer: TrailRenderer::new(simulation, FRAME_FORMAT, scale),
+            target: OffscreenTarget::new(simulation.device(), width, height),
+        }
+    }
+
+    /// The target [`FrameCapture::render`] draws into.
+    pub fn texture(&self) -> &wgpu::Texture {
+        self.target.texture()
+    }
+
+    /// Encodes the colour pass without reading it back.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        self.trail_renderer
+            .draw(encoder, self.target.view(), wgpu::Color::BLACK);
+    }
+
+    /// Renders the current trail map and blocks until it has been read back.
+    pub fn capture(&self, simulation: &Simulation) -> std::io::Result<image::RgbaImage> {
+        self.target
+            .capture(simulation.device(), simulation.queue(), |encoder, view| {
+                self.trail_renderer.draw(encoder, view, wgpu::Color::BLACK)
+            })
+    }
+}
@@ -0,0 +1,132 @@
+//! Reloading WGSL from disk while the viewer runs, for `--shader-dir`.
+//!
+//! [`ShaderWatcher`] notices edited files, [`check_wgsl`] runs them through
+//! naga so mistakes are reported with the offending line instead of taking
+//! the device down, and [`catch_validation`] guards the pipeline rebuild that
+//! follows. Callers keep their previous pipeline whenever any step fails.
+
+use std::{
+    collections::HashMap,
+    fmt, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// The shaders `--shader-dir` picks up, by file name.
+pub const WATCHED_SHADERS: [&str; 5] = [
+    "slime.wgsl",
+    "place.wgsl",
+    "diffuse.wgsl",
+    "shader.wgsl",
+    "scaling.wgsl",
+];
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io(io::Error),
+    /// naga's report, with the source line it points at.
+    Invalid(String),
+    /// wgpu rejected the pipeline, e.g. because the bindings no longer match
+    /// the layout.
+    Pipeline(String),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Io(e) => write!(f, "could not read shader: {}", e),
+            ShaderError::Invalid(report) => write!(f, "{}", report.trim_end()),
+            ShaderError::Pipeline(e) => write!(f, "could not rebuild pipeline: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+impl From<io::Error> for ShaderError {
+    fn from(e: io::Error) -> Self {
+        ShaderError::Io(e)
+    }
+}
+
+/// Parses and validates `source` with naga. `path` is only used to label the
+/// report.
+pub fn check_wgsl(path: &Path, source: &str) -> Result<(), ShaderError> {
+    let path = path.display().to_string();
+    let module = naga::front::wgsl::parse_str(source)
+        .map_err(|e| ShaderError::Invalid(e.emit_to_string_with_path(source, &path)))?;
+    naga::valid::Validator::new(
+        naga::valid::ValidationFlags::all(),
+        naga::valid::Capabilities::all(),
+    )
+    .validate(&module)
+    .map_err(|e| ShaderError::Invalid(e.emit_to_string_with_path(source, &path)))?;
+    Ok(())
+}
+
+/// Runs `create` and turns any validation error wgpu raises meanwhile into
+/// an `Err`, instead of the uncaptured error handler's panic.
+pub fn catch_validation<T>(
+    device: &wgpu::Device,
+    create: impl FnOnce() -> T,
+) -> Result<T, ShaderError> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let created = create();
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(e) => Err(ShaderError::Pipeline(e.to_string())),
+        None => Ok(created),
+    }
+}
+
+/// Creates a shader module from `source`, which has passed [`check_wgsl`].
+pub fn create_shader_module(
+    device: &wgpu::Device,
+    label: &str,
+    source: &str,
+) -> Result<wgpu::ShaderModule, ShaderError> {
+    catch_validation(device, || {
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        })
+    })
+}
+
+/// Notices changes to the [`WATCHED_SHADERS`] in one directory by their
+/// modification times.
+pub struct ShaderWatcher {
+    dir: PathBuf,
+    modified: HashMap<&'static str, SystemTime>,
+}
+
+impl ShaderWatcher {
+    /// Every shader already in `dir` counts as changed on the first
+    /// [`ShaderWatcher::poll`], so the files on disk replace the built-in
+    /// ones straight away.
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_owned(),
+            modified: HashMap::new(),
+        }
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    /// The watched shaders written to since the last call, with their
+    /// contents. Missing files are skipped until they appear.
+    pub fn poll(&mut self) -> Vec<(&'static str, io::Result<String>)> {
+        let mut changed = Vec::new();
+        for name in WATCHED_SHADERS {
+            let path = self.dir.join(name);
+            let Ok(modified) = path.metadata().and_then(|metadata| metadata.modified()) else {
+                continue;
+            };
+            if self.modified.insert(name, modified) != Some(modified) {
+                changed.push((name, std::fs::read_to_string(path)));
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,48 @@
+//! Physarum-style slime mould simulation on wgpu compute shaders.
+//!
+//! [`Simulation`] owns the device, the agent buffer and the trail textures and
+//! has no windowing dependency; the `slime-webgpu` binary is a thin winit viewer
+//! on top of it.
+
+pub mod bindings;
+pub mod brush;
+pub mod checkpoint;
+pub mod config;
+pub mod cpu;
+pub mod export;
+pub mod food;
+pub mod headless;
+pub mod hot_reload;
+pub mod obstacles;
+pub mod params;
+pub mod preset;
+pub mod record;
+pub mod render;
+pub mod repellent;
+pub mod screenshot;
+pub mod simulation;
+pub mod spawn;
+pub mod timestep;
+
+pub use bindings::{Action, Bindings, Parameter, StepSizes};
+pub use brush::{Brush, BrushTool, Relocations};
+pub use checkpoint::{Checkpoint, CheckpointError};
+pub use config::{BoundaryMode, Config, ConfigError, DiffuseSettings, SimulationConfig};
+pub use food::{FoodMap, FoodSettings, FoodSource};
+pub use obstacles::{ObstacleMask, ObstacleSettings};
+pub use params::{
+    Agent, AgentChunk, BrushParams, FoodParams, RenderParams, ShaderParams, SpawnParams,
+    SpeciesSettings,
+};
+pub use preset::Preset;
+pub use repellent::{RepellentCircle, RepellentMap, RepellentSettings};
+pub use simulation::Simulation;
+pub use spawn::{SpawnMode, SpawnSettings, Spawner};
+pub use timestep::FixedTimestep;
+
+/// Must match `@workgroup_size` in `slime.wgsl` and `spawn.wgsl`.
+pub const AGENTS_PER_GROUP: u32 = 128;
+/// Must match `@workgroup_size` in `diffuse.wgsl` and of `paint` in `brush.wgsl`.
+pub const DIFFUSE_TILE_SIZE: u32 = 16;
+/// Must match the size of the colour array in `present.wgsl`.
+pub const MAX_SPECIES: usize = 8;
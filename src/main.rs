@@ -1,8 +1,28 @@
-use std::{sync::Arc, time::Instant};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use clap::{arg, command, Parser};
-use rand::Rng;
-use wgpu::{util::DeviceExt, BindGroup, BufferAddress, BufferDescriptor, BufferUsages, Device};
+use cgmath::SquareMatrix;
+use clap::Parser;
+use gui::{Controls, Gui};
+use serde::{de::IntoDeserializer, Deserialize};
+use slime_webgpu::{
+    cpu::CpuSimulation,
+    export,
+    food::ALL_SPECIES,
+    headless::{self, FrameCapture, OffscreenTarget},
+    hot_reload::{catch_validation, check_wgsl, create_shader_module, ShaderError, ShaderWatcher},
+    preset::{self, Transition},
+    record::{RecordTarget, Recorder},
+    render::{CpuTrailRenderer, TrailRenderer, Vertex, VERTICES},
+    screenshot::{self, ScreenshotError, ScreenshotInfo},
+    Action, Bindings, BoundaryMode, Brush, BrushTool, Checkpoint, Config, ConfigError,
+    DiffuseSettings, FixedTimestep, FoodMap, ObstacleMask, Preset, RepellentMap, Simulation,
+    SpawnMode, Spawner, SpeciesSettings, StepSizes,
+};
 use winit::{
     application::ApplicationHandler,
     event::*,
@@ -10,12 +30,8 @@ use winit::{
     keyboard::{KeyCode, PhysicalKey},
     window::{Fullscreen, Window, WindowId},
 };
-static AGENTS_PER_GROUP: u32 = 128;
-static NUM_AGENTS: u32 = (1 << 23) - AGENTS_PER_GROUP;
-static DIFFUSE_TILE_SIZE: u32 = 16;
-static SCALE_DOWN_FACTOR: f32 = 1.0;
-static SIM_WIDTH: u32 = (3840.0 * SCALE_DOWN_FACTOR) as _;
-static SIM_HEIGHT: u32 = (2160.0 * SCALE_DOWN_FACTOR) as _;
+
+mod gui;
 
 /// Slime Simulation
 #[derive(Parser)]
@@ -24,116 +40,593 @@ struct Args {
     /// Enable VSync
     #[arg(long)]
     vsync: bool,
+    /// Render offscreen without opening a window and write PNG frames
+    #[arg(long)]
+    headless: bool,
+    /// Number of frames to render in headless mode
+    #[arg(long, default_value_t = 300)]
+    frames: u32,
+    /// Render headless into a looping animation (.gif, or .png/.apng for
+    /// APNG) instead of separate frames. Animated WebP is not supported
+    #[arg(long, conflicts_with_all = ["record", "record_pipe"])]
+    export_gif: Option<PathBuf>,
+    /// Put every Nth headless frame into the animation
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    every: u32,
+    /// Width the animation is downsampled to
+    #[arg(long, default_value_t = 480)]
+    gif_width: u32,
+    /// Crossfade this many frames at the end of the animation into its start
+    /// for a seamless loop
+    #[arg(long, default_value_t = 0)]
+    crossfade: usize,
+    /// Output directory for headless frames
+    #[arg(long, default_value = "frames")]
+    out: PathBuf,
+    /// Where agent updates and diffusion run in the windowed viewer
+    #[arg(long, value_enum, default_value_t = BackendKind::Gpu)]
+    backend: BackendKind,
+    /// TOML or JSON file with simulation, species and diffusion parameters
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Trail map width, overriding the config file
+    #[arg(long)]
+    width: Option<u32>,
+    /// Trail map height, overriding the config file
+    #[arg(long)]
+    height: Option<u32>,
+    /// Number of agents, overriding the config file
+    #[arg(long)]
+    agents: Option<u32>,
+    /// Trail map texels per rendered pixel, overriding the config file
+    #[arg(long)]
+    scale: Option<f32>,
+    /// Initial agent placement, overriding the config file
+    #[arg(long, value_enum)]
+    spawn: Option<SpawnMode>,
+    /// Spawn agents more densely where this image is brighter (implies
+    /// `--spawn image`)
+    #[arg(long)]
+    spawn_image: Option<PathBuf>,
+    /// Mask image whose bright pixels are walls, overriding the config file
+    #[arg(long)]
+    obstacles: Option<PathBuf>,
+    /// Image whose brightness is food that keeps feeding the trail,
+    /// overriding the config file
+    #[arg(long)]
+    food: Option<PathBuf>,
+    /// Image whose brightness repels agents, overriding the config file
+    #[arg(long)]
+    repellent: Option<PathBuf>,
+    /// Seed for agent placement and steering, printed at startup if left out
+    #[arg(long)]
+    seed: Option<u32>,
+    /// Fixed simulation steps per second, overriding the config file
+    #[arg(long)]
+    step_rate: Option<f32>,
+    /// Most simulation steps run per rendered frame, overriding the config
+    /// file
+    #[arg(long)]
+    max_substeps: Option<u32>,
+    /// Simulation speed multiplier, overriding the config file
+    #[arg(long)]
+    speed: Option<f32>,
+    /// What agents do at the edge of the map, overriding the config file
+    #[arg(long, value_enum)]
+    boundary: Option<BoundaryMode>,
+    /// Where F5 saves a checkpoint of the whole simulation
+    #[arg(long, default_value = "checkpoint.slime")]
+    checkpoint: PathBuf,
+    /// Start from a checkpoint instead of spawning, taking the sizes, species
+    /// and seed from it
+    #[arg(long)]
+    resume: Option<PathBuf>,
+    /// Preset file the overlay's Load and Save buttons start out with, and
+    /// that the next and previous preset keys apply without `--presets`
+    #[arg(long, default_value = "preset.toml")]
+    preset: PathBuf,
+    /// Directory of .toml and .json presets the next and previous preset
+    /// keys cycle through in name order
+    #[arg(long)]
+    presets: Option<PathBuf>,
+    /// Seconds a preset takes to morph into the next; 0 switches at once
+    #[arg(long, default_value_t = 2.0)]
+    preset_transition: f32,
+    /// TOML or JSON file mapping keys to actions, with their step sizes
+    #[arg(long)]
+    bindings: Option<PathBuf>,
+    /// Print the key bindings in the format `--bindings` reads, then exit
+    #[arg(long)]
+    print_bindings: bool,
+    /// Directory F12 saves full-resolution PNG and EXR screenshots into
+    #[arg(long, default_value = "screenshots")]
+    screenshots: PathBuf,
+    /// Take the species settings and seed from a screenshot PNG
+    #[arg(long)]
+    from_png: Option<PathBuf>,
+    /// Record the rendered frames to an uncompressed Y4M file
+    #[arg(long, conflicts_with = "record_pipe")]
+    record: Option<PathBuf>,
+    /// Record by piping raw RGBA frames into this shell command's stdin
+    #[arg(long)]
+    record_pipe: Option<String>,
+    /// Video frames per second of simulated time when recording
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u32).range(1..))]
+    record_fps: u32,
+    /// Watch this directory for slime.wgsl, place.wgsl, diffuse.wgsl,
+    /// shader.wgsl and scaling.wgsl and hot-reload them when they change
+    #[arg(long)]
+    shader_dir: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum BackendKind {
+    /// Compute shaders on a Vulkan adapter
+    Gpu,
+    /// rayon on all CPU cores, only presenting through wgpu
+    Cpu,
+}
+
+/// Radius in trail map texels of the left and right mouse button brushes.
+static BRUSH_RADIUS: f32 = 16.0;
+/// Trail the left button paints per second it is held down.
+static PAINT_RATE: f32 = 30.0;
+/// Share of the trail the right button erases per second.
+static ERASE_RATE: f32 = 8.0;
+/// Agents Ctrl + left button moves under the cursor per second.
+static RELOCATE_RATE: f32 = 4000.0;
+/// Texels per second the gravity well pulls agents at its centre.
+static ATTRACT_RATE: f32 = 120.0;
+
+/// Radius in trail map texels of the middle mouse button repellent brush.
+static REPELLENT_BRUSH_RADIUS: f32 = 24.0;
+/// Repellent the brush adds per second it is held down.
+static REPELLENT_BRUSH_RATE: f32 = 2.0;
+
+/// How often `--shader-dir` is checked for edits.
+static SHADER_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Wall-clock time each headless frame stands for, as there is no real clock
+/// to follow.
+static HEADLESS_DELTA: f32 = 1.0 / 60.0;
+
+/// Loads `--resume` if given, exiting with the error if it can't be read.
+fn load_checkpoint(args: &Args) -> Option<Checkpoint> {
+    let path = args.resume.as_ref()?;
+    Some(Checkpoint::load(path).unwrap_or_else(|e| {
+        eprintln!("error: {}: {}", path.display(), e);
+        std::process::exit(1);
+    }))
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-#[allow(non_snake_case)]
-struct SpeciesSettings {
-    moveSpeed: f32,
-    turnSpeed: f32,
-    sensorAngleDegrees: f32,
-    sensorOffsetDst: f32,
-    sensorSize: f32,
-    colourR: f32,
-    colourG: f32,
-    colourB: f32,
-    colourA: f32,
+/// Loads `--bindings` if given, exiting with the error if it can't be read or
+/// names a key winit doesn't know. Also returns the map from each key to its
+/// action.
+fn load_bindings(args: &Args) -> (Bindings, HashMap<KeyCode, Action>) {
+    let bindings = match &args.bindings {
+        Some(path) => Bindings::load(path).unwrap_or_else(|e| {
+            eprintln!("error: {}: {}", path.display(), e);
+            std::process::exit(1);
+        }),
+        None => Bindings::default(),
+    };
+    let keys = bindings
+        .keys
+        .iter()
+        .map(|(name, &action)| {
+            let code: Result<_, serde::de::value::Error> =
+                KeyCode::deserialize(name.as_str().into_deserializer());
+            let code = code.unwrap_or_else(|_| {
+                let source = args.bindings.as_deref().unwrap_or(Path::new("bindings"));
+                eprintln!(
+                    "error: {}: unknown key {:?}, expected a winit KeyCode like \"KeyD\" or \"F5\"",
+                    source.display(),
+                    name
+                );
+                std::process::exit(1);
+            });
+            (code, action)
+        })
+        .collect();
+    (bindings, keys)
+}
+
+/// Lists `--presets` if given, or else just `--preset`, exiting with the error
+/// if the directory can't be read or holds no presets.
+fn load_presets(args: &Args) -> Vec<PathBuf> {
+    let Some(dir) = &args.presets else {
+        return vec![args.preset.clone()];
+    };
+    let presets = preset::library(dir).unwrap_or_else(|e| {
+        eprintln!("error: {}: {}", dir.display(), e);
+        std::process::exit(1);
+    });
+    if presets.is_empty() {
+        eprintln!("error: {}: no .toml or .json presets", dir.display());
+        std::process::exit(1);
+    }
+    println!("{} presets in {}", presets.len(), dir.display());
+    presets
+}
+
+/// Loads `--config` if given and applies the size overrides and `checkpoint`
+/// on top, exiting with the error if anything is invalid.
+fn load_config(args: &Args, checkpoint: Option<&Checkpoint>) -> Config {
+    let mut config = match &args.config {
+        Some(path) => Config::load(path).unwrap_or_else(|e| {
+            eprintln!("error: {}: {}", path.display(), e);
+            std::process::exit(1);
+        }),
+        None => Config::default(),
+    };
+    let simulation = &mut config.simulation;
+    simulation.width = args.width.unwrap_or(simulation.width);
+    simulation.height = args.height.unwrap_or(simulation.height);
+    simulation.num_agents = args.agents.unwrap_or(simulation.num_agents);
+    simulation.scale = args.scale.unwrap_or(simulation.scale);
+    simulation.seed = args.seed.or(simulation.seed);
+    simulation.step_rate = args.step_rate.unwrap_or(simulation.step_rate);
+    simulation.max_substeps = args.max_substeps.unwrap_or(simulation.max_substeps);
+    simulation.speed = args.speed.unwrap_or(simulation.speed);
+    simulation.boundary = args.boundary.unwrap_or(simulation.boundary);
+    let spawn = &mut config.spawn;
+    spawn.mode = args.spawn.unwrap_or(spawn.mode);
+    if let Some(path) = &args.spawn_image {
+        spawn.mode = SpawnMode::Image;
+        spawn.image = Some(path.clone());
+    }
+    if let Some(path) = &args.obstacles {
+        config.obstacles.image = Some(path.clone());
+    }
+    if let Some(path) = &args.food {
+        config.food.image = Some(path.clone());
+    }
+    if let Some(path) = &args.repellent {
+        config.repellent.image = Some(path.clone());
+    }
+    if let Some(path) = &args.from_png {
+        let info = ScreenshotInfo::read_png(path).unwrap_or_else(|e| {
+            eprintln!("error: {}: {}", path.display(), e);
+            std::process::exit(1);
+        });
+        println!(
+            "using parameters from frame {} of {}",
+            info.frame,
+            path.display()
+        );
+        info.apply_to(&mut config);
+    }
+    if let Some(checkpoint) = checkpoint {
+        checkpoint.apply_to(&mut config);
+    }
+    exit_on_error(config.validate());
+    config
+}
+
+/// Resolves the spawn seed and image, printing the seed so a run can be
+/// repeated with `--seed`.
+fn build_spawner(config: &Config) -> Spawner {
+    let spawner = exit_on_error(Spawner::new(config));
+    println!("seed: {}", spawner.seed());
+    spawner
+}
+
+/// Loads the obstacle mask, exiting with the error if the image can't be read.
+fn load_obstacles(config: &Config) -> ObstacleMask {
+    exit_on_error(ObstacleMask::new(&config.obstacles))
+}
+
+/// Lays out the food sources, exiting with the error if the image can't be
+/// read.
+fn load_food(config: &Config) -> FoodMap {
+    exit_on_error(FoodMap::new(config))
+}
+
+/// Lays out the repellent, exiting with the error if the image can't be read.
+fn load_repellent(config: &Config) -> RepellentMap {
+    exit_on_error(RepellentMap::new(config))
+}
+
+/// Starts `--record` or `--record-pipe` if given, exiting with the error if
+/// the file or command can't be opened.
+fn start_recording(
+    args: &Args,
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> Option<Recorder> {
+    let (target, description) = match (&args.record, &args.record_pipe) {
+        (Some(path), _) => (RecordTarget::Y4m(path.clone()), path.display().to_string()),
+        (None, Some(command)) => (
+            RecordTarget::Pipe(command.clone()),
+            format!("`{}`", command),
+        ),
+        (None, None) => return None,
+    };
+    let recorder = Recorder::new(device, &target, width, height, format, args.record_fps)
+        .unwrap_or_else(|e| {
+            eprintln!("error: could not start recording: {}", e);
+            std::process::exit(1);
+        });
+    println!(
+        "recording {}x{} at {} fps to {}",
+        width, height, args.record_fps, description
+    );
+    Some(recorder)
+}
+
+fn finish_recording(recorder: Recorder, device: &wgpu::Device) {
+    match recorder.finish(device) {
+        Ok(frames) => println!("recorded {} frames", frames),
+        Err(e) => eprintln!("error: recording failed: {}", e),
+    }
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-#[allow(non_snake_case)]
-struct ShaderParams {
-    numAgents: f32,
-    width: f32,
-    height: f32,
-    delta: f32,
-    time: f32,
+fn exit_on_error<T>(result: Result<T, ConfigError>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    })
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-#[allow(non_snake_case)]
-struct RenderParams {
-    width: f32,
-    height: f32,
-    scaleDownFactor: f32,
+enum Backend {
+    Gpu {
+        simulation: Box<Simulation>,
+        trail_renderer: TrailRenderer,
+        /// What is on the GPU, painted here and uploaded a few rows at a time.
+        repellent: RepellentMap,
+    },
+    Cpu {
+        simulation: Box<CpuSimulation>,
+        trail_renderer: CpuTrailRenderer,
+    },
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-#[allow(non_snake_case)]
-struct Agent {
-    posX: f32,
-    posY: f32,
-    angle: f32,
-    // intensity: f32,
+impl Backend {
+    /// Rebuilds the pipeline that runs `name`, one of
+    /// [`slime_webgpu::hot_reload::WATCHED_SHADERS`] other than
+    /// `scaling.wgsl`. Returns false if this backend doesn't use the shader.
+    fn reload_shader(
+        &mut self,
+        name: &str,
+        path: &Path,
+        source: &str,
+    ) -> Result<bool, ShaderError> {
+        let Backend::Gpu {
+            simulation,
+            trail_renderer,
+            ..
+        } = self
+        else {
+            return Ok(false);
+        };
+        match name {
+            "slime.wgsl" => simulation.reload_update_shader(path, source)?,
+            "place.wgsl" => simulation.reload_place_shader(path, source)?,
+            "diffuse.wgsl" => simulation.reload_diffuse_shader(path, source)?,
+            "shader.wgsl" => trail_renderer.reload_shader(simulation.device(), path, source)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn species(&self) -> &[SpeciesSettings] {
+        match self {
+            Backend::Gpu { simulation, .. } => simulation.species(),
+            Backend::Cpu { simulation, .. } => simulation.species(),
+        }
+    }
+
+    fn set_species(&mut self, species: &[SpeciesSettings]) {
+        match self {
+            Backend::Gpu { simulation, .. } => simulation.set_species(species),
+            Backend::Cpu { simulation, .. } => simulation.set_species(species),
+        }
+    }
+
+    fn diffuse(&self) -> DiffuseSettings {
+        match self {
+            Backend::Gpu { simulation, .. } => simulation.diffuse(),
+            Backend::Cpu { simulation, .. } => simulation.diffuse(),
+        }
+    }
+
+    fn set_diffuse(&mut self, diffuse: DiffuseSettings) {
+        match self {
+            Backend::Gpu { simulation, .. } => simulation.set_diffuse(diffuse),
+            Backend::Cpu { simulation, .. } => simulation.set_diffuse(diffuse),
+        }
+    }
+
+    fn checkpoint(&self) -> std::io::Result<Checkpoint> {
+        match self {
+            Backend::Gpu { simulation, .. } => simulation.checkpoint(),
+            Backend::Cpu { simulation, .. } => Ok(simulation.checkpoint()),
+        }
+    }
+
+    fn restore(&mut self, checkpoint: &Checkpoint) {
+        match self {
+            Backend::Gpu {
+                simulation,
+                repellent,
+                ..
+            } => {
+                simulation.restore(checkpoint);
+                repellent.values.clone_from(&checkpoint.repellent);
+            }
+            Backend::Cpu { simulation, .. } => simulation.restore(checkpoint),
+        }
+    }
+
+    fn brush(&mut self, brush: &Brush) {
+        match self {
+            Backend::Gpu { simulation, .. } => simulation.brush(brush),
+            Backend::Cpu { simulation, .. } => simulation.brush(brush),
+        }
+    }
+
+    /// See [`RepellentMap::paint`].
+    fn paint_repellent(&mut self, x: f32, y: f32, radius: f32, amount: f32) {
+        match self {
+            Backend::Gpu {
+                simulation,
+                repellent,
+                ..
+            } => {
+                let rows = repellent.paint(x, y, radius, amount);
+                simulation.write_repellent(repellent, rows);
+            }
+            Backend::Cpu { simulation, .. } => simulation.paint_repellent(x, y, radius, amount),
+        }
+    }
+
+    /// The colour pass at full trail map resolution, the raw trail texels and
+    /// the parameters to embed, all read back to the CPU.
+    fn screenshot(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+    ) -> std::io::Result<(image::RgbaImage, Vec<[f32; 4]>, ScreenshotInfo)> {
+        let (image, texels, shader_params, species) = match self {
+            Backend::Gpu { simulation, .. } => (
+                FrameCapture::new(simulation, 1.0).capture(simulation)?,
+                simulation.read_trail_texels()?,
+                simulation.shader_params(),
+                simulation.species(),
+            ),
+            Backend::Cpu { simulation, .. } => {
+                let trail_map = simulation.trail_map();
+                let trail_renderer =
+                    CpuTrailRenderer::new(device, queue, simulation, headless::FRAME_FORMAT, 1.0);
+                trail_renderer.upload(queue, trail_map, simulation.species());
+                let target = OffscreenTarget::new(device, trail_map.width, trail_map.height);
+                let image = target.capture(device, queue, |encoder, view| {
+                    trail_renderer.draw(encoder, view, wgpu::Color::BLACK)
+                })?;
+                (
+                    image,
+                    trail_map.to_texels(),
+                    simulation.shader_params(),
+                    simulation.species(),
+                )
+            }
+        };
+        let info = ScreenshotInfo {
+            species: species.to_vec(),
+            seed: shader_params.seed,
+            frame: shader_params.frame,
+        };
+        Ok((image, texels, info))
+    }
+
+    fn step(&mut self, delta: f32) {
+        match self {
+            Backend::Gpu { simulation, .. } => simulation.step(delta),
+            Backend::Cpu { simulation, .. } => simulation.step(delta),
+        }
+    }
+
+    fn vertex_buffer(&self) -> &wgpu::Buffer {
+        match self {
+            Backend::Gpu { trail_renderer, .. } => trail_renderer.vertex_buffer(),
+            Backend::Cpu { trail_renderer, .. } => trail_renderer.vertex_buffer(),
+        }
+    }
+
+    fn draw(
+        &self,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        clear_color: wgpu::Color,
+    ) {
+        match self {
+            Backend::Gpu { trail_renderer, .. } => trail_renderer.draw(encoder, view, clear_color),
+            Backend::Cpu {
+                simulation,
+                trail_renderer,
+            } => {
+                trail_renderer.upload(queue, simulation.trail_map(), simulation.species());
+                trail_renderer.draw(encoder, view, clear_color);
+            }
+        }
+    }
 }
+
 struct State<'a> {
     surface: wgpu::Surface<'a>,
-    window: Arc<Box<winit::window::Window>>,
+    window: Arc<winit::window::Window>,
     device: wgpu::Device,
     queue: wgpu::Queue,
+    backend: Backend,
     config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
     clear_color: wgpu::Color,
-    render_pipeline: wgpu::RenderPipeline,
-    render_bind_group: BindGroup,
-    compute_pipeline: wgpu::ComputePipeline,
-    compute_bind_group: BindGroup,
-    compute_diffuse_pipeline: wgpu::ComputePipeline,
-    compute_diffuse_bind_group: BindGroup,
-    shader_param_buffer: wgpu::Buffer,
-    species_param_buffer: wgpu::Buffer,
-    vertex_buffer: wgpu::Buffer,
-    time: f32,
-    ping_texture: wgpu::Texture,
-    pong_texture: wgpu::Texture,
     then: Instant,
-    bundle: wgpu::RenderBundle,
-    shader_param_data: ShaderParams,
-    species_param_data: SpeciesSettings,
+    timestep: FixedTimestep,
+    checkpoint_path: PathBuf,
+    screenshot_dir: PathBuf,
+    recorder: Option<Recorder>,
+    /// Video frames owed to the recorder for the steps run since the last draw.
+    record_due: u32,
+    sim_aspect: f32,
+    /// Trail map size, to find the texel under the cursor.
+    sim_size: [f32; 2],
+    cursor: Option<winit::dpi::PhysicalPosition<f64>>,
+    modifiers: winit::keyboard::ModifiersState,
+    /// The left mouse button is down, painting trail, relocating agents or
+    /// pulling them in.
+    brushing: bool,
+    /// The right mouse button is down, erasing trail.
+    erasing: bool,
+    /// The middle mouse button is down, painting repellent.
+    painting_repellent: bool,
+    /// G turns the left button into a gravity well.
+    gravity_well: bool,
+    /// The trail layer the left button paints into, cycled with Tab.
+    brush_species: u32,
+    /// Parameter overlay, toggled with F1.
+    gui: Gui,
+    /// What each bound key does, see [`load_bindings`].
+    keys: HashMap<KeyCode, Action>,
+    steps: StepSizes,
+    /// Files the next and previous preset keys go through, see
+    /// [`load_presets`].
+    presets: Vec<PathBuf>,
+    /// The preset last picked, `None` until a preset key is pressed.
+    preset_index: Option<usize>,
+    /// The morph into the last preset picked, until it is done or the
+    /// overlay changes something.
+    transition: Option<Transition>,
+    transition_seconds: f32,
+    /// Set by `--shader-dir`.
+    shader_watcher: Option<ShaderWatcher>,
+    last_shader_poll: Instant,
 
+    sim_texture: wgpu::Texture,
     sim_texture_view: wgpu::TextureView,
     scaling_pipeline: wgpu::RenderPipeline,
+    scaling_pipeline_layout: wgpu::PipelineLayout,
     scaled_texture_bind_group: wgpu::BindGroup,
-    sampler: wgpu::Sampler,
     uniform_buffer: wgpu::Buffer,
-    args: Args,
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
-struct Vertex {
-    position: [f32; 3],
-}
-
-const VERTICES: &[Vertex] = &[
-    Vertex {
-        position: [-1.0, 1.0, 0.0],
-    },
-    Vertex {
-        position: [-1.0, -1.0, 0.0],
-    },
-    Vertex {
-        position: [1.0, -1.0, 0.0],
-    },
-    Vertex {
-        position: [1.0, -1.0, 0.0],
-    },
-    Vertex {
-        position: [1.0, 1.0, 0.0],
-    },
-    Vertex {
-        position: [-1.0, 1.0, 0.0],
-    },
-];
-
 impl<'a> State<'a> {
     // Creating some of the wgpu types requires async code
     async fn new(window: Window, args: Args) -> Self {
         let size = window.inner_size();
+        let checkpoint = load_checkpoint(&args);
+        let sim_config = load_config(&args, checkpoint.as_ref());
+        let (bindings, keys) = load_bindings(&args);
+        let spawner = build_spawner(&sim_config);
+        let obstacles = load_obstacles(&sim_config);
+        let food = load_food(&sim_config);
+        let repellent = load_repellent(&sim_config);
+        let sim_width = sim_config.simulation.width;
+        let sim_height = sim_config.simulation.height;
+        let (render_width, render_height) = sim_config.simulation.render_size();
         // window.set_fullscreen(Some(Fullscreen::Exclusive(
         //     window
         //         .primary_monitor()
@@ -146,11 +639,16 @@ impl<'a> State<'a> {
         // window.set_fullscreen(None);
         // The instance is a handle to our GPU
         // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
+        // The CPU backend only presents, so any backend that can draw will do
+        let backends = match args.backend {
+            BackendKind::Gpu => wgpu::Backends::VULKAN,
+            BackendKind::Cpu => wgpu::Backends::all(),
+        };
         let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::VULKAN,
+            backends,
             ..Default::default()
         });
-        let window = Arc::new(Box::new(window));
+        let window = Arc::new(window);
         let surface = instance.create_surface(window.clone()).unwrap();
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
@@ -160,16 +658,25 @@ impl<'a> State<'a> {
             })
             .await
             .unwrap();
-        let (device, queue) = adapter
-            .request_device(&wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
-                required_limits: wgpu::Limits::default(),
-                memory_hints: wgpu::MemoryHints::Performance,
-                trace: wgpu::Trace::Off,
-                label: None,
-            })
-            .await
-            .unwrap();
+        let device_descriptor = match args.backend {
+            BackendKind::Gpu => Simulation::device_descriptor(&adapter),
+            BackendKind::Cpu => wgpu::DeviceDescriptor {
+                required_limits: wgpu::Limits::downlevel_webgl2_defaults()
+                    .using_resolution(adapter.limits()),
+                ..Default::default()
+            },
+        };
+        let (device, queue) = adapter.request_device(&device_descriptor).await.unwrap();
+        exit_on_error(match args.backend {
+            BackendKind::Gpu => {
+                sim_config
+                    .simulation
+                    .check_limits(&device.limits(), &obstacles, &spawner)
+            }
+            BackendKind::Cpu => sim_config
+                .simulation
+                .check_texture_limits(&device.limits(), &obstacles),
+        });
 
         let vsync_mode = if args.vsync {
             wgpu::PresentMode::AutoVsync
@@ -188,412 +695,75 @@ impl<'a> State<'a> {
             alpha_mode: wgpu::CompositeAlphaMode::Auto,
         };
         surface.configure(&device, &config);
+        let gui = Gui::new(&window, &device, config.format, &args.preset);
 
         let clear_color = wgpu::Color::BLACK;
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Render Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/shader.wgsl").into()),
-        });
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
-        let ping_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("Ping Texture"),
-            size: wgpu::Extent3d {
-                width: SIM_WIDTH,
-                height: SIM_HEIGHT,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba32Float,
-            usage: wgpu::TextureUsages::STORAGE_BINDING
-                | wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::COPY_DST,
-            view_formats: &[wgpu::TextureFormat::Rgba32Float],
-        });
-        let pong_texture = device.create_texture(&wgpu::TextureDescriptor {
-            label: Some("Pong Texture"),
-            size: wgpu::Extent3d {
-                width: SIM_WIDTH,
-                height: SIM_HEIGHT,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba32Float,
-            usage: wgpu::TextureUsages::STORAGE_BINDING
-                | wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::COPY_SRC,
-            view_formats: &[wgpu::TextureFormat::Rgba32Float],
-        });
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-        let render_param_data = RenderParams {
-            width: SIM_WIDTH as _,
-            height: SIM_HEIGHT as _,
-            scaleDownFactor: SCALE_DOWN_FACTOR as _,
-        };
-        let render_param_slice = &[render_param_data];
-        let render_param_slice: &[u8] = bytemuck::cast_slice(render_param_slice);
-        let render_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    // Texture Sampler
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
-                        count: None,
-                    },
-                    // Storage Texture
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::StorageTexture {
-                            access: wgpu::StorageTextureAccess::ReadOnly,
-                            format: wgpu::TextureFormat::Rgba32Float,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                        },
-                        count: None,
-                    },
-                    // Render Params Buffer
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::FRAGMENT,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: wgpu::BufferSize::new(render_param_slice.len() as _),
-                        },
-                        count: None,
-                    },
-                ],
-                label: None,
-            });
-        let render_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&render_bind_group_layout],
-                push_constant_ranges: &[],
-            });
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            cache: None,
-            label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
-            multiview: None,
-            vertex: wgpu::VertexState {
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                module: &shader,
-                entry_point: Some("vs_main"), // 1.
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &wgpu::vertex_attr_array![0 => Float32x3],
-                }], // 2.
-            },
-            fragment: Some(wgpu::FragmentState {
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                // 3.
-                module: &shader,
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    // 4.
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList, // 1.
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw, // 2.
-                cull_mode: Some(wgpu::Face::Back),
-                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                polygon_mode: wgpu::PolygonMode::Fill,
-                // Requires Features::DEPTH_CLAMPING
-                unclipped_depth: false,
-                // Requires Features::CONSERVATIVE_RASTERIZATION
-                conservative: false,
-            },
-            depth_stencil: None, // 1.
-            multisample: wgpu::MultisampleState::default(), // multisample: wgpu::MultisampleState {
-                                 //     count: SAMPLE_COUNT,              // 2.
-                                 //     mask: !0,                         // 3.
-                                 //     alpha_to_coverage_enabled: false, // 4.
-                                 // },
-        });
-        let render_param_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Render Parameter Buffer"),
-            contents: bytemuck::cast_slice(&[render_param_data]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST, // | wgpu::BufferUsages::MAP_WRITE,
-        });
-        let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &render_pipeline.get_bind_group_layout(0),
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::Sampler(&sampler),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(
-                        &ping_texture.create_view(&wgpu::TextureViewDescriptor::default()),
-                    ),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: render_param_buffer.as_entire_binding(),
-                },
-            ],
-            label: None,
-        });
-
-        let mut encoder =
-            device.create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
-                label: None,
-                multiview: None,
-                color_formats: &[Some(config.format)],
-                depth_stencil: None,
-                sample_count: 1,
-            });
-        encoder.set_pipeline(&render_pipeline);
-        encoder.set_vertex_buffer(0, vertex_buffer.slice(..));
-        encoder.draw(0..VERTICES.len() as _, 0..1);
-        let bundle = encoder.finish(&wgpu::RenderBundleDescriptor {
-            label: Some("main"),
-        });
-
-        let shader_param_data = ShaderParams {
-            numAgents: NUM_AGENTS as _,
-            width: SIM_WIDTH as _,
-            height: SIM_HEIGHT as _,
-            delta: 0.03,
-            time: 0.0,
-        };
-        let shader_param_slice = &[shader_param_data];
-        let shader_param_slice: &[u8] = bytemuck::cast_slice(shader_param_slice);
-
-        let shader_param_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Shader Parameter Buffer"),
-            contents: shader_param_slice,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST, // | wgpu::BufferUsages::MAP_WRITE,
-        });
-        let species_param_data = SpeciesSettings {
-            moveSpeed: 120.0,
-            turnSpeed: -4.0,
-            sensorAngleDegrees: 112.0,
-            sensorOffsetDst: 50.0,
-            sensorSize: 0.0,
-            colourR: 0.0,
-            colourG: 1.0,
-            colourB: 0.0,
-            colourA: 1.0,
+        let mut backend = match args.backend {
+            BackendKind::Gpu => {
+                let simulation = Simulation::new(
+                    device.clone(),
+                    queue.clone(),
+                    &sim_config,
+                    &spawner,
+                    &obstacles,
+                    &food,
+                    &repellent,
+                );
+                let trail_renderer =
+                    TrailRenderer::new(&simulation, config.format, sim_config.simulation.scale);
+                Backend::Gpu {
+                    simulation: Box::new(simulation),
+                    trail_renderer,
+                    repellent,
+                }
+            }
+            BackendKind::Cpu => {
+                let simulation = CpuSimulation::new(
+                    spawner.spawn_agents(),
+                    &sim_config,
+                    &spawner,
+                    &obstacles,
+                    &food,
+                    &repellent,
+                );
+                let trail_renderer = CpuTrailRenderer::new(
+                    &device,
+                    &queue,
+                    &simulation,
+                    config.format,
+                    sim_config.simulation.scale,
+                );
+                Backend::Cpu {
+                    simulation: Box::new(simulation),
+                    trail_renderer,
+                }
+            }
         };
-        let species_param_slice = &[species_param_data];
-        let species_param_slice: &[u8] = bytemuck::cast_slice(species_param_slice);
-
-        let species_param_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Species Parameter Buffer"),
-            contents: species_param_slice,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST, // | wgpu::BufferUsages::MAP_WRITE,
-        });
-        let compute_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    // Shader Parameter Buffer
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: wgpu::BufferSize::new(shader_param_slice.len() as _),
-                        },
-                        count: None,
-                    },
-                    // Species Parameter Buffer
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: wgpu::BufferSize::new(species_param_slice.len() as _),
-                        },
-                        count: None,
-                    },
-                    // Agents Buffer
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Storage { read_only: false },
-                            has_dynamic_offset: false,
-                            min_binding_size: wgpu::BufferSize::new((NUM_AGENTS * 4 * 3) as _),
-                        },
-                        count: None,
-                    },
-                    // Storage Texture
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 3,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::StorageTexture {
-                            access: wgpu::StorageTextureAccess::ReadWrite,
-                            format: wgpu::TextureFormat::Rgba32Float,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                        },
-                        count: None,
-                    },
-                ],
-                label: None,
-            });
-        let compute_diffuse_bind_group_layout =
-            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                entries: &[
-                    // Shader Parameter Buffer
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 0,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::Buffer {
-                            ty: wgpu::BufferBindingType::Uniform,
-                            has_dynamic_offset: false,
-                            min_binding_size: wgpu::BufferSize::new(shader_param_slice.len() as _),
-                        },
-                        count: None,
-                    },
-                    // Ping (Read) Texture
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 1,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::StorageTexture {
-                            access: wgpu::StorageTextureAccess::ReadOnly,
-                            format: wgpu::TextureFormat::Rgba32Float,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                        },
-                        count: None,
-                    },
-                    // Pong (Write) Texture
-                    wgpu::BindGroupLayoutEntry {
-                        binding: 2,
-                        visibility: wgpu::ShaderStages::COMPUTE,
-                        ty: wgpu::BindingType::StorageTexture {
-                            access: wgpu::StorageTextureAccess::WriteOnly,
-                            format: wgpu::TextureFormat::Rgba32Float,
-                            view_dimension: wgpu::TextureViewDimension::D2,
-                        },
-                        count: None,
-                    },
-                ],
-                label: None,
-            });
-        let compute_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("compute"),
-                bind_group_layouts: &[&compute_bind_group_layout],
-                push_constant_ranges: &[],
-            });
-        let compute_diffuse_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("compute diffuse"),
-                bind_group_layouts: &[&compute_diffuse_bind_group_layout],
-                push_constant_ranges: &[],
-            });
-        // // Compute shader pipeline
-        let compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Compute Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/slime.wgsl").into()),
-        });
-        let compute_diffuse_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Compute Diffuse Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/diffuse.wgsl").into()),
-        });
-        let compute_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            cache: None,
-            compilation_options: wgpu::PipelineCompilationOptions::default(),
-            label: Some("Compute Pipeline"),
-            layout: Some(&compute_pipeline_layout),
-            module: &compute_shader,
-            entry_point: Some("update"),
-        });
-        let compute_diffuse_pipeline =
-            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                cache: None,
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                label: Some("Compute Diffuse Pipeline"),
-                layout: Some(&compute_diffuse_pipeline_layout),
-                module: &compute_diffuse_shader,
-                entry_point: Some("diffuse"),
-            });
-        let agent_buffer = Self::build_agent_buffer(&device);
-        let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &compute_pipeline.get_bind_group_layout(0),
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: shader_param_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: species_param_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: agent_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: wgpu::BindingResource::TextureView(
-                        &ping_texture.create_view(&wgpu::TextureViewDescriptor::default()),
-                    ),
-                },
-            ],
-            label: None,
-        });
+        if let Some(checkpoint) = &checkpoint {
+            backend.restore(checkpoint);
+        }
 
-        let compute_diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &compute_diffuse_pipeline.get_bind_group_layout(0),
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: shader_param_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::TextureView(
-                        &ping_texture.create_view(&wgpu::TextureViewDescriptor::default()),
-                    ),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: wgpu::BindingResource::TextureView(
-                        &pong_texture.create_view(&wgpu::TextureViewDescriptor::default()),
-                    ),
-                },
-            ],
-            label: None,
-        });
         // Create fixed-size simulation texture with matching format
         let sim_texture = device.create_texture(&wgpu::TextureDescriptor {
             view_formats: &[config.format],
             size: wgpu::Extent3d {
-                width: SIM_WIDTH,
-                height: SIM_HEIGHT,
+                width: render_width,
+                height: render_height,
                 depth_or_array_layers: 1,
             },
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: config.format, // Use same format as surface config
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
             label: Some("simulation_texture"),
         });
 
         let sim_texture_view = sim_texture.create_view(&Default::default());
+        let recorder = start_recording(&args, &device, render_width, render_height, config.format);
 
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -679,121 +849,55 @@ impl<'a> State<'a> {
         });
 
         // Create scaling pipeline
-        let scaling_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            cache: None,
-            label: Some("scaling_pipeline"),
-            layout: Some(&scaling_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &scaling_shader,
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                entry_point: Some("vs_main"),
-                buffers: &[wgpu::VertexBufferLayout {
-                    array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
-                    step_mode: wgpu::VertexStepMode::Vertex,
-                    attributes: &wgpu::vertex_attr_array![0 => Float32x3],
-                }], // 2.  // Add the vertex buffer layout here
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &scaling_shader,
-                compilation_options: wgpu::PipelineCompilationOptions::default(),
-                entry_point: Some("fs_main"),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: None,
-                unclipped_depth: false,
-                polygon_mode: wgpu::PolygonMode::Fill,
-                conservative: false,
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-        });
+        let scaling_pipeline = scaling_pipeline(
+            &device,
+            &scaling_pipeline_layout,
+            &scaling_shader,
+            config.format,
+        );
 
         Self {
-            args,
             window,
             surface,
             device,
             queue,
+            backend,
             config,
             size,
             clear_color,
-            render_pipeline,
-            render_bind_group,
-            compute_pipeline,
-            compute_bind_group,
-            compute_diffuse_pipeline,
-            compute_diffuse_bind_group,
-            vertex_buffer,
-            shader_param_buffer,
-            species_param_buffer,
-            time: shader_param_data.time,
-            ping_texture,
-            pong_texture,
             then: Instant::now(),
-            bundle,
-            shader_param_data,
-            species_param_data,
+            timestep: FixedTimestep::new(&sim_config.simulation),
+            checkpoint_path: args.checkpoint.clone(),
+            screenshot_dir: args.screenshots.clone(),
+            recorder,
+            record_due: 0,
+            sim_aspect: sim_width as f32 / sim_height as f32,
+            sim_size: [sim_width as f32, sim_height as f32],
+            cursor: None,
+            modifiers: Default::default(),
+            brushing: false,
+            erasing: false,
+            painting_repellent: false,
+            gravity_well: false,
+            brush_species: ALL_SPECIES,
+            gui,
+            keys,
+            steps: bindings.steps,
+            presets: load_presets(&args),
+            preset_index: None,
+            transition: None,
+            transition_seconds: args.preset_transition.max(0.0),
+            shader_watcher: args.shader_dir.as_deref().map(ShaderWatcher::new),
+            last_shader_poll: Instant::now(),
+            sim_texture,
             sim_texture_view,
             scaling_pipeline,
+            scaling_pipeline_layout,
             scaled_texture_bind_group,
-            sampler,
             uniform_buffer,
         }
     }
 
-    fn build_agent_buffer(device: &Device) -> wgpu::Buffer {
-        let mut agents = vec![
-            Agent {
-                posX: 0.0,
-                posY: 0.0,
-                angle: 0.0,
-                // intensity: 0.0,
-            };
-            NUM_AGENTS as _
-        ];
-
-        let mut rng = rand::rng();
-        let now = std::time::Instant::now();
-        for agent in &mut agents {
-            static R: f64 = 300.0;
-            static CENTER_X: f64 = SIM_WIDTH as f64 / 2.0;
-            static CENTER_Y: f64 = SIM_HEIGHT as f64 / 2.0;
-            static RAD_TO_DEG: f64 = 180.0 * std::f64::consts::FRAC_1_PI;
-
-            let r = R * rng.random_range::<f64, _>(0.0..1.0).sqrt();
-            let theta = rng.random_range::<f64, _>(0.0..1.0) * 2.0 * std::f64::consts::PI;
-            agent.posX = (CENTER_X + r * theta.cos()) as f32;
-            agent.posY = (CENTER_Y + r * theta.sin()) as f32;
-            let angle = rng.random_range::<f64, _>(0.0..1.0) * 2.0 * std::f64::consts::PI;
-            // agent.posX = 100.0;
-            // agent.posY = 100.0;
-            agent.angle = ((angle + std::f64::consts::PI) * RAD_TO_DEG) as f32;
-            // agent.posX = rng.random_range(0.0..SIM_WIDTH as f32/ 2.0);
-            // agent.posY = rng.random_range(-(SIM_HEIGHT  as f32/ 2.0)..SIM_HEIGHT as f32);
-        }
-
-        println!("generated agents in {}ms", now.elapsed().as_millis());
-
-        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Agent Buffer"),
-            contents: bytemuck::cast_slice(&agents),
-            usage: wgpu::BufferUsages::VERTEX
-                | wgpu::BufferUsages::STORAGE
-                | wgpu::BufferUsages::COPY_DST,
-        });
-
-        buffer
-    }
-
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
@@ -801,8 +905,12 @@ impl<'a> State<'a> {
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
 
-            let projection =
-                get_projection_matrix(new_size.width as f32, new_size.height as f32, true);
+            let projection = get_projection_matrix(
+                new_size.width as f32,
+                new_size.height as f32,
+                self.sim_aspect,
+                true,
+            );
 
             self.queue
                 .write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&projection));
@@ -812,161 +920,285 @@ impl<'a> State<'a> {
     fn input(&mut self, event: &WindowEvent) -> bool {
         match event {
             WindowEvent::KeyboardInput {
-                device_id: _,
                 event:
                     KeyEvent {
                         state: ElementState::Pressed,
-                        physical_key: PhysicalKey::Code(KeyCode::KeyD),
+                        physical_key: PhysicalKey::Code(code),
                         ..
                     },
                 ..
-            } => {
-                self.species_param_data.moveSpeed += 1.0;
-                self.update_uniform_buffer(&self.species_param_buffer, &self.species_param_data);
+            } => match self.keys.get(code) {
+                Some(&action) => {
+                    self.perform(action);
+                    true
+                }
+                None => false,
+            },
+            WindowEvent::CursorMoved { position, .. } => {
+                self.cursor = Some(*position);
                 true
             }
-            WindowEvent::KeyboardInput {
-                device_id: _,
-                event:
-                    KeyEvent {
-                        state: ElementState::Pressed,
-                        physical_key: PhysicalKey::Code(KeyCode::KeyL),
-                        ..
-                    },
-                ..
-            } => {
-                std::thread::sleep(std::time::Duration::from_millis(20));
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor = None;
                 true
             }
-            WindowEvent::KeyboardInput {
-                device_id: _,
-                event:
-                    KeyEvent {
-                        state: ElementState::Pressed,
-                        physical_key: PhysicalKey::Code(KeyCode::KeyA),
-                        ..
-                    },
-                ..
-            } => {
-                self.species_param_data.moveSpeed =
-                    (self.species_param_data.moveSpeed - 1.0).max(0.0);
-                self.update_uniform_buffer(&self.species_param_buffer, &self.species_param_data);
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
                 true
             }
-
-            WindowEvent::KeyboardInput {
-                device_id: _,
-                event:
-                    KeyEvent {
-                        state: ElementState::Pressed,
-                        physical_key: PhysicalKey::Code(KeyCode::KeyQ),
-                        ..
-                    },
-                ..
-            } => {
-                self.species_param_data.turnSpeed =
-                    (self.species_param_data.turnSpeed + 1.0).min(0.0);
-                self.update_uniform_buffer(&self.species_param_buffer, &self.species_param_data);
+            WindowEvent::MouseInput { state, button, .. } => {
+                let pressed = *state == ElementState::Pressed;
+                match button {
+                    MouseButton::Left => self.brushing = pressed,
+                    MouseButton::Right => self.erasing = pressed,
+                    MouseButton::Middle => self.painting_repellent = pressed,
+                    _ => return false,
+                }
                 true
             }
-            WindowEvent::KeyboardInput {
-                device_id: _,
-                event:
-                    KeyEvent {
-                        state: ElementState::Pressed,
-                        physical_key: PhysicalKey::Code(KeyCode::KeyE),
-                        ..
-                    },
-                ..
-            } => {
-                self.species_param_data.turnSpeed -= 1.0;
-                self.update_uniform_buffer(&self.species_param_buffer, &self.species_param_data);
-                true
+            _ => false,
+        }
+    }
+
+    fn perform(&mut self, action: Action) {
+        match action {
+            Action::SpeedUp => self.change_speed(self.steps.speed_factor),
+            Action::SlowDown => self.change_speed(1.0 / self.steps.speed_factor),
+            Action::Pause => {
+                let paused = !self.timestep.is_paused();
+                self.timestep.set_paused(paused);
+                println!("{}", if paused { "paused" } else { "resumed" });
             }
-            WindowEvent::KeyboardInput {
-                device_id: _,
-                event:
-                    KeyEvent {
-                        state: ElementState::Pressed,
-                        physical_key: PhysicalKey::Code(KeyCode::KeyS),
-                        ..
-                    },
-                ..
-            } => {
-                self.species_param_data.sensorOffsetDst =
-                    (self.species_param_data.sensorOffsetDst - 1.0).max(0.0);
-                self.update_uniform_buffer(&self.species_param_buffer, &self.species_param_data);
-                true
+            Action::SingleStep => self.timestep.single_step(),
+            Action::Stall => std::thread::sleep(Duration::from_millis(20)),
+            Action::SaveCheckpoint => match self
+                .backend
+                .checkpoint()
+                .and_then(|checkpoint| checkpoint.save(&self.checkpoint_path))
+            {
+                Ok(()) => println!("saved {}", self.checkpoint_path.display()),
+                Err(e) => eprintln!("error: {}: {}", self.checkpoint_path.display(), e),
+            },
+            Action::Screenshot => {
+                let saved = self
+                    .backend
+                    .screenshot(&self.device, &self.queue)
+                    .map_err(ScreenshotError::Io)
+                    .and_then(|(image, texels, info)| {
+                        screenshot::save(&self.screenshot_dir, &image, &texels, &info)
+                    });
+                match saved {
+                    Ok(paths) => {
+                        for path in paths {
+                            println!("saved {}", path.display());
+                        }
+                    }
+                    Err(e) => eprintln!("error: {}: {}", self.screenshot_dir.display(), e),
+                }
             }
-            WindowEvent::KeyboardInput {
-                device_id: _,
-                event:
-                    KeyEvent {
-                        state: ElementState::Pressed,
-                        physical_key: PhysicalKey::Code(KeyCode::KeyW),
-                        ..
-                    },
-                ..
-            } => {
-                self.species_param_data.sensorOffsetDst += 1.0;
-                self.update_uniform_buffer(&self.species_param_buffer, &self.species_param_data);
-                true
+            Action::NextPreset => self.cycle_preset(true),
+            Action::PreviousPreset => self.cycle_preset(false),
+            Action::ToggleHud => self.gui.toggle(),
+            Action::ToggleGravityWell => {
+                self.gravity_well = !self.gravity_well;
+                println!(
+                    "left button: {}",
+                    if self.gravity_well {
+                        "gravity well"
+                    } else {
+                        "paint"
+                    }
+                );
+            }
+            Action::CycleBrushSpecies => {
+                // All layers, then each species in turn
+                self.brush_species = self.brush_species.wrapping_add(1);
+                if self.brush_species >= self.backend.species().len() as u32 {
+                    self.brush_species = ALL_SPECIES;
+                    println!("painting every species");
+                } else {
+                    println!("painting species {}", self.brush_species);
+                }
+            }
+            adjustment => {
+                let (parameter, sign) = adjustment
+                    .adjustment()
+                    .expect("every other action has its own arm");
+                let mut species = self.backend.species().to_vec();
+                let mut diffuse = self.backend.diffuse();
+                let amount = sign * self.steps.get(parameter);
+                let value = parameter.adjust(amount, &mut species, &mut diffuse);
+                self.backend.set_species(&species);
+                self.backend.set_diffuse(diffuse);
+                println!("{}: {}", parameter, value);
             }
-            _ => false,
         }
     }
 
-    fn update_uniform_buffer<T: bytemuck::Pod + Send + Sync + std::fmt::Debug>(
-        &self,
-        buffer: &wgpu::Buffer,
-        data: &T,
-    ) {
-        let bytes = bytemuck::bytes_of(data);
-        // dbg!(&data);
-        // self.queue.write_buffer(buffer, 0, bytemuck::bytes_of(data));
+    /// Moves one place along the preset list and starts morphing into that
+    /// preset, keeping the number of species (see [`Preset::species_for`]).
+    /// The first press goes to the first preset, or to the last going back.
+    fn cycle_preset(&mut self, forward: bool) {
+        let len = self.presets.len();
+        let index = match (self.preset_index, forward) {
+            (None, true) => 0,
+            (None, false) => len - 1,
+            (Some(index), true) => (index + 1) % len,
+            (Some(index), false) => (index + len - 1) % len,
+        };
+        self.preset_index = Some(index);
+        let path = &self.presets[index];
+        match Preset::load(path) {
+            Ok(preset) => {
+                let species = self.backend.species();
+                let from = Preset {
+                    species: species.to_vec(),
+                    diffuse: self.backend.diffuse(),
+                };
+                let to = Preset {
+                    species: preset.species_for(species.len()),
+                    diffuse: preset.diffuse,
+                };
+                self.transition = Some(Transition::new(from, to, self.transition_seconds));
+                let name = path.file_stem().unwrap_or_default().to_string_lossy();
+                println!("preset: {}", name);
+            }
+            Err(e) => eprintln!("error: {}: {}", path.display(), e),
+        }
+    }
 
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Command Encoder"),
-            });
-        let device_buffer = self.device.create_buffer(&BufferDescriptor {
-            label: None,
-            size: bytes.len() as u64,
-            usage: BufferUsages::COPY_SRC,
-            mapped_at_creation: true,
-        });
-        let buffer_slice = device_buffer.slice(..);
-        let _ = self.device.poll(wgpu::wgt::PollType::Wait);
-        buffer_slice.get_mapped_range_mut()[..bytes.len()].copy_from_slice(bytes);
-        device_buffer.unmap();
-        encoder.copy_buffer_to_buffer(
-            &device_buffer,
-            0,
-            &buffer,
-            0,
-            std::mem::size_of_val(&bytes) as BufferAddress,
-        ); //A mutable borrow to the command encoder is needed here in order to update uniforms
-        self.queue.submit(std::iter::once(encoder.finish()));
+    /// Scales the `delta` fed to each step; the step rate stays the same.
+    fn change_speed(&mut self, factor: f32) {
+        self.timestep.set_speed(self.timestep.speed() * factor);
+        println!("speed: {}x", self.timestep.speed());
     }
 
     fn update(&mut self) {
         let now = Instant::now();
-        let delta = now.duration_since(self.then).as_secs_f32();
+        let elapsed = now.duration_since(self.then).as_secs_f32();
         self.then = now;
+        self.gui.record_frame(elapsed);
+        if now.duration_since(self.last_shader_poll) >= SHADER_POLL_INTERVAL {
+            self.last_shader_poll = now;
+            self.reload_shaders();
+        }
+        if let Some(transition) = &mut self.transition {
+            let preset = transition.advance(elapsed);
+            self.backend.set_species(&preset.species);
+            self.backend.set_diffuse(preset.diffuse);
+            if transition.is_finished() {
+                self.transition = None;
+            }
+        }
 
-        // Time is used for shader RNG
-        self.time = self.time + delta;
+        if let Some((x, y)) = self.cursor_texel() {
+            let brush = Brush {
+                x,
+                y,
+                radius: BRUSH_RADIUS,
+                species: self.brush_species,
+                ..Brush::default()
+            };
+            if self.brushing {
+                let (tool, rate) = if self.modifiers.control_key() {
+                    (BrushTool::Relocate, RELOCATE_RATE)
+                } else if self.gravity_well {
+                    (BrushTool::Attract, ATTRACT_RATE)
+                } else {
+                    (BrushTool::Paint, PAINT_RATE)
+                };
+                self.backend.brush(&Brush {
+                    tool,
+                    amount: rate * elapsed,
+                    ..brush
+                });
+            }
+            if self.erasing {
+                self.backend.brush(&Brush {
+                    tool: BrushTool::Erase,
+                    amount: ERASE_RATE * elapsed,
+                    ..brush
+                });
+            }
+        }
+        // Shift paints attractant instead
+        if self.painting_repellent {
+            if let Some((x, y)) = self.cursor_texel() {
+                let sign = if self.modifiers.shift_key() {
+                    -1.0
+                } else {
+                    1.0
+                };
+                let amount = sign * REPELLENT_BRUSH_RATE * elapsed;
+                self.backend
+                    .paint_repellent(x, y, REPELLENT_BRUSH_RADIUS, amount);
+            }
+        }
+
+        let steps = self.timestep.advance(elapsed);
+        for _ in 0..steps {
+            self.backend.step(self.timestep.delta());
+        }
+        if let Some(recorder) = &mut self.recorder {
+            self.record_due += recorder.advance(steps as f32 * self.timestep.step_size());
+        }
+    }
 
-        let shader_param_data = ShaderParams {
-            numAgents: NUM_AGENTS as _,
-            width: SIM_WIDTH as _,
-            height: SIM_HEIGHT as _,
-            delta,
-            time: self.time,
+    /// Rebuilds the pipelines of any shader edited in `--shader-dir`. A shader
+    /// that fails to compile is reported and the running one kept.
+    fn reload_shaders(&mut self) {
+        let Some(watcher) = &mut self.shader_watcher else {
+            return;
         };
-        println!("delta: {}", shader_param_data.delta);
-        self.update_uniform_buffer(&self.shader_param_buffer, &shader_param_data);
+        for (name, source) in watcher.poll() {
+            let path = watcher.path(name);
+            let result = source.map_err(ShaderError::from).and_then(|source| {
+                if name == "scaling.wgsl" {
+                    check_wgsl(&path, &source)?;
+                    let module = create_shader_module(&self.device, "scaling_shader", &source)?;
+                    self.scaling_pipeline = catch_validation(&self.device, || {
+                        scaling_pipeline(
+                            &self.device,
+                            &self.scaling_pipeline_layout,
+                            &module,
+                            self.config.format,
+                        )
+                    })?;
+                    Ok(true)
+                } else {
+                    self.backend.reload_shader(name, &path, &source)
+                }
+            });
+            match result {
+                Ok(true) => println!("reloaded {}", path.display()),
+                Ok(false) => println!("{} is not used by the CPU backend", name),
+                Err(e) => eprintln!("error: {}: {}", path.display(), e),
+            }
+        }
+    }
+
+    /// The trail map position under the cursor, found by undoing the scaling
+    /// pass's projection.
+    fn cursor_texel(&self) -> Option<(f32, f32)> {
+        let cursor = self.cursor?;
+        let (window_width, window_height) = (self.size.width as f32, self.size.height as f32);
+        let projection = cgmath::Matrix4::from(get_projection_matrix(
+            window_width,
+            window_height,
+            self.sim_aspect,
+            true,
+        ));
+        let ndc = cgmath::vec4(
+            2.0 * cursor.x as f32 / window_width - 1.0,
+            1.0 - 2.0 * cursor.y as f32 / window_height,
+            0.0,
+            1.0,
+        );
+        let quad = projection.invert()? * ndc;
+        // The texture coordinates `scaling.wgsl` gives the quad
+        let u = quad.x * 0.5 + 0.5;
+        let v = 0.5 - quad.y * 0.5;
+        Some((u * self.sim_size[0], v * self.sim_size[1]))
     }
 
     fn draw(&mut self) -> Result<(), wgpu::SurfaceError> {
@@ -980,38 +1212,23 @@ impl<'a> State<'a> {
                 label: Some("Render Encoder"),
             });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &self.sim_texture_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(self.clear_color),
-                        store: wgpu::StoreOp::Store,
-                    },
-                    depth_slice: None,
-                })],
-                depth_stencil_attachment: None,
-                ..Default::default()
-            });
-            let mut encoder =
-                self.device
-                    .create_render_bundle_encoder(&wgpu::RenderBundleEncoderDescriptor {
-                        label: None,
-                        multiview: None,
-                        color_formats: &[Some(self.config.format)],
-                        depth_stencil: None,
-                        sample_count: 1,
-                    });
-            encoder.set_pipeline(&self.render_pipeline);
-            encoder.set_bind_group(0, &self.render_bind_group, &[]);
-            encoder.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            encoder.draw(0..VERTICES.len() as _, 0..1);
-            self.bundle = encoder.finish(&wgpu::RenderBundleDescriptor {
-                label: Some("main"),
-            });
-            render_pass.execute_bundles(std::iter::once(&self.bundle));
+        self.backend.draw(
+            &self.queue,
+            &mut encoder,
+            &self.sim_texture_view,
+            self.clear_color,
+        );
+        let mut record_result = Ok(());
+        if let Some(recorder) = &mut self.recorder {
+            if self.record_due > 0 {
+                record_result = recorder.capture(
+                    &self.device,
+                    &mut encoder,
+                    &self.sim_texture,
+                    self.record_due,
+                );
+                self.record_due = 0;
+            }
         }
 
         {
@@ -1032,70 +1249,98 @@ impl<'a> State<'a> {
 
             scaling_pass.set_pipeline(&self.scaling_pipeline);
             scaling_pass.set_bind_group(0, &self.scaled_texture_bind_group, &[]);
-            scaling_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            scaling_pass.set_vertex_buffer(0, self.backend.vertex_buffer().slice(..));
             scaling_pass.draw(0..VERTICES.len() as _, 0..1);
         }
 
+        let before = Controls {
+            species: self.backend.species().to_vec(),
+            diffuse: self.backend.diffuse(),
+            paused: self.timestep.is_paused(),
+        };
+        let mut controls = before.clone();
+        self.gui.draw(
+            &self.window,
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            &view,
+            &mut controls,
+        );
+        if controls != before {
+            self.transition = None;
+            self.backend.set_species(&controls.species);
+            self.backend.set_diffuse(controls.diffuse);
+            self.timestep.set_paused(controls.paused);
+        }
+
         self.queue.submit(std::iter::once(encoder.finish()));
         output.present();
 
-        Ok(())
-    }
-
-    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                label: Some("Command Encoder"),
-            });
-
-        {
-            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                label: Some("Compute Pass"),
-                ..Default::default()
-            });
-            compute_pass.set_pipeline(&self.compute_pipeline);
-            compute_pass.set_bind_group(0, &self.compute_bind_group, &[]);
-            compute_pass.dispatch_workgroups(NUM_AGENTS / AGENTS_PER_GROUP, 1, 1);
-        }
-        {
-            let mut compute_diffuse_pass =
-                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                    label: Some("Compute Diffuse Pass"),
-                    ..Default::default()
-                });
-            compute_diffuse_pass.set_pipeline(&self.compute_diffuse_pipeline);
-            compute_diffuse_pass.set_bind_group(0, &self.compute_diffuse_bind_group, &[]);
-            compute_diffuse_pass.dispatch_workgroups(
-                SIM_WIDTH / DIFFUSE_TILE_SIZE,
-                SIM_HEIGHT / DIFFUSE_TILE_SIZE,
-                1,
-            );
-        }
-        {
-            encoder.copy_texture_to_texture(
-                self.pong_texture.as_image_copy(),
-                self.ping_texture.as_image_copy(),
-                wgpu::Extent3d {
-                    width: SIM_WIDTH,
-                    height: SIM_HEIGHT,
-                    depth_or_array_layers: 1,
-                },
-            );
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = record_result.and_then(|()| recorder.submitted(&self.device)) {
+                eprintln!("error: recording stopped: {}", e);
+                self.recorder = None;
+            }
         }
-        // submit will accept anything that implements IntoIter
-        self.queue.submit(std::iter::once(encoder.finish()));
 
         Ok(())
     }
 }
+
+/// The pass that draws the colour pass's output scaled to fit the window.
+fn scaling_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        cache: None,
+        label: Some("scaling_pipeline"),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module,
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            entry_point: Some("vs_main"),
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+            }], // 2.  // Add the vertex buffer layout here
+        },
+        fragment: Some(wgpu::FragmentState {
+            module,
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        multiview: None,
+    })
+}
+
 fn get_projection_matrix(
     window_width: f32,
     window_height: f32,
+    sim_aspect: f32,
     center_crop: bool,
 ) -> [[f32; 4]; 4] {
     let window_aspect = window_width / window_height;
-    let sim_aspect = (SIM_WIDTH as f32) / (SIM_HEIGHT as f32);
 
     let (width, height) = if window_aspect > sim_aspect {
         if center_crop {
@@ -1151,7 +1396,9 @@ impl<'a> ApplicationHandler for SlimeSim<'a> {
         event: WindowEvent,
     ) {
         let state = self.state.as_mut().unwrap();
-        state.input(&event);
+        if !state.gui.on_window_event(&state.window, &event) {
+            state.input(&event);
+        }
         match event {
             WindowEvent::RedrawRequested => {
                 // state.then = Instant::now();
@@ -1165,15 +1412,6 @@ impl<'a> ApplicationHandler for SlimeSim<'a> {
                     Err(e) => eprintln!("{:?}", e),
                 }
                 state.update();
-                match state.render() {
-                    Ok(_) => {}
-                    // Reconfigure the surface if lost
-                    Err(wgpu::SurfaceError::Lost) => state.resize(state.size),
-                    // The system is out of memory, we should probably quit
-                    Err(wgpu::SurfaceError::OutOfMemory) => event_loop.exit(),
-                    // All other errors (Outdated, Timeout) should be resolved by the next frame
-                    Err(e) => eprintln!("{:?}", e),
-                }
                 state.window.request_redraw();
             }
 
@@ -1196,10 +1434,138 @@ impl<'a> ApplicationHandler for SlimeSim<'a> {
             _ => {}
         }
     }
+
+    fn exiting(&mut self, _event_loop: &ActiveEventLoop) {
+        if let Some(state) = &mut self.state {
+            if let Some(recorder) = state.recorder.take() {
+                finish_recording(recorder, &state.device);
+            }
+        }
+    }
 }
+fn run_headless(args: Args) {
+    let animation_frames = args.frames.div_ceil(args.every) as usize;
+    if args.export_gif.is_some() && (animation_frames == 0 || animation_frames < 2 * args.crossfade)
+    {
+        eprintln!(
+            "error: --crossfade {} needs at least {} animation frames, --frames {} --every {} gives {}",
+            args.crossfade,
+            (2 * args.crossfade).max(1),
+            args.frames,
+            args.every,
+            animation_frames
+        );
+        std::process::exit(1);
+    }
+    let checkpoint = load_checkpoint(&args);
+    let config = load_config(&args, checkpoint.as_ref());
+    let spawner = build_spawner(&config);
+    let obstacles = load_obstacles(&config);
+    let food = load_food(&config);
+    let repellent = load_repellent(&config);
+    let (device, queue) = pollster::block_on(headless::request_device());
+    exit_on_error(
+        config
+            .simulation
+            .check_limits(&device.limits(), &obstacles, &spawner),
+    );
+    let mut simulation = Simulation::new(
+        device, queue, &config, &spawner, &obstacles, &food, &repellent,
+    );
+    if let Some(checkpoint) = &checkpoint {
+        simulation.restore(checkpoint);
+    }
+    let capture = FrameCapture::new(&simulation, config.simulation.scale);
+    let (render_width, render_height) = config.simulation.render_size();
+    let mut recorder = start_recording(
+        &args,
+        simulation.device(),
+        render_width,
+        render_height,
+        headless::FRAME_FORMAT,
+    );
+    let mut animation = Vec::new();
+    if recorder.is_none() && args.export_gif.is_none() {
+        std::fs::create_dir_all(&args.out).expect("failed to create output directory");
+    }
+
+    let mut timestep = FixedTimestep::new(&config.simulation);
+    for frame in 0..args.frames {
+        let steps = timestep.advance(HEADLESS_DELTA);
+        for _ in 0..steps {
+            simulation.step(timestep.delta());
+        }
+        if let Some(recorder) = &mut recorder {
+            let due = recorder.advance(steps as f32 * timestep.step_size());
+            if due > 0 {
+                let device = simulation.device();
+                let mut encoder = device.create_command_encoder(&Default::default());
+                capture.render(&mut encoder);
+                exit_on_recording_error(recorder.capture(
+                    device,
+                    &mut encoder,
+                    capture.texture(),
+                    due,
+                ));
+                simulation.queue().submit(std::iter::once(encoder.finish()));
+                exit_on_recording_error(recorder.submitted(device));
+            }
+            continue;
+        }
+        if args.export_gif.is_some() {
+            if frame % args.every == 0 {
+                let image = exit_on_io_error(capture.capture(&simulation));
+                animation.push(export::downsample(&image, args.gif_width));
+            }
+            continue;
+        }
+        let path = args.out.join(format!("frame_{:05}.png", frame));
+        exit_on_io_error(capture.capture(&simulation))
+            .save(&path)
+            .unwrap_or_else(|e| panic!("failed to write {}: {}", path.display(), e));
+        println!("wrote {}", path.display());
+    }
+    if let Some(recorder) = recorder {
+        finish_recording(recorder, simulation.device());
+    }
+    if let Some(path) = &args.export_gif {
+        let frames = export::crossfade_loop(animation, args.crossfade);
+        let delay = Duration::from_secs_f32(HEADLESS_DELTA * args.every as f32);
+        match export::save_animation(path, &frames, delay) {
+            Ok(()) => println!("wrote {} frames to {}", frames.len(), path.display()),
+            Err(e) => {
+                eprintln!("error: {}: {}", path.display(), e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn exit_on_recording_error(result: std::io::Result<()>) {
+    if let Err(e) = result {
+        eprintln!("error: recording failed: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn exit_on_io_error<T>(result: std::io::Result<T>) -> T {
+    result.unwrap_or_else(|e| {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    })
+}
+
 fn main() {
     let args = Args::parse();
     env_logger::init();
+    if args.print_bindings {
+        print!("{}", load_bindings(&args).0.to_toml());
+        return;
+    }
+    if args.headless || args.export_gif.is_some() {
+        run_headless(args);
+        return;
+    }
     let event_loop = EventLoop::new().unwrap();
     let mut app = SlimeSim {
         args: Some(args),
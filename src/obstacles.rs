@@ -0,0 +1,139 @@
+//! Walls loaded from a mask image, for mazes and floor plans.
+//!
+//! Agents never step from open ground into a wall, trails do not diffuse
+//! through walls, and the colour passes draw the walls over the trail. The
+//! mask is kept at the resolution of its image and stretched over the whole
+//! map, the same way the spawn image is.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::ConfigError;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct ObstacleSettings {
+    /// Mask image whose bright pixels (above 50% luminance) are walls.
+    pub image: Option<PathBuf>,
+    /// Make the dark pixels of `image` the walls instead.
+    pub invert: bool,
+    /// Colour the walls are drawn in, blended over the trail by `colourA`.
+    pub colour_r: f32,
+    pub colour_g: f32,
+    pub colour_b: f32,
+    pub colour_a: f32,
+}
+
+impl Default for ObstacleSettings {
+    fn default() -> Self {
+        Self {
+            image: None,
+            invert: false,
+            colour_r: 0.25,
+            colour_g: 0.25,
+            colour_b: 0.3,
+            colour_a: 1.0,
+        }
+    }
+}
+
+/// One byte per mask pixel, 255 for walls and 0 for open ground, so it
+/// uploads straight into an `R8Unorm` texture.
+#[derive(Clone, Debug)]
+pub struct ObstacleMask {
+    pub width: u32,
+    pub height: u32,
+    pub walls: Vec<u8>,
+    /// [`ObstacleSettings`] colour the walls are drawn in, alpha last.
+    pub colour: [f32; 4],
+}
+
+impl ObstacleMask {
+    /// Loads the mask image if `settings` has one, and otherwise gives a mask
+    /// without walls.
+    pub fn new(settings: &ObstacleSettings) -> Result<Self, ConfigError> {
+        let mut mask = match &settings.image {
+            Some(path) => Self::load(path, settings.invert)?,
+            None => Self::open(),
+        };
+        mask.colour = [
+            settings.colour_r,
+            settings.colour_g,
+            settings.colour_b,
+            settings.colour_a,
+        ];
+        Ok(mask)
+    }
+
+    pub fn load(path: &Path, invert: bool) -> Result<Self, ConfigError> {
+        let image = image::open(path)
+            .map_err(|e| ConfigError::Image {
+                path: path.to_owned(),
+                error: e.to_string(),
+            })?
+            .into_luma8();
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            walls: image
+                .pixels()
+                .map(|p| if (p[0] >= 128) != invert { 255 } else { 0 })
+                .collect(),
+            colour: [0.0; 4],
+        })
+    }
+
+    /// A 1x1 mask without walls, as the passes still need a texture bound.
+    pub fn open() -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            walls: vec![0],
+            colour: [0.0; 4],
+        }
+    }
+
+    /// Mirrors `isWall` in the shaders: whether texel (`x`, `y`) of a
+    /// `map_width x map_height` trail map is a wall. Texels off the map never
+    /// are.
+    pub fn is_wall(&self, x: i32, y: i32, map_width: u32, map_height: u32) -> bool {
+        if x < 0 || y < 0 || x as u32 >= map_width || y as u32 >= map_height {
+            return false;
+        }
+        let mask_x = x as u32 * self.width / map_width;
+        let mask_y = y as u32 * self.height / map_height;
+        self.walls[(mask_y * self.width + mask_x) as usize] != 0
+    }
+
+    /// The mask as a sampled `R8Unorm` texture, bound as `Obstacles` by every
+    /// pass that needs it.
+    pub fn create_texture(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> wgpu::Texture {
+        let size = wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Obstacle Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            texture.as_image_copy(),
+            &self.walls,
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(self.width),
+                rows_per_image: None,
+            },
+            size,
+        );
+        texture
+    }
+}
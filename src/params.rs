@@ -0,0 +1,164 @@
+use serde::{Deserialize, Serialize};
+
+use crate::Config;
+
+#[repr(C)]
+#[derive(
+    Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable, Serialize, Deserialize,
+)]
+#[serde(default, deny_unknown_fields)]
+#[allow(non_snake_case)]
+pub struct SpeciesSettings {
+    pub moveSpeed: f32,
+    pub turnSpeed: f32,
+    pub sensorAngleDegrees: f32,
+    pub sensorOffsetDst: f32,
+    pub sensorSize: f32,
+    pub colourR: f32,
+    pub colourG: f32,
+    pub colourB: f32,
+    pub colourA: f32,
+    /// How strongly the repellent layer pushes this species away; negative
+    /// values attract it instead.
+    pub repellentSensitivity: f32,
+}
+
+impl Default for SpeciesSettings {
+    fn default() -> Self {
+        Self {
+            moveSpeed: 120.0,
+            turnSpeed: -4.0,
+            sensorAngleDegrees: 112.0,
+            sensorOffsetDst: 50.0,
+            sensorSize: 0.0,
+            colourR: 1.0,
+            colourG: 1.0,
+            colourB: 1.0,
+            colourA: 1.0,
+            repellentSensitivity: 1.0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[allow(non_snake_case)]
+pub struct ShaderParams {
+    pub numAgents: f32,
+    pub width: f32,
+    pub height: f32,
+    pub delta: f32,
+    /// Mixed into the `update` RNG together with `frame`, so a run only
+    /// depends on the seed and the sequence of timesteps.
+    pub seed: u32,
+    /// Steps taken so far.
+    pub frame: u32,
+    pub diffuseRate: f32,
+    pub decayRate: f32,
+    pub depositIntensity: f32,
+    /// What agents do at the edge of the map, a [`crate::BoundaryMode`].
+    pub boundaryMode: u32,
+}
+
+impl ShaderParams {
+    /// The parameters at the first step of a run of `config`. The seed is
+    /// `simulation.seed`, or 0 if there is none; a run with a drawn seed
+    /// takes it from its [`crate::Spawner`] instead.
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            numAgents: config.simulation.num_agents as _,
+            width: config.simulation.width as _,
+            height: config.simulation.height as _,
+            delta: 0.03,
+            seed: config.simulation.seed.unwrap_or(0),
+            frame: 0,
+            diffuseRate: config.diffuse.diffuse_rate,
+            decayRate: config.diffuse.decay_rate,
+            depositIntensity: config.diffuse.deposit_intensity,
+            boundaryMode: config.simulation.boundary as u32,
+        }
+    }
+}
+
+/// The range of agents one `update` dispatch works on.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[allow(non_snake_case)]
+pub struct AgentChunk {
+    pub firstAgent: u32,
+    pub numAgents: u32,
+}
+
+/// Mirrors `FoodParams` in `diffuse.wgsl`, see [`crate::FoodSettings`].
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[allow(non_snake_case)]
+pub struct FoodParams {
+    pub amount: f32,
+    pub depletion: f32,
+    /// The trail layer food is fed into, or [`crate::food::ALL_SPECIES`].
+    pub species: u32,
+}
+
+/// Mirrors `SpawnParams` in `spawn.wgsl`; `mode` is a [`crate::SpawnMode`].
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[allow(non_snake_case)]
+pub struct SpawnParams {
+    pub mode: u32,
+    pub seed: u32,
+    pub numSpecies: u32,
+    pub radius: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[allow(non_snake_case)]
+pub struct RenderParams {
+    pub width: f32,
+    pub height: f32,
+    pub scaleDownFactor: f32,
+    /// [`crate::ObstacleMask::colour`]
+    pub obstacleColourR: f32,
+    pub obstacleColourG: f32,
+    pub obstacleColourB: f32,
+    pub obstacleColourA: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[allow(non_snake_case)]
+pub struct Agent {
+    pub posX: f32,
+    pub posY: f32,
+    pub angle: f32,
+    /// Index into the species buffer, and the trail layer this agent deposits
+    /// into.
+    pub speciesIndex: u32,
+    // intensity: f32,
+}
+
+/// Mirrors `BrushParams` in `brush.wgsl`, see [`crate::Brush`].
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+#[allow(non_snake_case)]
+pub struct BrushParams {
+    /// A [`crate::BrushTool`].
+    pub tool: u32,
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+    pub amount: f32,
+    /// The trail layer painted into, or [`crate::food::ALL_SPECIES`].
+    pub species: u32,
+    /// The agents [`crate::BrushTool::Relocate`] moves under the brush, wrapping
+    /// around the end of the population.
+    pub firstRelocated: u32,
+    pub numRelocated: u32,
+    pub numAgents: u32,
+    pub seed: u32,
+    pub width: f32,
+    pub height: f32,
+}
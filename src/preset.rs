@@ -0,0 +1,148 @@
+//! The species and diffusion settings of a run, saved on their own so they
+//! can be swapped in while it keeps going.
+//!
+//! A preset file is a config file with only `[[species]]` and `[diffuse]`
+//! sections, so any config file loads as a preset too. A directory of them
+//! makes a [`library`] the viewer cycles through, morphing from one to the
+//! next with a [`Transition`].
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Config, ConfigError, DiffuseSettings, SpeciesSettings};
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Preset {
+    pub species: Vec<SpeciesSettings>,
+    pub diffuse: DiffuseSettings,
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        let config = Config::default();
+        Self {
+            species: config.species,
+            diffuse: config.diffuse,
+        }
+    }
+}
+
+impl Preset {
+    /// Reads the species and diffusion settings out of a config file, see
+    /// [`Config::load`].
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let config = Config::load(path)?;
+        Ok(Self {
+            species: config.species,
+            diffuse: config.diffuse,
+        })
+    }
+
+    /// Writes JSON if `path` has a `.json` extension and TOML otherwise.
+    pub fn save(&self, path: &Path) -> Result<(), ConfigError> {
+        let text = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => {
+                serde_json::to_string_pretty(self).map_err(|e| ConfigError::Parse(e.to_string()))?
+            }
+            _ => toml::to_string(self).map_err(|e| ConfigError::Parse(e.to_string()))?,
+        };
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+
+    /// Species settings for a simulation with `count` species, from a preset
+    /// with at least one. Presets with fewer species repeat their last one,
+    /// and extra ones are left out, as the number of trail layers can't
+    /// change.
+    pub fn species_for(&self, count: usize) -> Vec<SpeciesSettings> {
+        (0..count)
+            .map(|i| self.species[i.min(self.species.len() - 1)])
+            .collect()
+    }
+
+    /// The settings `t` of the way from `self` to `other`, which has as many
+    /// species. `sensorSize` is rounded, as agents sense whole texels.
+    pub fn lerp(&self, other: &Preset, t: f32) -> Preset {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        let species = self
+            .species
+            .iter()
+            .zip(&other.species)
+            .map(|(a, b)| SpeciesSettings {
+                moveSpeed: mix(a.moveSpeed, b.moveSpeed),
+                turnSpeed: mix(a.turnSpeed, b.turnSpeed),
+                sensorAngleDegrees: mix(a.sensorAngleDegrees, b.sensorAngleDegrees),
+                sensorOffsetDst: mix(a.sensorOffsetDst, b.sensorOffsetDst),
+                sensorSize: mix(a.sensorSize, b.sensorSize).round(),
+                colourR: mix(a.colourR, b.colourR),
+                colourG: mix(a.colourG, b.colourG),
+                colourB: mix(a.colourB, b.colourB),
+                colourA: mix(a.colourA, b.colourA),
+                repellentSensitivity: mix(a.repellentSensitivity, b.repellentSensitivity),
+            })
+            .collect();
+        let (a, b) = (&self.diffuse, &other.diffuse);
+        Preset {
+            species,
+            diffuse: DiffuseSettings {
+                diffuse_rate: mix(a.diffuse_rate, b.diffuse_rate),
+                decay_rate: mix(a.decay_rate, b.decay_rate),
+                deposit_intensity: mix(a.deposit_intensity, b.deposit_intensity),
+            },
+        }
+    }
+}
+
+/// The preset files in `dir`, every `.toml` and `.json` sorted by name. Each
+/// is named after its file stem.
+pub fn library(dir: &Path) -> Result<Vec<PathBuf>, ConfigError> {
+    let mut paths = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        if path.is_file() && matches!(extension, Some("toml" | "json")) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+/// Moves the species and diffusion settings from one preset to another over
+/// `duration` seconds of wall-clock time.
+#[derive(Clone, Debug)]
+pub struct Transition {
+    from: Preset,
+    to: Preset,
+    duration: f32,
+    elapsed: f32,
+}
+
+impl Transition {
+    /// `to` needs as many species as `from`, see [`Preset::species_for`]. A
+    /// `duration` of 0 goes straight to `to`.
+    pub fn new(from: Preset, to: Preset, duration: f32) -> Self {
+        Self {
+            from,
+            to,
+            duration,
+            elapsed: 0.0,
+        }
+    }
+
+    /// Moves `seconds` further along and returns the settings to apply now.
+    pub fn advance(&mut self, seconds: f32) -> Preset {
+        self.elapsed += seconds;
+        if self.is_finished() {
+            self.to.clone()
+        } else {
+            self.from.lerp(&self.to, self.elapsed / self.duration)
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
@@ -0,0 +1,479 @@
+//! Video recording of the rendered frames.
+//!
+//! [`Recorder`] copies frames into a small pool of staging buffers and maps
+//! them with `map_async`, so reading a frame back overlaps with rendering the
+//! next ones instead of stalling the queue. Mapped frames are converted and
+//! written on a thread of their own, in the order they were captured, either
+//! to a Y4M file or, as raw RGBA, to the stdin of a child process such as
+//! ffmpeg. Rendering only waits for the writer once every buffer is queued up
+//! behind it.
+//!
+//! The video runs at a fixed frame rate in simulated time: one second of video
+//! is `stepRate` simulation steps however fast the frames were actually
+//! rendered, with frames repeated or skipped to match.
+
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, OnceLock,
+    },
+    thread::JoinHandle,
+};
+
+use crate::simulation::padded_bytes_per_row;
+
+/// Staging buffers in flight at once. Capturing with all of them busy waits
+/// for the oldest.
+pub const STAGING_BUFFERS: usize = 4;
+
+#[derive(Clone, Debug)]
+pub enum RecordTarget {
+    /// An uncompressed 4:4:4 Y4M file.
+    Y4m(PathBuf),
+    /// A shell command that reads raw RGBA frames from stdin, e.g.
+    /// `ffmpeg -f rawvideo -pix_fmt rgba -s 1920x1080 -r 60 -i - out.mp4`.
+    Pipe(String),
+}
+
+enum Sink {
+    Y4m(BufWriter<File>),
+    Pipe {
+        child: Child,
+        stdin: BufWriter<std::process::ChildStdin>,
+    },
+}
+
+struct PendingFrame {
+    buffer: wgpu::Buffer,
+    /// How many times the frame goes into the video.
+    repeat: u32,
+    /// `None` until `map_async` has been called after the copy was submitted,
+    /// then set to the outcome by its callback.
+    mapped: Option<Arc<OnceLock<Result<(), wgpu::BufferAsyncError>>>>,
+}
+
+/// A mapped staging buffer on its way to the writer thread.
+struct MappedFrame {
+    buffer: wgpu::Buffer,
+    repeat: u32,
+}
+
+/// Turns simulated time into a count of video frames at a fixed rate.
+struct FramePacing {
+    frame_time: f32,
+    accumulator: f32,
+}
+
+pub struct Recorder {
+    width: u32,
+    height: u32,
+    padded_bytes_per_row: u32,
+    pacing: FramePacing,
+    free_buffers: Vec<wgpu::Buffer>,
+    pending: VecDeque<PendingFrame>,
+    /// Mapped frames for the writer thread. Only [`STAGING_BUFFERS`] exist, so
+    /// this never holds more.
+    frames: Option<SyncSender<MappedFrame>>,
+    /// Buffers the writer thread has unmapped, to be reused.
+    written: Receiver<wgpu::Buffer>,
+    /// Converts and writes frames, so a slow encoder doesn't hold up
+    /// rendering. Returns the number of video frames written.
+    writer: Option<JoinHandle<io::Result<u64>>>,
+}
+
+/// The writer thread's end of a [`Recorder`].
+struct FrameWriter {
+    sink: Sink,
+    width: u32,
+    padded_bytes_per_row: u32,
+    /// The source is BGRA rather than RGBA.
+    swap_red_blue: bool,
+    frames_written: u64,
+    /// Converted frame, reused between frames.
+    scratch: Vec<u8>,
+}
+
+impl Recorder {
+    /// Starts recording `width x height` frames of `format`, which must be an
+    /// 8-bit RGBA or BGRA format, at `fps` frames per simulated second.
+    pub fn new(
+        device: &wgpu::Device,
+        target: &RecordTarget,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        fps: u32,
+    ) -> io::Result<Self> {
+        use wgpu::TextureFormat::*;
+        let swap_red_blue = match format {
+            Rgba8Unorm | Rgba8UnormSrgb => false,
+            Bgra8Unorm | Bgra8UnormSrgb => true,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("cannot record frames of {:?}", format),
+                ))
+            }
+        };
+        let sink = match target {
+            RecordTarget::Y4m(path) => {
+                let mut file = BufWriter::new(File::create(path)?);
+                writeln!(
+                    file,
+                    "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                    width, height, fps
+                )?;
+                Sink::Y4m(file)
+            }
+            RecordTarget::Pipe(command) => {
+                let mut child = shell(command).stdin(Stdio::piped()).spawn()?;
+                let stdin = BufWriter::new(child.stdin.take().expect("stdin is piped"));
+                Sink::Pipe { child, stdin }
+            }
+        };
+        let padded_bytes_per_row = padded_bytes_per_row(width * 4);
+        let free_buffers = (0..STAGING_BUFFERS)
+            .map(|_| {
+                device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Recording Staging Buffer"),
+                    size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+                    usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                    mapped_at_creation: false,
+                })
+            })
+            .collect();
+        let (frames, frames_in) = mpsc::sync_channel(STAGING_BUFFERS);
+        let (written_out, written) = mpsc::channel();
+        let frame_writer = FrameWriter {
+            sink,
+            width,
+            padded_bytes_per_row,
+            swap_red_blue,
+            frames_written: 0,
+            scratch: Vec::new(),
+        };
+        let writer = std::thread::Builder::new()
+            .name("recorder".to_owned())
+            .spawn(move || frame_writer.run(frames_in, written_out))?;
+        Ok(Self {
+            width,
+            height,
+            padded_bytes_per_row,
+            pacing: FramePacing::new(fps),
+            free_buffers,
+            pending: VecDeque::new(),
+            frames: Some(frames),
+            written,
+            writer: Some(writer),
+        })
+    }
+
+    /// Banks `elapsed` simulated seconds and returns how many video frames
+    /// are now due.
+    pub fn advance(&mut self, elapsed: f32) -> u32 {
+        self.pacing.advance(elapsed)
+    }
+
+    /// Encodes a copy of `texture` into a staging buffer, to go into the video
+    /// `repeat` times. Call [`Recorder::submitted`] once `encoder` has been
+    /// submitted.
+    pub fn capture(
+        &mut self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        texture: &wgpu::Texture,
+        repeat: u32,
+    ) -> io::Result<()> {
+        let buffer = self.free_buffer(device)?;
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: wgpu::TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(self.padded_bytes_per_row),
+                    rows_per_image: Some(self.height),
+                },
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.pending.push_back(PendingFrame {
+            buffer,
+            repeat,
+            mapped: None,
+        });
+        Ok(())
+    }
+
+    /// Starts mapping the frames captured since the last call and hands the
+    /// ones that have finished to the writer thread, without blocking. A frame
+    /// that could not be mapped, e.g. because the device was lost, is an
+    /// error, as is anything the writer thread ran into.
+    pub fn submitted(&mut self, device: &wgpu::Device) -> io::Result<()> {
+        for frame in self
+            .pending
+            .iter_mut()
+            .filter(|frame| frame.mapped.is_none())
+        {
+            let mapped = Arc::new(OnceLock::new());
+            let callback_mapped = mapped.clone();
+            frame
+                .buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let _ = callback_mapped.set(result);
+                });
+            frame.mapped = Some(mapped);
+        }
+        let _ = device.poll(wgpu::wgt::PollType::Poll);
+        while self.pending.front().is_some_and(PendingFrame::is_ready) {
+            let frame = self.pending.pop_front().unwrap();
+            self.send(frame)?;
+        }
+        self.free_buffers.extend(self.written.try_iter());
+        Ok(())
+    }
+
+    /// Waits for every captured frame to be written, then closes the file or
+    /// pipe and returns the number of video frames written.
+    pub fn finish(mut self, device: &wgpu::Device) -> io::Result<u64> {
+        self.submitted(device)?;
+        while !self.pending.is_empty() {
+            self.send_oldest(device)?;
+        }
+        // Closing the channel lets the writer thread finish up
+        self.frames = None;
+        self.join_writer()
+    }
+
+    /// A staging buffer nothing is using, waiting for the oldest captured
+    /// frame to be mapped and written if every one is busy.
+    fn free_buffer(&mut self, device: &wgpu::Device) -> io::Result<wgpu::Buffer> {
+        if let Some(buffer) = self.free_buffers.pop() {
+            return Ok(buffer);
+        }
+        if let Ok(buffer) = self.written.try_recv() {
+            return Ok(buffer);
+        }
+        if !self.pending.is_empty() {
+            self.send_oldest(device)?;
+        }
+        match self.written.recv() {
+            Ok(buffer) => Ok(buffer),
+            Err(_) => Err(self.writer_stopped()),
+        }
+    }
+
+    /// Blocks until the oldest captured frame is mapped and sends it to the
+    /// writer thread.
+    fn send_oldest(&mut self, device: &wgpu::Device) -> io::Result<()> {
+        let frame = self
+            .pending
+            .pop_front()
+            .expect("a frame is in flight whenever no buffer is free");
+        assert!(frame.mapped.is_some(), "captured frame was never submitted");
+        while !frame.is_ready() {
+            device
+                .poll(wgpu::PollType::wait_indefinitely())
+                .map_err(io::Error::other)?;
+        }
+        self.send(frame)
+    }
+
+    fn send(&mut self, frame: PendingFrame) -> io::Result<()> {
+        if let Some(Err(e)) = frame.mapped.as_deref().and_then(OnceLock::get) {
+            return Err(io::Error::other(format!(
+                "could not read back a recorded frame: {}",
+                e
+            )));
+        }
+        let frames = self.frames.as_ref().expect("only closed by finish");
+        let mapped = MappedFrame {
+            buffer: frame.buffer,
+            repeat: frame.repeat,
+        };
+        if frames.send(mapped).is_err() {
+            return Err(self.writer_stopped());
+        }
+        Ok(())
+    }
+
+    /// The error that stopped the writer thread, which hangs up on the
+    /// channels when it gives up.
+    fn writer_stopped(&mut self) -> io::Error {
+        match self.join_writer() {
+            Ok(_) => io::Error::other("recording stopped"),
+            Err(e) => e,
+        }
+    }
+
+    fn join_writer(&mut self) -> io::Result<u64> {
+        let Some(writer) = self.writer.take() else {
+            return Err(io::Error::other("recording stopped"));
+        };
+        writer
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+}
+
+impl PendingFrame {
+    /// Whether mapping has finished, successfully or not.
+    fn is_ready(&self) -> bool {
+        self.mapped
+            .as_ref()
+            .is_some_and(|mapped| mapped.get().is_some())
+    }
+}
+
+impl FrameWriter {
+    /// Writes frames until the [`Recorder`] closes the channel, handing each
+    /// buffer back through `written`, then closes the file or pipe.
+    fn run(
+        mut self,
+        frames: Receiver<MappedFrame>,
+        written: mpsc::Sender<wgpu::Buffer>,
+    ) -> io::Result<u64> {
+        for frame in frames {
+            self.write(&frame)?;
+            // The recorder may already be gone after an error of its own
+            let _ = written.send(frame.buffer);
+        }
+        match self.sink {
+            Sink::Y4m(mut file) => file.flush()?,
+            Sink::Pipe { mut child, stdin } => {
+                // Closing stdin tells the child the stream has ended
+                drop(stdin.into_inner().map_err(|e| e.into_error())?);
+                let status = child.wait()?;
+                if !status.success() {
+                    return Err(io::Error::other(format!(
+                        "recording command exited with {}",
+                        status
+                    )));
+                }
+            }
+        }
+        Ok(self.frames_written)
+    }
+
+    fn write(&mut self, frame: &MappedFrame) -> io::Result<()> {
+        {
+            let mapped = frame.buffer.slice(..).get_mapped_range();
+            self.scratch.clear();
+            for row in mapped.chunks(self.padded_bytes_per_row as usize) {
+                for pixel in row[..(self.width * 4) as usize].chunks_exact(4) {
+                    if self.swap_red_blue {
+                        self.scratch
+                            .extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+                    } else {
+                        self.scratch.extend_from_slice(pixel);
+                    }
+                }
+            }
+        }
+        frame.buffer.unmap();
+
+        for _ in 0..frame.repeat {
+            match &mut self.sink {
+                Sink::Y4m(file) => {
+                    file.write_all(b"FRAME\n")?;
+                    write_yuv444(file, &self.scratch)?;
+                }
+                Sink::Pipe { stdin, .. } => stdin.write_all(&self.scratch)?,
+            }
+            self.frames_written += 1;
+        }
+        Ok(())
+    }
+}
+
+impl FramePacing {
+    fn new(fps: u32) -> Self {
+        Self {
+            frame_time: 1.0 / fps as f32,
+            accumulator: 0.0,
+        }
+    }
+
+    fn advance(&mut self, elapsed: f32) -> u32 {
+        self.accumulator += elapsed;
+        let due = (self.accumulator / self.frame_time) as u32;
+        self.accumulator -= due as f32 * self.frame_time;
+        due
+    }
+}
+
+fn shell(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+/// Writes RGBA pixels as full-size Y, Cb and Cr planes, using studio-swing
+/// BT.601 as Y4M players expect.
+fn write_yuv444(writer: &mut impl Write, rgba: &[u8]) -> io::Result<()> {
+    let pixels = || {
+        rgba.chunks_exact(4).map(|p| {
+            (
+                p[0] as f32 / 255.0,
+                p[1] as f32 / 255.0,
+                p[2] as f32 / 255.0,
+            )
+        })
+    };
+    let plane = |f: fn(f32, f32, f32) -> f32| -> Vec<u8> {
+        pixels()
+            .map(|(r, g, b)| f(r, g, b).round().clamp(0.0, 255.0) as u8)
+            .collect()
+    };
+    writer.write_all(&plane(|r, g, b| {
+        16.0 + 65.481 * r + 128.553 * g + 24.966 * b
+    }))?;
+    writer.write_all(&plane(|r, g, b| {
+        128.0 - 37.797 * r - 74.203 * g + 112.0 * b
+    }))?;
+    writer.write_all(&plane(|r, g, b| {
+        128.0 + 112.0 * r - 93.786 * g - 18.214 * b
+    }))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pacing_banks_the_remainder() {
+        let mut pacing = FramePacing::new(4);
+        assert_eq!(pacing.advance(0.125), 0);
+        assert_eq!(pacing.advance(0.125), 1);
+        assert_eq!(pacing.advance(0.0625), 0);
+        // A long frame is made up for with repeats, keeping the remainder
+        assert_eq!(pacing.advance(1.0), 4);
+        assert_eq!(pacing.advance(0.1875), 1);
+        assert_eq!(pacing.advance(0.0), 0);
+    }
+
+    #[test]
+    fn yuv444_is_planar_studio_swing() {
+        let rgba = [255, 255, 255, 255, 0, 0, 0, 255, 255, 0, 0, 255];
+        let mut yuv = Vec::new();
+        write_yuv444(&mut yuv, &rgba).unwrap();
+        assert_eq!(yuv, [235, 16, 81, 128, 128, 90, 128, 128, 240]);
+    }
+}
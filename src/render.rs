@@ -0,0 +1,600 @@
+use std::path::Path;
+
+use wgpu::{util::DeviceExt, BindGroup};
+
+use crate::{
+    cpu::{CpuSimulation, TrailMap},
+    hot_reload::{catch_validation, check_wgsl, create_shader_module, ShaderError},
+    RenderParams, Simulation, SpeciesSettings, MAX_SPECIES,
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 3],
+}
+
+pub const VERTICES: &[Vertex] = &[
+    Vertex {
+        position: [-1.0, 1.0, 0.0],
+    },
+    Vertex {
+        position: [-1.0, -1.0, 0.0],
+    },
+    Vertex {
+        position: [1.0, -1.0, 0.0],
+    },
+    Vertex {
+        position: [1.0, -1.0, 0.0],
+    },
+    Vertex {
+        position: [1.0, 1.0, 0.0],
+    },
+    Vertex {
+        position: [-1.0, 1.0, 0.0],
+    },
+];
+
+/// Size of the image a colour pass with `scale` renders a `width x height`
+/// trail map into.
+pub fn render_size(width: u32, height: u32, scale: f32) -> (u32, u32) {
+    let scaled = |size: u32| ((size as f32 / scale).ceil() as u32).max(1);
+    (scaled(width), scaled(height))
+}
+
+/// The `shader.wgsl` colour pass, which turns the raw trail map of a
+/// [`Simulation`] into a displayable image of `format`, reading `scale` trail
+/// texels per pixel (see [`crate::SimulationConfig::scale`]), and draws its
+/// walls on top.
+pub struct TrailRenderer {
+    render_pipeline: wgpu::RenderPipeline,
+    render_pipeline_layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    render_bind_group: BindGroup,
+    vertex_buffer: wgpu::Buffer,
+}
+
+impl TrailRenderer {
+    pub fn new(simulation: &Simulation, format: wgpu::TextureFormat, scale: f32) -> Self {
+        let device = simulation.device();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Render Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/shader.wgsl").into()),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor::default());
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let [obstacle_r, obstacle_g, obstacle_b, obstacle_a] = simulation.obstacle_colour();
+        let render_param_data = RenderParams {
+            width: simulation.width() as _,
+            height: simulation.height() as _,
+            scaleDownFactor: scale,
+            obstacleColourR: obstacle_r,
+            obstacleColourG: obstacle_g,
+            obstacleColourB: obstacle_b,
+            obstacleColourA: obstacle_a,
+        };
+        let render_param_slice = &[render_param_data];
+        let render_param_slice: &[u8] = bytemuck::cast_slice(render_param_slice);
+        let render_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    // Texture Sampler
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::NonFiltering),
+                        count: None,
+                    },
+                    // Storage Texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::ReadOnly,
+                            format: wgpu::TextureFormat::Rgba32Float,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                        },
+                        count: None,
+                    },
+                    // Render Params Buffer
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(render_param_slice.len() as _),
+                        },
+                        count: None,
+                    },
+                    // Species Parameter Buffer
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<
+                                SpeciesSettings,
+                            >()
+                                as _),
+                        },
+                        count: None,
+                    },
+                    // Obstacle Texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                ],
+                label: None,
+            });
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[&render_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let render_pipeline = render_pipeline(device, &render_pipeline_layout, &shader, format);
+        let render_param_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Render Parameter Buffer"),
+            contents: bytemuck::cast_slice(&[render_param_data]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST, // | wgpu::BufferUsages::MAP_WRITE,
+        });
+        let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &render_pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(
+                        &simulation
+                            .trail_texture()
+                            .create_view(&wgpu::TextureViewDescriptor {
+                                dimension: Some(wgpu::TextureViewDimension::D2Array),
+                                ..Default::default()
+                            }),
+                    ),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: render_param_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: simulation.species_buffer().as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(
+                        &simulation
+                            .obstacle_texture()
+                            .create_view(&Default::default()),
+                    ),
+                },
+            ],
+            label: None,
+        });
+
+        Self {
+            render_pipeline,
+            render_pipeline_layout,
+            format,
+            render_bind_group,
+            vertex_buffer,
+        }
+    }
+
+    /// Rebuilds the colour pass from a new `shader.wgsl`, keeping the old one
+    /// if `source` doesn't compile or no longer fits the bindings.
+    pub fn reload_shader(
+        &mut self,
+        device: &wgpu::Device,
+        path: &Path,
+        source: &str,
+    ) -> Result<(), ShaderError> {
+        check_wgsl(path, source)?;
+        let module = create_shader_module(device, "Render Shader", source)?;
+        self.render_pipeline = catch_validation(device, || {
+            render_pipeline(device, &self.render_pipeline_layout, &module, self.format)
+        })?;
+        Ok(())
+    }
+
+    /// Full-screen quad shared with any pass that draws over the whole target.
+    pub fn vertex_buffer(&self) -> &wgpu::Buffer {
+        &self.vertex_buffer
+    }
+
+    /// Records the colour pass into `encoder`, clearing `view` to `clear_color`
+    /// first. `view` is expected to be [`crate::SimulationConfig::render_size`].
+    pub fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        clear_color: wgpu::Color,
+    ) {
+        draw_fullscreen(
+            encoder,
+            view,
+            clear_color,
+            &self.render_pipeline,
+            &self.render_bind_group,
+            &self.vertex_buffer,
+        );
+    }
+}
+
+/// The `shader.wgsl` pipeline of a [`TrailRenderer`].
+fn render_pipeline(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+) -> wgpu::RenderPipeline {
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        cache: None,
+        label: Some("Render Pipeline"),
+        layout: Some(layout),
+        multiview: None,
+        vertex: wgpu::VertexState {
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            module,
+            entry_point: Some("vs_main"), // 1.
+            buffers: &[wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+            }], // 2.
+        },
+        fragment: Some(wgpu::FragmentState {
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            // 3.
+            module,
+            entry_point: Some("fs_main"),
+            targets: &[Some(wgpu::ColorTargetState {
+                // 4.
+                format,
+                blend: Some(wgpu::BlendState::REPLACE),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleList, // 1.
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw, // 2.
+            cull_mode: Some(wgpu::Face::Back),
+            // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+            polygon_mode: wgpu::PolygonMode::Fill,
+            // Requires Features::DEPTH_CLAMPING
+            unclipped_depth: false,
+            // Requires Features::CONSERVATIVE_RASTERIZATION
+            conservative: false,
+        },
+        depth_stencil: None, // 1.
+        multisample: wgpu::MultisampleState::default(), // multisample: wgpu::MultisampleState {
+                             //     count: SAMPLE_COUNT,              // 2.
+                             //     mask: !0,                         // 3.
+                             //     alpha_to_coverage_enabled: false, // 4.
+                             // },
+    })
+}
+
+/// Mirrors `SpeciesColours` in `present.wgsl`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[allow(non_snake_case)]
+struct SpeciesColours {
+    numSpecies: u32,
+    _padding: [u32; 3],
+    colours: [[f32; 4]; MAX_SPECIES],
+}
+
+/// The colour pass for [`CpuSimulation`]: the trail map is uploaded into a
+/// plain `R32Float` array texture each frame and tonemapped like
+/// [`TrailRenderer`] does, so it only needs a device with baseline features.
+pub struct CpuTrailRenderer {
+    render_pipeline: wgpu::RenderPipeline,
+    render_bind_group: BindGroup,
+    vertex_buffer: wgpu::Buffer,
+    trail_texture: wgpu::Texture,
+    species_colour_buffer: wgpu::Buffer,
+}
+
+impl CpuTrailRenderer {
+    /// Sized for the trail map of `simulation`, which later calls to
+    /// [`CpuTrailRenderer::upload`] must match, and drawing its walls.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        simulation: &CpuSimulation,
+        format: wgpu::TextureFormat,
+        scale: f32,
+    ) -> Self {
+        let TrailMap {
+            width,
+            height,
+            layers,
+            ..
+        } = *simulation.trail_map();
+        let obstacles = simulation.obstacles();
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Present Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/present.wgsl").into()),
+        });
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let trail_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("CPU Trail Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R32Float,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[wgpu::TextureFormat::R32Float],
+        });
+        let render_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    // Trail Texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // Render Params Buffer
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                std::mem::size_of::<RenderParams>() as _,
+                            ),
+                        },
+                        count: None,
+                    },
+                    // Species Colour Buffer
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(std::mem::size_of::<
+                                SpeciesColours,
+                            >()
+                                as _),
+                        },
+                        count: None,
+                    },
+                    // Obstacle Texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                ],
+                label: None,
+            });
+        let render_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Present Pipeline Layout"),
+                bind_group_layouts: &[&render_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            cache: None,
+            label: Some("Present Pipeline"),
+            layout: Some(&render_pipeline_layout),
+            multiview: None,
+            vertex: wgpu::VertexState {
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Vertex,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                module: &shader,
+                entry_point: Some("fs_main"),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+        });
+        let render_param_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Present Parameter Buffer"),
+            contents: bytemuck::cast_slice(&[RenderParams {
+                width: width as _,
+                height: height as _,
+                scaleDownFactor: scale,
+                obstacleColourR: obstacles.colour[0],
+                obstacleColourG: obstacles.colour[1],
+                obstacleColourB: obstacles.colour[2],
+                obstacleColourA: obstacles.colour[3],
+            }]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let obstacle_texture = obstacles.create_texture(device, queue);
+        let species_colour_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Species Colour Buffer"),
+            size: std::mem::size_of::<SpeciesColours>() as _,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &render_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&trail_texture.create_view(
+                        &wgpu::TextureViewDescriptor {
+                            dimension: Some(wgpu::TextureViewDimension::D2Array),
+                            ..Default::default()
+                        },
+                    )),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: render_param_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: species_colour_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(
+                        &obstacle_texture.create_view(&Default::default()),
+                    ),
+                },
+            ],
+            label: None,
+        });
+
+        Self {
+            render_pipeline,
+            render_bind_group,
+            vertex_buffer,
+            trail_texture,
+            species_colour_buffer,
+        }
+    }
+
+    pub fn vertex_buffer(&self) -> &wgpu::Buffer {
+        &self.vertex_buffer
+    }
+
+    /// Copies the sensed channel of every layer of `trail_map` into the GPU
+    /// texture, along with the colour each layer is tinted with.
+    pub fn upload(&self, queue: &wgpu::Queue, trail_map: &TrailMap, species: &[SpeciesSettings]) {
+        let mut species_colours = SpeciesColours {
+            numSpecies: species.len().min(MAX_SPECIES) as u32,
+            _padding: [0; 3],
+            colours: [[0.0; 4]; MAX_SPECIES],
+        };
+        for (colour, species) in species_colours.colours.iter_mut().zip(species) {
+            *colour = [
+                species.colourR,
+                species.colourG,
+                species.colourB,
+                species.colourA,
+            ];
+        }
+        queue.write_buffer(
+            &self.species_colour_buffer,
+            0,
+            bytemuck::bytes_of(&species_colours),
+        );
+
+        queue.write_texture(
+            self.trail_texture.as_image_copy(),
+            bytemuck::cast_slice(&trail_map.trail),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(trail_map.width * 4),
+                rows_per_image: Some(trail_map.height),
+            },
+            wgpu::Extent3d {
+                width: trail_map.width,
+                height: trail_map.height,
+                depth_or_array_layers: trail_map.layers,
+            },
+        );
+    }
+
+    /// Same contract as [`TrailRenderer::draw`], using the last uploaded trail map.
+    pub fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        clear_color: wgpu::Color,
+    ) {
+        draw_fullscreen(
+            encoder,
+            view,
+            clear_color,
+            &self.render_pipeline,
+            &self.render_bind_group,
+            &self.vertex_buffer,
+        );
+    }
+}
+
+fn draw_fullscreen(
+    encoder: &mut wgpu::CommandEncoder,
+    view: &wgpu::TextureView,
+    clear_color: wgpu::Color,
+    pipeline: &wgpu::RenderPipeline,
+    bind_group: &BindGroup,
+    vertex_buffer: &wgpu::Buffer,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Render Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(clear_color),
+                store: wgpu::StoreOp::Store,
+            },
+            depth_slice: None,
+        })],
+        depth_stencil_attachment: None,
+        ..Default::default()
+    });
+    render_pass.set_pipeline(pipeline);
+    render_pass.set_bind_group(0, bind_group, &[]);
+    render_pass.set_vertex_buffer(0, vertex_buffer.slice(..));
+    render_pass.draw(0..VERTICES.len() as _, 0..1);
+}
@@ -0,0 +1,113 @@
+//! A signed field that pushes agents away, for light-avoidance experiments and
+//! no-go zones.
+//!
+//! `sense` subtracts the repellent under each sample, times the species'
+//! `repellentSensitivity`, from the weight it returns. Negative repellent
+//! attracts. The field is kept at trail map resolution so it can be painted
+//! with the mouse, and it neither diffuses nor decays.
+
+use std::{ops::Range, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{food::disk_texels, spawn::LuminanceMap, Config, ConfigError};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct RepellentSettings {
+    /// Image whose luminance times `strength` is the repellent on each texel,
+    /// stretched over the whole map.
+    pub image: Option<PathBuf>,
+    /// Repellent of a white pixel of `image`; negative to make it attract.
+    pub strength: f32,
+    /// Round patches of repellent, added to `image` and to each other.
+    pub circles: Vec<RepellentCircle>,
+}
+
+impl Default for RepellentSettings {
+    fn default() -> Self {
+        Self {
+            image: None,
+            strength: 1.0,
+            circles: Vec::new(),
+        }
+    }
+}
+
+/// A disk of repellent centred on (`x`, `y`) in trail map texels.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct RepellentCircle {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+    /// Repellent on each texel of the disk; negative to make it attract.
+    pub strength: f32,
+}
+
+impl Default for RepellentCircle {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            radius: 32.0,
+            strength: 1.0,
+        }
+    }
+}
+
+/// The repellent on each texel of the trail map, row by row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RepellentMap {
+    pub width: u32,
+    pub height: u32,
+    pub values: Vec<f32>,
+}
+
+impl RepellentMap {
+    /// Lays out the `[repellent]` section of `config` over a trail map of the
+    /// configured size, loading the image if there is one.
+    pub fn new(config: &Config) -> Result<Self, ConfigError> {
+        let settings = &config.repellent;
+        let width = config.simulation.width;
+        let height = config.simulation.height;
+        let mut values = vec![0.0; (width * height) as usize];
+        if let Some(path) = &settings.image {
+            let image = LuminanceMap::load(path)?;
+            for (i, value) in values.iter_mut().enumerate() {
+                let x = i as u32 % width * image.width / width;
+                let y = i as u32 / width * image.height / height;
+                *value = image.values[(y * image.width + x) as usize] * settings.strength;
+            }
+        }
+        let mut map = Self {
+            width,
+            height,
+            values,
+        };
+        for circle in &settings.circles {
+            map.paint(circle.x, circle.y, circle.radius, circle.strength);
+        }
+        Ok(map)
+    }
+
+    /// Repellent on texel (`x`, `y`), 0 off the map.
+    pub fn at(&self, x: i32, y: i32) -> f32 {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            0.0
+        } else {
+            self.values[(y as u32 * self.width + x as u32) as usize]
+        }
+    }
+
+    /// Adds `amount` to every texel within `radius` of (`x`, `y`), returning
+    /// the rows that changed so only those need uploading.
+    pub fn paint(&mut self, x: f32, y: f32, radius: f32, amount: f32) -> Range<u32> {
+        let mut rows: Option<Range<u32>> = None;
+        for (column, row) in disk_texels(x, y, radius, self.width, self.height) {
+            self.values[(row * self.width + column) as usize] += amount;
+            rows = Some(rows.map_or(row..row + 1, |rows| rows.start..row + 1));
+        }
+        rows.unwrap_or(0..0)
+    }
+}
@@ -0,0 +1,194 @@
+//! Full-resolution screenshots.
+//!
+//! A screenshot is the tonemapped frame as an 8-bit PNG plus the raw
+//! `Rgba32Float` trail texture as one 32-bit float EXR per species. The PNG
+//! carries the species settings, seed and step counter in `tEXt` chunks, so
+//! [`ScreenshotInfo::read_png`] can pick the parameters back up from it.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+
+use crate::{Config, SpeciesSettings};
+
+const SPECIES_KEY: &str = "slime:species";
+const SEED_KEY: &str = "slime:seed";
+const FRAME_KEY: &str = "slime:frame";
+
+/// The parameters embedded in a screenshot PNG.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScreenshotInfo {
+    pub species: Vec<SpeciesSettings>,
+    pub seed: u32,
+    /// Steps taken when the screenshot was made.
+    pub frame: u32,
+}
+
+#[derive(Debug)]
+pub enum ScreenshotError {
+    Io(io::Error),
+    Png(String),
+    Image(image::ImageError),
+    /// A `tEXt` chunk is missing or does not parse.
+    Metadata {
+        key: &'static str,
+        error: String,
+    },
+}
+
+impl fmt::Display for ScreenshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScreenshotError::Io(e) => write!(f, "{}", e),
+            ScreenshotError::Png(e) => write!(f, "png: {}", e),
+            ScreenshotError::Image(e) => write!(f, "{}", e),
+            ScreenshotError::Metadata { key, error } => write!(f, "{}: {}", key, error),
+        }
+    }
+}
+
+impl std::error::Error for ScreenshotError {}
+
+impl From<io::Error> for ScreenshotError {
+    fn from(e: io::Error) -> Self {
+        ScreenshotError::Io(e)
+    }
+}
+
+impl From<png::EncodingError> for ScreenshotError {
+    fn from(e: png::EncodingError) -> Self {
+        ScreenshotError::Png(e.to_string())
+    }
+}
+
+impl From<png::DecodingError> for ScreenshotError {
+    fn from(e: png::DecodingError) -> Self {
+        ScreenshotError::Png(e.to_string())
+    }
+}
+
+impl ScreenshotInfo {
+    /// Reads the `tEXt` chunks written by [`save_png`].
+    pub fn read_png(path: &Path) -> Result<Self, ScreenshotError> {
+        let reader = png::Decoder::new(io::BufReader::new(File::open(path)?)).read_info()?;
+        let text = |key: &'static str| {
+            reader
+                .info()
+                .uncompressed_latin1_text
+                .iter()
+                .find(|chunk| chunk.keyword == key)
+                .map(|chunk| chunk.text.as_str())
+                .ok_or(ScreenshotError::Metadata {
+                    key,
+                    error: "missing".to_owned(),
+                })
+        };
+        let parse = |key: &'static str| {
+            text(key)?
+                .parse::<u32>()
+                .map_err(|e| ScreenshotError::Metadata {
+                    key,
+                    error: e.to_string(),
+                })
+        };
+        Ok(Self {
+            species: serde_json::from_str(text(SPECIES_KEY)?).map_err(|e| {
+                ScreenshotError::Metadata {
+                    key: SPECIES_KEY,
+                    error: e.to_string(),
+                }
+            })?,
+            seed: parse(SEED_KEY)?,
+            frame: parse(FRAME_KEY)?,
+        })
+    }
+
+    /// Sets the species and seed of `config` to the ones in the screenshot.
+    pub fn apply_to(&self, config: &mut Config) {
+        config.species = self.species.clone();
+        config.simulation.seed = Some(self.seed);
+    }
+}
+
+/// Writes `image` as an 8-bit RGBA PNG with `info` in `tEXt` chunks.
+pub fn save_png(
+    path: &Path,
+    image: &image::RgbaImage,
+    info: &ScreenshotInfo,
+) -> Result<(), ScreenshotError> {
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(File::create(path)?),
+        image.width(),
+        image.height(),
+    );
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    let species = serde_json::to_string(&info.species).expect("species always serialise");
+    encoder.add_text_chunk(SPECIES_KEY.to_owned(), species)?;
+    encoder.add_text_chunk(SEED_KEY.to_owned(), info.seed.to_string())?;
+    encoder.add_text_chunk(FRAME_KEY.to_owned(), info.frame.to_string())?;
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(image.as_raw())?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Writes one `width x height` layer of raw trail texels as a 32-bit float
+/// RGBA EXR.
+pub fn save_exr(
+    path: &Path,
+    width: u32,
+    height: u32,
+    texels: &[[f32; 4]],
+) -> Result<(), ScreenshotError> {
+    let image = image::Rgba32FImage::from_raw(width, height, texels.as_flattened().to_vec())
+        .expect("one texel per pixel");
+    image.save(path).map_err(ScreenshotError::Image)
+}
+
+/// Saves `image` and every species layer of `texels` into `dir` as
+/// `screenshot-<frame>.png` and `screenshot-<frame>[-species<i>].exr`,
+/// returning the paths written. Screenshots of a frame already saved, e.g.
+/// while paused, get `-2`, `-3` and so on after the frame number instead of
+/// replacing it.
+pub fn save(
+    dir: &Path,
+    image: &image::RgbaImage,
+    texels: &[[f32; 4]],
+    info: &ScreenshotInfo,
+) -> Result<Vec<PathBuf>, ScreenshotError> {
+    std::fs::create_dir_all(dir)?;
+    let (width, height) = image.dimensions();
+    let layers: Vec<&[[f32; 4]]> = texels.chunks((width * height) as usize).collect();
+    let paths_for = |stem: &str| {
+        let png_path = dir.join(format!("{}.png", stem));
+        let exr_paths = (0..layers.len()).map(|i| {
+            if layers.len() == 1 {
+                dir.join(format!("{}.exr", stem))
+            } else {
+                dir.join(format!("{}-species{}.exr", stem, i))
+            }
+        });
+        std::iter::once(png_path)
+            .chain(exr_paths)
+            .collect::<Vec<_>>()
+    };
+    let stem = format!("screenshot-{:06}", info.frame);
+    let mut paths = paths_for(&stem);
+    for copy in 2.. {
+        if !paths.iter().any(|path| path.exists()) {
+            break;
+        }
+        paths = paths_for(&format!("{}-{}", stem, copy));
+    }
+
+    save_png(&paths[0], image, info)?;
+    for (path, layer) in paths[1..].iter().zip(&layers) {
+        save_exr(path, width, height, layer)?;
+    }
+    Ok(paths)
+}
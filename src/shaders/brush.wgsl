@@ -0,0 +1,157 @@
+// Mouse brushes, applied between steps. `paint` runs over the texels of the
+// brush's bounding box in every layer, `moveAgents` over one slice of the
+// agent buffer like `update`. `cpu.rs` mirrors both.
+
+fn triple32(x: u32) -> u32 {
+    var y = x;
+    y = y ^ (y >> 17u);
+    y = y * 0xed5ad4bbu;
+    y = y ^ (y >> 11u);
+    y = y * 0xac4c1b51u;
+    y = y ^ (y >> 15u);
+    y = y * 0x31848babu;
+    y = y ^ (y >> 14u);
+    return y;
+}
+
+fn scaleToRange01(state: u32) -> f32 {
+    return f32(state) / 4294967295.0;
+}
+
+// Advances `state` and returns a number in [0, 1]
+fn random01(state: ptr<function, u32>) -> f32 {
+    *state = triple32(*state);
+    return scaleToRange01(*state);
+}
+
+struct Agent {
+    posX: f32,
+    posY: f32,
+    angle: f32,
+    speciesIndex: u32
+};
+struct Agents {
+    data: array<Agent>
+};
+
+struct AgentChunk {
+    firstAgent: u32,
+    numAgents: u32
+};
+
+struct BrushParams {
+    tool: u32,
+    x: f32,
+    y: f32,
+    radius: f32,
+    amount: f32,
+    species: u32,
+    firstRelocated: u32,
+    numRelocated: u32,
+    numAgents: u32,
+    seed: u32,
+    width: f32,
+    height: f32
+};
+
+@group(0) @binding(0) var<uniform> brushParams: BrushParams;
+// One layer per species, with the sensed trail in r and the deposited one in b
+@group(0) @binding(1) var TrailTexture: texture_storage_2d_array<rgba32float, read_write>;
+@group(0) @binding(2) var<storage, read_write> agents: Agents;
+@group(0) @binding(3) var<uniform> agentChunk: AgentChunk;
+// Stretched over the whole map, walls are 1 and open ground 0
+@group(0) @binding(4) var Obstacles: texture_2d<f32>;
+
+// Must match `BrushTool` in `brush.rs`
+const TOOL_PAINT: u32 = 0u;
+const TOOL_ERASE: u32 = 1u;
+const TOOL_RELOCATE: u32 = 2u;
+const TOOL_ATTRACT: u32 = 3u;
+
+// `brushParams.species` when every layer is painted
+const ALL_SPECIES: u32 = 0xffffffffu;
+
+const TWO_PI: f32 = 6.28318530718;
+
+fn centre() -> vec2<f32> {
+    return vec2<f32>(brushParams.x, brushParams.y);
+}
+
+fn mapSize() -> vec2<f32> {
+    return vec2<f32>(brushParams.width, brushParams.height);
+}
+
+// Like `isWall` in `slime.wgsl`
+fn isWall(texel: vec2<i32>) -> bool {
+    let size = vec2<i32>(mapSize());
+    if any(texel < vec2<i32>(0)) || any(texel >= size) {
+        return false;
+    }
+    let maskTexel = vec2<u32>(texel) * textureDimensions(Obstacles) / vec2<u32>(size);
+    return textureLoad(Obstacles, maskTexel, 0).r > 0.5;
+}
+
+@compute @workgroup_size(16,16,1)
+fn paint(@builtin(global_invocation_id) id: vec3<u32>) {
+    let origin = max(vec2<i32>(floor(centre() - brushParams.radius)), vec2<i32>(0));
+    let texel = origin + vec2<i32>(id.xy);
+    if any(texel >= vec2<i32>(mapSize())) {
+        return;
+    }
+    // Measured from the texel centre, like `RepellentMap::paint`
+    let offset = vec2<f32>(texel) + 0.5 - centre();
+    if dot(offset, offset) > brushParams.radius * brushParams.radius {
+        return;
+    }
+
+    var pix = textureLoad(TrailTexture, texel, id.z);
+    if brushParams.tool == TOOL_ERASE {
+        let kept = 1.0 - clamp(brushParams.amount, 0.0, 1.0);
+        pix.r = pix.r * kept;
+        pix.b = pix.b * kept;
+    } else if brushParams.species == ALL_SPECIES || brushParams.species == id.z {
+        pix.r = max(0.0, pix.r + brushParams.amount);
+        pix.b = max(0.0, pix.b + brushParams.amount);
+    }
+    textureStore(TrailTexture, texel, id.z, pix);
+}
+
+@compute @workgroup_size(128,1,1)
+fn moveAgents(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) numWorkgroups: vec3<u32>) {
+    let index = id.x + id.y * numWorkgroups.x * 128u;
+    if index >= agentChunk.numAgents {
+        return;
+    }
+    let agentId = agentChunk.firstAgent + index;
+    let position = vec2<f32>(agents.data[index].posX, agents.data[index].posY);
+
+    if brushParams.tool == TOOL_RELOCATE {
+        // Counted from the first relocated agent, wrapping around the population
+        let turn = (agentId + brushParams.numAgents - brushParams.firstRelocated) % brushParams.numAgents;
+        if turn >= brushParams.numRelocated {
+            return;
+        }
+        var state = triple32(agentId) ^ brushParams.seed;
+        let r = brushParams.radius * sqrt(random01(&state));
+        let theta = random01(&state) * TWO_PI;
+        let relocated = centre() + vec2<f32>(cos(theta), sin(theta)) * r;
+        let clamped = min(mapSize() - vec2<f32>(1.0), max(vec2<f32>(0.0), relocated));
+        agents.data[index].posX = clamped.x;
+        agents.data[index].posY = clamped.y;
+        agents.data[index].angle = random01(&state) * TWO_PI;
+    } else if brushParams.tool == TOOL_ATTRACT {
+        let toCentre = centre() - position;
+        let distance = length(toCentre);
+        if distance <= 0.0 || distance > brushParams.radius {
+            return;
+        }
+        let pull = min(distance, brushParams.amount * (1.0 - distance / brushParams.radius));
+        let pulled = position + toCentre / distance * pull;
+        // Walls hold agents back, as they do in `update`
+        if isWall(vec2<i32>(pulled)) && !isWall(vec2<i32>(position)) {
+            return;
+        }
+        agents.data[index].posX = pulled.x;
+        agents.data[index].posY = pulled.y;
+    }
+}
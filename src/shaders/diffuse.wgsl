@@ -3,12 +3,43 @@ struct ShaderParams {
     width: f32,
     height: f32,
     delta: f32,
-    time: f32
+    seed: u32,
+    frame: u32,
+    diffuseRate: f32,
+    decayRate: f32,
+    depositIntensity: f32,
+    boundaryMode: u32
 };
 
 @group(0) @binding(0) var<uniform> shaderParams : ShaderParams;
-@group(0) @binding(1) var PingTexture : texture_storage_2d<rgba32float, read>;
-@group(0) @binding(2) var PongTexture : texture_storage_2d<rgba32float, write>;
+// One layer per species, diffused independently with id.z as the layer
+@group(0) @binding(1) var PingTexture : texture_storage_2d_array<rgba32float, read>;
+@group(0) @binding(2) var PongTexture : texture_storage_2d_array<rgba32float, write>;
+
+// Stretched over the whole map, walls are 1 and open ground 0
+@group(0) @binding(3) var Obstacles: texture_2d<f32>;
+
+struct FoodParams {
+    amount: f32,
+    depletion: f32,
+    species: u32
+};
+
+@group(0) @binding(4) var<uniform> foodParams: FoodParams;
+// Food left on each texel of the map, row by row
+@group(0) @binding(5) var<storage, read_write> Food: array<f32>;
+
+// `foodParams.species` when every layer is fed
+const ALL_SPECIES: u32 = 0xffffffffu;
+
+// Must match `slime.wgsl`
+const BOUNDARY_WRAP: u32 = 0u;
+
+fn isWall(texel: vec2<i32>) -> bool {
+    let mapSize = vec2<u32>(u32(shaderParams.width), u32(shaderParams.height));
+    let maskTexel = vec2<u32>(texel) * textureDimensions(Obstacles) / mapSize;
+    return textureLoad(Obstacles, maskTexel, 0).r > 0.5;
+}
 
 
 fn rgb2hsv(c: vec3<f32>) -> vec3<f32> {
@@ -29,21 +60,38 @@ fn hsv2rgb(c: vec3<f32>) -> vec3<f32> {
 @compute @workgroup_size(16,16,1)
 fn diffuse(@builtin(global_invocation_id) id: vec3<u32>) {
     let delta = shaderParams.delta;
-    const diffuseRate = 10.0;
-    const decayRate = 0.25;
+    let diffuseRate = shaderParams.diffuseRate;
+    let decayRate = shaderParams.decayRate;
     if id.x < 0u || id.x >= u32(shaderParams.width) || id.y < 0u || id.y >= u32(shaderParams.height) {
         return;
     }
 
+    // Nothing is kept inside walls
+    if isWall(vec2<i32>(id.xy)) {
+        textureStore(PongTexture, vec2<i32>(id.xy), id.z, vec4<f32>(0.0, 0.0, 0.0, 1.0));
+        return;
+    }
+
     var sum = vec4<f32>(0.0);
-    let originalPix = textureLoad(PingTexture, vec2<i32>(id.xy));
+    let originalPix = textureLoad(PingTexture, vec2<i32>(id.xy), id.z);
     var originalCol = vec4<f32>(vec3<f32>(originalPix.b), 1.0);
 	// 3x3 blur
     for (var offsetX = -1; offsetX <= 1; offsetX = offsetX + 1) {
         for (var offsetY = -1; offsetY <= 1; offsetY = offsetY + 1) {
-            var sampleX = min(i32(shaderParams.width) - 1, max(0, i32(id.x) + offsetX));
-            var sampleY = min(i32(shaderParams.height) - 1, max(0, i32(id.y) + offsetY));
-            sum = sum + vec4<f32>(vec3<f32>(textureLoad(PingTexture, vec2<i32>(sampleX, sampleY)).b), 1.0);
+            let width = i32(shaderParams.width);
+            let height = i32(shaderParams.height);
+            var sampleX = min(width - 1, max(0, i32(id.x) + offsetX));
+            var sampleY = min(height - 1, max(0, i32(id.y) + offsetY));
+            if shaderParams.boundaryMode == BOUNDARY_WRAP {
+                sampleX = (i32(id.x) + offsetX + width) % width;
+                sampleY = (i32(id.y) + offsetY + height) % height;
+            }
+            // Walls reflect the trail back instead of soaking it up
+            var neighbour = originalPix.b;
+            if !isWall(vec2<i32>(sampleX, sampleY)) {
+                neighbour = textureLoad(PingTexture, vec2<i32>(sampleX, sampleY), id.z).b;
+            }
+            sum = sum + vec4<f32>(vec3<f32>(neighbour), 1.0);
         }
     }
 
@@ -58,6 +106,34 @@ fn diffuse(@builtin(global_invocation_id) id: vec3<u32>) {
     let decayedCol = blurredCol.rgb * decayFactor;
     // let decayedCol = blurredCol.rgb / vec3<f32>(1.0 + decayFactor);
 	//DiffusedTrailMap[id.xy] = blurredCol * saturate(1 - decayRate * delta);
-    textureStore(PongTexture, vec2<i32>(id.xy), max(vec4<f32>(0.0), vec4<f32>(decayedCol.r, 0.0, decayedCol.r, 1.0)));
+
+    // Food deposits like an agent would, scaled by what is left of it
+    var fed = decayedCol.r;
+    if foodParams.species == ALL_SPECIES || foodParams.species == id.z {
+        let food = Food[id.y * u32(shaderParams.width) + id.x];
+        fed = fed + food * foodParams.amount * delta * 10.0;
+    }
+    textureStore(PongTexture, vec2<i32>(id.xy), id.z, max(vec4<f32>(0.0), vec4<f32>(fed, 0.0, fed, 1.0)));
+}
+
+// Runs before `diffuse`, while the ping texture still holds this step's
+// deposits: any texel an agent deposited on in any layer (blue above red) is
+// eaten from.
+@compute @workgroup_size(16,16,1)
+fn consume(@builtin(global_invocation_id) id: vec3<u32>) {
+    if id.x >= u32(shaderParams.width) || id.y >= u32(shaderParams.height) {
+        return;
+    }
+    let index = id.y * u32(shaderParams.width) + id.x;
+    if Food[index] <= 0.0 {
+        return;
+    }
+    for (var layer = 0u; layer < textureNumLayers(PingTexture); layer = layer + 1u) {
+        let pix = textureLoad(PingTexture, vec2<i32>(id.xy), layer);
+        if pix.b > pix.r {
+            Food[index] = max(0.0, Food[index] - foodParams.depletion * shaderParams.delta);
+            return;
+        }
+    }
 }
 
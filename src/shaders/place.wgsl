@@ -0,0 +1,89 @@
+// Agent placement shared by `spawn.wgsl` and the respawning in `slime.wgsl`,
+// appended to both when their modules are created. The including module
+// declares `Agent`, `SpawnParams` and the `spawnParams` and `Luminance`
+// bindings. `spawn.rs` mirrors `placeAgent` on the CPU.
+
+fn triple32(x: u32) -> u32 {
+    var y = x;
+    y = y ^ (y >> 17u);
+    y = y * 0xed5ad4bbu;
+    y = y ^ (y >> 11u);
+    y = y * 0xac4c1b51u;
+    y = y ^ (y >> 15u);
+    y = y * 0x31848babu;
+    y = y ^ (y >> 14u);
+    return y;
+}
+
+fn scaleToRange01(state: u32) -> f32 {
+    return f32(state) / 4294967295.0;
+}
+
+// Advances `state` and returns a number in [0, 1]
+fn random01(state: ptr<function, u32>) -> f32 {
+    *state = triple32(*state);
+    return scaleToRange01(*state);
+}
+
+const PI : f32 = 3.14159265359;
+const TWO_PI : f32 = 6.28318530718;
+
+const SPAWN_DISK: u32 = 0u;
+const SPAWN_RING_INWARD: u32 = 1u;
+const SPAWN_RING_OUTWARD: u32 = 2u;
+const SPAWN_RANDOM: u32 = 3u;
+const SPAWN_POINT: u32 = 4u;
+const SPAWN_IMAGE: u32 = 5u;
+
+// Candidate positions tried per agent before settling for the last one
+const IMAGE_ATTEMPTS: u32 = 64u;
+
+fn luminanceAt(position: vec2<f32>) -> f32 {
+    let size = textureDimensions(Luminance);
+    let uv = position / vec2<f32>(spawnParams.width, spawnParams.height);
+    let texel = min(vec2<u32>(uv * vec2<f32>(size)), size - vec2<u32>(1u));
+    return textureLoad(Luminance, texel, 0).r;
+}
+
+// A new agent of species `speciesIndex` somewhere in the spawn region, drawn
+// from the RNG `seedState`.
+fn placeAgent(seedState: u32, speciesIndex: u32) -> Agent {
+    var state = seedState;
+    let size = vec2<f32>(spawnParams.width, spawnParams.height);
+    let centre = size / 2.0;
+    var position = centre;
+    var angle = 0.0;
+
+    switch spawnParams.mode {
+        case SPAWN_RING_INWARD, SPAWN_RING_OUTWARD: {
+            let theta = random01(&state) * TWO_PI;
+            position = centre + vec2<f32>(cos(theta), sin(theta)) * spawnParams.radius;
+            angle = select(theta, theta + PI, spawnParams.mode == SPAWN_RING_INWARD);
+        }
+        case SPAWN_RANDOM: {
+            position = vec2<f32>(random01(&state), random01(&state)) * size;
+            angle = random01(&state) * TWO_PI;
+        }
+        case SPAWN_POINT: {
+            angle = random01(&state) * TWO_PI;
+        }
+        case SPAWN_IMAGE: {
+            for (var attempt = 0u; attempt < IMAGE_ATTEMPTS; attempt++) {
+                position = vec2<f32>(random01(&state), random01(&state)) * size;
+                if random01(&state) < luminanceAt(position) {
+                    break;
+                }
+            }
+            angle = random01(&state) * TWO_PI;
+        }
+        // SPAWN_DISK
+        default: {
+            let r = spawnParams.radius * sqrt(random01(&state));
+            let theta = random01(&state) * TWO_PI;
+            position = centre + vec2<f32>(cos(theta), sin(theta)) * r;
+            angle = random01(&state) * TWO_PI;
+        }
+    }
+
+    return Agent(position.x, position.y, angle, speciesIndex);
+}
@@ -0,0 +1,81 @@
+// Colour pass for the CPU backend: same tonemapping as shader.wgsl, but reads
+// an uploaded r32float trail map instead of the simulation's storage texture.
+
+// Vertex shader
+struct VertexInput {
+    @location(0) position: vec3<f32>
+};
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>
+};
+
+@group(0) @binding(0) var TrailTexture : texture_2d_array<f32>;
+
+struct RenderParams {
+    width: f32,
+    height: f32,
+    scaleDownFactor: f32,
+    obstacleColourR: f32,
+    obstacleColourG: f32,
+    obstacleColourB: f32,
+    obstacleColourA: f32,
+};
+@group(0) @binding(1) var<uniform> renderParams: RenderParams;
+
+// Uniform rather than storage so this pass runs on downlevel devices, hence
+// the fixed MAX_SPECIES (8) entries
+struct SpeciesColours {
+    numSpecies: u32,
+    colours: array<vec4<f32>, 8>,
+};
+@group(0) @binding(2) var<uniform> speciesColours: SpeciesColours;
+
+@vertex
+fn vs_main(
+    vertex: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(vertex.position, 1.0);
+    return out;
+}
+
+// Fragment shader
+
+// Stretched over the whole map, walls are 1 and open ground 0
+@group(0) @binding(3) var Obstacles: texture_2d<f32>;
+
+fn isWall(texel: vec2<i32>) -> bool {
+    let mapSize = vec2<i32>(i32(renderParams.width), i32(renderParams.height));
+    if any(texel < vec2<i32>(0)) || any(texel >= mapSize) {
+        return false;
+    }
+    let maskTexel = vec2<u32>(texel) * textureDimensions(Obstacles) / vec2<u32>(mapSize);
+    return textureLoad(Obstacles, maskTexel, 0).r > 0.5;
+}
+
+const GAMMA: f32 = 1.01;
+const INV_GAMMA: f32 = 1.0 / GAMMA;
+
+// Gamma correction
+fn gamma_correct(color: f32) -> f32 {
+    return pow(max(color, 0.0), INV_GAMMA);
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let position = vec2<i32>(in.clip_position.xy * renderParams.scaleDownFactor);
+
+    var colour = vec3<f32>(0.0);
+    for (var layer = 0u; layer < speciesColours.numSpecies; layer++) {
+        let trail = textureLoad(TrailTexture, position, layer, 0).r;
+        let tint = speciesColours.colours[layer];
+        colour = colour + tint.rgb * tint.a * gamma_correct(trail) * 10.0;
+    }
+
+    if isWall(position) {
+        let obstacleColour = vec3<f32>(renderParams.obstacleColourR, renderParams.obstacleColourG, renderParams.obstacleColourB);
+        colour = mix(colour, obstacleColour, renderParams.obstacleColourA);
+    }
+
+    return vec4<f32>(colour, 1.0);
+}
@@ -7,7 +7,7 @@ struct VertexOutput {
 };
 
 @group(0) @binding(0) var SourceTextureSampler : sampler;
-@group(0) @binding(1) var SourceTexture : texture_storage_2d<rgba32float, read>;
+@group(0) @binding(1) var SourceTexture : texture_storage_2d_array<rgba32float, read>;
 
 @vertex
 fn vs_main(
@@ -24,9 +24,39 @@ struct RenderParams {
     width: f32,
     height: f32,
     scaleDownFactor: f32,
+    obstacleColourR: f32,
+    obstacleColourG: f32,
+    obstacleColourB: f32,
+    obstacleColourA: f32,
 };
 @group(0) @binding(2) var<uniform> renderParams: RenderParams;
 
+struct SpeciesSettings {
+    moveSpeed: f32,
+    turnSpeed: f32,
+    sensorAngleDegrees: f32,
+    sensorOffsetDst: f32,
+    sensorSize: f32,
+    colourR: f32,
+    colourG: f32,
+    colourB: f32,
+    colourA: f32,
+    repellentSensitivity: f32
+};
+@group(0) @binding(3) var<storage, read> speciesSettings: array<SpeciesSettings>;
+
+// Stretched over the whole map, walls are 1 and open ground 0
+@group(0) @binding(4) var Obstacles: texture_2d<f32>;
+
+fn isWall(texel: vec2<i32>) -> bool {
+    let mapSize = vec2<i32>(i32(renderParams.width), i32(renderParams.height));
+    if any(texel < vec2<i32>(0)) || any(texel >= mapSize) {
+        return false;
+    }
+    let maskTexel = vec2<u32>(texel) * textureDimensions(Obstacles) / vec2<u32>(mapSize);
+    return textureLoad(Obstacles, maskTexel, 0).r > 0.5;
+}
+
 const GAMMA: f32 = 1.01;
 const INV_GAMMA: f32 = 1.0 / GAMMA;
 
@@ -37,11 +67,23 @@ fn gamma_correct(color: f32) -> f32 {
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    var thing = textureLoad(SourceTexture, vec2<i32>((in.clip_position * renderParams.scaleDownFactor).xy));
-    
-    let corrected = gamma_correct(thing.r);
-    
-    return vec4<f32>(vec3<f32>(corrected * 10.0), 1.0);
+    let position = vec2<i32>((in.clip_position * renderParams.scaleDownFactor).xy);
+
+    // Each species' trail is tonemapped on its own and tinted with its colour
+    var colour = vec3<f32>(0.0);
+    for (var layer = 0u; layer < arrayLength(&speciesSettings); layer++) {
+        let thing = textureLoad(SourceTexture, position, layer);
+        let settings = speciesSettings[layer];
+        let tint = vec3<f32>(settings.colourR, settings.colourG, settings.colourB) * settings.colourA;
+        colour = colour + tint * gamma_correct(thing.r) * 10.0;
+    }
+
+    if isWall(position) {
+        let obstacleColour = vec3<f32>(renderParams.obstacleColourR, renderParams.obstacleColourG, renderParams.obstacleColourB);
+        colour = mix(colour, obstacleColour, renderParams.obstacleColourA);
+    }
+
+    return vec4<f32>(colour, 1.0);
     // return in.clip_position / vec4<f32>(1000.0);
 }
  
\ No newline at end of file
@@ -1,20 +1,9 @@
-fn triple32(x: u32) -> u32 {
-    var y = x;
-    y = y ^ (y >> 17u);
-    y = y * 0xed5ad4bbu;
-    y = y ^ (y >> 11u);
-    y = y * 0xac4c1b51u;
-    y = y ^ (y >> 15u);
-    y = y * 0x31848babu;
-    y = y ^ (y >> 14u);
-    return y;
-}
-
 struct Agent {
     // position: vec2<f32>;
 	posX: f32,
     posY: f32,
-    angle: f32
+    angle: f32,
+    speciesIndex: u32
 	//intensity: f32;
 };
 struct Agents {
@@ -30,7 +19,8 @@ struct SpeciesSettings {
     colourR: f32,
     colourG: f32,
     colourB: f32,
-    colourA: f32
+    colourA: f32,
+    repellentSensitivity: f32
 };
 
 struct ShaderParams {
@@ -38,13 +28,18 @@ struct ShaderParams {
     width: f32,
     height: f32,
     delta: f32,
-    time: f32
+    seed: u32,
+    frame: u32,
+    diffuseRate: f32,
+    decayRate: f32,
+    depositIntensity: f32,
+    boundaryMode: u32
 };
 @group(0) @binding(0)
 var<uniform> shaderParams : ShaderParams;
 
 @group(0) @binding(1)
-var<uniform> speciesSettings: SpeciesSettings;
+var<storage, read> speciesSettings: array<SpeciesSettings>;
 
 @group(0) @binding(2)
 var<storage, read_write> agents: Agents;
@@ -52,7 +47,76 @@ struct FloatArray {
     elements: array<f32>,
 };
 // @group(0) @binding(3) var<storage, read_write> Texture : FloatArray;
-@group(0) @binding(3) var SourceTexture : texture_storage_2d<rgba32float, read_write>;
+// One layer per species, each with the sensed trail in r and this step's
+// deposits in b
+@group(0) @binding(3) var SourceTexture : texture_storage_2d_array<rgba32float, read_write>;
+
+// The slice of the population bound to `agents`. Populations larger than
+// max_storage_buffer_binding_size are split into several slices, each updated
+// by its own dispatch.
+struct AgentChunk {
+    firstAgent: u32,
+    numAgents: u32
+};
+@group(0) @binding(4)
+var<uniform> agentChunk: AgentChunk;
+
+// Where BOUNDARY_RESPAWN puts agents back, see `place.wgsl`
+struct SpawnParams {
+    mode: u32,
+    seed: u32,
+    numSpecies: u32,
+    radius: f32,
+    width: f32,
+    height: f32
+};
+@group(0) @binding(5) var<uniform> spawnParams: SpawnParams;
+@group(0) @binding(6) var Luminance: texture_2d<f32>;
+// Stretched over the whole map, walls are 1 and open ground 0
+@group(0) @binding(7) var Obstacles: texture_2d<f32>;
+// Signed repellent on each texel of the map, row by row
+@group(0) @binding(8) var<storage, read> Repellent: array<f32>;
+
+const BOUNDARY_WRAP: u32 = 0u;
+const BOUNDARY_REFLECT: u32 = 1u;
+const BOUNDARY_CLAMP: u32 = 2u;
+const BOUNDARY_RESPAWN: u32 = 3u;
+
+fn mapSize() -> vec2<i32> {
+    return vec2<i32>(i32(shaderParams.width), i32(shaderParams.height));
+}
+
+fn onMap(texel: vec2<i32>) -> bool {
+    return all(texel >= vec2<i32>(0)) && all(texel < mapSize());
+}
+
+fn isWall(texel: vec2<i32>) -> bool {
+    if !onMap(texel) {
+        return false;
+    }
+    let maskTexel = vec2<u32>(texel) * textureDimensions(Obstacles) / vec2<u32>(mapSize());
+    return textureLoad(Obstacles, maskTexel, 0).r > 0.5;
+}
+
+fn wrapTexel(texel: vec2<i32>) -> vec2<i32> {
+    let size = mapSize();
+    return (texel % size + size) % size;
+}
+
+fn wrapCoordinate(value: f32, size: f32) -> f32 {
+    let wrapped = value - floor(value / size) * size;
+    // Tiny negative values round up to `size`
+    return select(wrapped, 0.0, wrapped >= size);
+}
+
+fn reflectCoordinate(value: f32, size: f32) -> f32 {
+    return select(select(value, 2.0 * size - value, value >= size), -value, value < 0.0);
+}
+
+fn clampToMap(position: vec2<f32>) -> vec2<f32> {
+    let size = vec2<f32>(shaderParams.width, shaderParams.height);
+    return min(size - vec2<f32>(1.0), max(vec2<f32>(0.0), position));
+}
 
 
 
@@ -70,51 +134,63 @@ fn sense(agent: Agent, settings: SpeciesSettings, sensorAngleOffset: f32) -> f32
         for (var offsetY = -i32(settings.sensorSize); offsetY <= i32(settings.sensorSize); offsetY++) {
 			// let sampleX = min(i32(shaderParams.width) - 1, max(0, sensorCentreX + i32(offsetX)));
 			// let sampleY = min(i32(shaderParams.height) - 1, max(0, sensorCentreY + i32(offsetY)));
-            let sampleX = sensorCentreX + i32(offsetX);
-            let sampleY = sensorCentreY + i32(offsetY);
+            var texel = vec2<i32>(sensorCentreX + i32(offsetX), sensorCentreY + i32(offsetY));
+            if shaderParams.boundaryMode == BOUNDARY_WRAP {
+                texel = wrapTexel(texel);
+            } else if !onMap(texel) {
+                // Beyond the walls there is no trail
+                continue;
+            }
 			//let offset : i32 = sampleY * i32(shaderParams.width) * 4 + sampleX * 4;
             // sum = sum + dot(vec4<f32>(1.0), vec4<f32>(
             //     textureLoad(SourceTexture, vec2<i32>(sampleX, sampleY)).r,
             // ));
-            sum = sum + textureLoad(SourceTexture, vec2<i32>(sampleX, sampleY)).r;
+            // Attracted to the agent's own species, repelled by every other one
+            for (var layer = 0u; layer < arrayLength(&speciesSettings); layer++) {
+                let weight = select(-1.0, 1.0, layer == agent.speciesIndex);
+                sum = sum + weight * textureLoad(SourceTexture, texel, layer).r;
+            }
+            // and pushed away by the repellent
+            sum = sum - settings.repellentSensitivity * Repellent[texel.y * mapSize().x + texel.x];
         }
     }
 
     return sum;
 }
 
-fn scaleToRange01(state: u32) -> f32 {
-    return f32(state) / 4294967295.0;
-}
-
 const PI_OVER_180 : f32 = 0.01745329251;
-const TWO_PI : f32 = 6.28318530718;
 
+// `triple32`, `random01`, `PI`, `TWO_PI` and `placeAgent` come from
+// `place.wgsl`, which is appended to this module.
 
 @compute @workgroup_size(128,1,1)
-fn update(@builtin(global_invocation_id) id: vec3<u32>) {
-    if id.x >= u32(shaderParams.numAgents) {
+fn update(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) numWorkgroups: vec3<u32>) {
+    // Dispatches wider than max_compute_workgroups_per_dimension continue in y
+    let index = id.x + id.y * numWorkgroups.x * 128u;
+    if index >= agentChunk.numAgents {
         return;
     }
+    let agentId = agentChunk.firstAgent + index;
 
-    var agent = agents.data[id.x];
+    var agent = agents.data[index];
+    let settings = speciesSettings[agent.speciesIndex];
     let pos = vec2<f32>(agent.posX, agent.posY);
 
 	//let intPos = vec2<i32>(i32(pos.x), i32(pos.y));
 	//let oldIntensity = textureLoad(SourceTexture, intPos).b;
 	//textureStore(SourceTexture, intPos, vec4<f32>(oldIntensity, oldIntensity, 0.0, 1.0));
 
-    var random = triple32(u32(pos.y * f32(shaderParams.width) + pos.x) + triple32(id.x + u32(shaderParams.time * 100000.0)));
+    var random = triple32(u32(pos.y * f32(shaderParams.width) + pos.x) + triple32(agentId + triple32(shaderParams.frame + triple32(shaderParams.seed))));
 
 	// Steer based on sensory data
-    var sensorAngleRad = speciesSettings.sensorAngleDegrees * PI_OVER_180;
-    var weightForward = sense(agent, speciesSettings, 0.0);
-    var weightLeft = sense(agent, speciesSettings, sensorAngleRad);
-    var weightRight = sense(agent, speciesSettings, -sensorAngleRad);
+    var sensorAngleRad = settings.sensorAngleDegrees * PI_OVER_180;
+    var weightForward = sense(agent, settings, 0.0);
+    var weightLeft = sense(agent, settings, sensorAngleRad);
+    var weightRight = sense(agent, settings, -sensorAngleRad);
 
 
     var randomSteerStrength = scaleToRange01(random);
-    var turnSpeed = speciesSettings.turnSpeed * TWO_PI;
+    var turnSpeed = settings.turnSpeed * TWO_PI;
 
 	// Continue in same direction
 	//if (weightForward > weightLeft && weightForward > weightRight) {
@@ -124,12 +200,12 @@ fn update(@builtin(global_invocation_id) id: vec3<u32>) {
     let shouldTurnRandomly = clamp((sign(weightLeft - weightForward) + sign(weightRight - weightForward)) / 2.0, 0.0, 1.0);
     let shouldTurnNormally = abs((sign(weightForward - weightLeft) - sign(weightForward - weightRight)) / 2.0);
 	//if (weightForward < weightLeft && weightForward < weightRight) {
-    agents.data[id.x].angle = agents.data[id.x].angle + (((randomSteerStrength - 0.5) * 2.0 * turnSpeed * shaderParams.delta) * shouldTurnRandomly);
+    agents.data[index].angle = agents.data[index].angle + (((randomSteerStrength - 0.5) * 2.0 * turnSpeed * shaderParams.delta) * shouldTurnRandomly);
 	//}
 
 	// Turn right
 	//elseif (weightRight > weightLeft) {
-    agents.data[id.x].angle = agents.data[id.x].angle + (shouldTurnNormally * sign(weightLeft - weightRight) * (randomSteerStrength * turnSpeed * shaderParams.delta));
+    agents.data[index].angle = agents.data[index].angle + (shouldTurnNormally * sign(weightLeft - weightRight) * (randomSteerStrength * turnSpeed * shaderParams.delta));
 	//}
 	// Turn left
 	//elseif (weightLeft > weightRight) {
@@ -139,34 +215,55 @@ fn update(@builtin(global_invocation_id) id: vec3<u32>) {
 
 	// Update position
     var direction = vec2<f32>(cos(agent.angle), sin(agent.angle));
-    var newPos: vec2<f32> = pos + direction * shaderParams.delta * speciesSettings.moveSpeed;
+    var newPos: vec2<f32> = pos + direction * shaderParams.delta * settings.moveSpeed;
 
 	
-	// Clamp position to map boundaries, and pick new random move dir if hit boundary
-	//if (newPos.x < 0.0 || newPos.x >= f32(shaderParams.width) || newPos.y < 0.0 || newPos.y >= f32(shaderParams.height)) {
-	//	random = triple32(random);
-	//	var randomAngle = scaleToRange01(random) * TWO_PI;
-//
-//		newPos.x = min(f32(shaderParams.width - 1.0),max(0.0, newPos.x));
-//		newPos.y = min(f32(shaderParams.height - 1.0),max(0.0, newPos.y));
-//		agents.data[id.x].angle = randomAngle;
-//	}
-	// else {
-	//     // var offset : i32 = i32(newPos.y) * i32(shaderParams.width) * 4 + i32(newPos.x) * 4;
-	// 	// var oldTrail : vec4<f32> = vec4<f32>(TrailMap.elements[offset], TrailMap.elements[offset + 1], TrailMap.elements[offset + 2], TrailMap.elements[offset + 3]);
-    //     // var newVal : vec4<f32> = min(vec4<f32>(1., 1., 1., 1.), oldTrail + vec4<f32>(1.0) * vec4<f32>(shaderParams.delta * shaderParams.delta, shaderParams.delta * shaderParams.delta, shaderParams.delta * shaderParams.delta, shaderParams.delta * shaderParams.delta));
-	// 	// TrailMap.elements[offset] = newVal.x;
-	// 	// TrailMap.elements[offset + 1] = newVal.y;
-	// 	// TrailMap.elements[offset + 2] = newVal.z;
-	// 	// TrailMap.elements[offset + 3] = newVal.w;
-	// }
-    agents.data[id.x].posX = newPos.x;
-    agents.data[id.x].posY = newPos.y;
+    // Keep the agent on the map
+    let size = vec2<f32>(shaderParams.width, shaderParams.height);
+    if any(newPos < vec2<f32>(0.0)) || any(newPos >= size) {
+        switch shaderParams.boundaryMode {
+            case BOUNDARY_WRAP: {
+                newPos = vec2<f32>(wrapCoordinate(newPos.x, size.x), wrapCoordinate(newPos.y, size.y));
+            }
+            case BOUNDARY_REFLECT: {
+                var angle = agents.data[index].angle;
+                if newPos.x < 0.0 || newPos.x >= size.x {
+                    angle = PI - angle;
+                }
+                if newPos.y < 0.0 || newPos.y >= size.y {
+                    angle = -angle;
+                }
+                agents.data[index].angle = angle;
+                newPos = clampToMap(vec2<f32>(reflectCoordinate(newPos.x, size.x), reflectCoordinate(newPos.y, size.y)));
+            }
+            case BOUNDARY_RESPAWN: {
+                let respawned = placeAgent(triple32(random), agent.speciesIndex);
+                agents.data[index].angle = respawned.angle;
+                // Spawn regions may reach past the edges
+                newPos = clampToMap(vec2<f32>(respawned.posX, respawned.posY));
+            }
+            // BOUNDARY_CLAMP
+            default: {
+                random = triple32(random);
+                agents.data[index].angle = scaleToRange01(random) * TWO_PI;
+                newPos = clampToMap(newPos);
+            }
+        }
+    }
+    // Walls block the way: stay put and turn somewhere else. Agents that
+    // started inside a wall may walk out of it.
+    if isWall(vec2<i32>(newPos)) && !isWall(vec2<i32>(pos)) {
+        random = triple32(random);
+        agents.data[index].angle = scaleToRange01(random) * TWO_PI;
+        newPos = pos;
+    }
+
+    agents.data[index].posX = newPos.x;
+    agents.data[index].posY = newPos.y;
     let intNewPos = vec2<i32>(i32(newPos.x), i32(newPos.y));
-    let pix = textureLoad(SourceTexture, intNewPos);
+    let pix = textureLoad(SourceTexture, intNewPos, agent.speciesIndex);
     
     // Make trail deposition frame-rate independent using delta time
-    let baseTrailIntensity = 0.009;
-    let trailIntensity = baseTrailIntensity * shaderParams.delta * 10.0;
-    textureStore(SourceTexture, intNewPos, vec4<f32>(pix.r, 0.0, trailIntensity + pix.r, 1.0));
+    let trailIntensity = shaderParams.depositIntensity * shaderParams.delta * 10.0;
+    textureStore(SourceTexture, intNewPos, agent.speciesIndex, vec4<f32>(pix.r, 0.0, trailIntensity + pix.r, 1.0));
 }
\ No newline at end of file
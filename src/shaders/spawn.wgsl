@@ -0,0 +1,45 @@
+// Initial agent placement. Every agent is derived from the seed and its own
+// index only, so the population is the same however it is dispatched, and
+// `spawn.rs` can reproduce it on the CPU. `placeAgent` and the RNG come from
+// `place.wgsl`, which is appended to this module.
+
+struct Agent {
+    posX: f32,
+    posY: f32,
+    angle: f32,
+    speciesIndex: u32
+};
+struct Agents {
+    data: array<Agent>
+};
+
+struct SpawnParams {
+    mode: u32,
+    seed: u32,
+    numSpecies: u32,
+    radius: f32,
+    width: f32,
+    height: f32
+};
+
+struct AgentChunk {
+    firstAgent: u32,
+    numAgents: u32
+};
+
+@group(0) @binding(0) var<uniform> spawnParams: SpawnParams;
+@group(0) @binding(1) var<storage, read_write> agents: Agents;
+@group(0) @binding(2) var<uniform> agentChunk: AgentChunk;
+// Stretched over the whole map; only read by SPAWN_IMAGE
+@group(0) @binding(3) var Luminance: texture_2d<f32>;
+
+@compute @workgroup_size(128,1,1)
+fn spawn(@builtin(global_invocation_id) id: vec3<u32>, @builtin(num_workgroups) numWorkgroups: vec3<u32>) {
+    let index = id.x + id.y * numWorkgroups.x * 128u;
+    if index >= agentChunk.numAgents {
+        return;
+    }
+    let agentId = agentChunk.firstAgent + index;
+
+    agents.data[index] = placeAgent(triple32(agentId + triple32(spawnParams.seed)), agentId % spawnParams.numSpecies);
+}
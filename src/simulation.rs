@@ -0,0 +1,1320 @@
+use std::{io, ops::Range, path::Path, sync::mpsc};
+
+use wgpu::{util::DeviceExt, BindGroup, BufferAddress, BufferDescriptor, BufferUsages, Device};
+
+use crate::{
+    checkpoint::Checkpoint,
+    cpu::TrailMap,
+    hot_reload::{catch_validation, check_wgsl, create_shader_module, ShaderError},
+    Agent, AgentChunk, Brush, BrushParams, Config, DiffuseSettings, FoodMap, FoodParams,
+    ObstacleMask, Relocations, RepellentMap, ShaderParams, SimulationConfig, SpawnParams, Spawner,
+    SpeciesSettings, AGENTS_PER_GROUP, DIFFUSE_TILE_SIZE,
+};
+
+/// The agent placement appended to `slime.wgsl` and `spawn.wgsl`, see
+/// [`with_placement`].
+pub const PLACE_SHADER: &str = include_str!("shaders/place.wgsl");
+
+const SPAWN_SHADER: &str = include_str!("shaders/spawn.wgsl");
+
+/// The agent buffer, trail textures and compute pipelines that make up one
+/// slime simulation.
+///
+/// Nothing here touches a window or surface: callers hand in a device and
+/// queue, advance the simulation with [`Simulation::step`] and read the trail
+/// map back from [`Simulation::trail_texture`].
+pub struct Simulation {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    compute_pipeline: wgpu::ComputePipeline,
+    /// Kept so [`Simulation::reload_update_shader`] can rebuild the pipeline
+    /// without touching the bind groups.
+    compute_pipeline_layout: wgpu::PipelineLayout,
+    /// The `slime.wgsl` and `place.wgsl` the `update` pipeline was last built
+    /// from, so either can be reloaded on its own.
+    update_source: String,
+    place_source: String,
+    /// One bind group per slice of the agent buffer, see [`agent_chunks`].
+    compute_bind_groups: Vec<(BindGroup, AgentChunk)>,
+    max_workgroups_per_dimension: u32,
+    compute_diffuse_pipeline: wgpu::ComputePipeline,
+    compute_diffuse_bind_group: BindGroup,
+    /// `consume` in `diffuse.wgsl`, only dispatched when food runs out.
+    compute_consume_pipeline: wgpu::ComputePipeline,
+    compute_diffuse_pipeline_layout: wgpu::PipelineLayout,
+    /// `paint` and `moveAgents` in `brush.wgsl`, see [`Simulation::brush`].
+    brush_paint_pipeline: wgpu::ComputePipeline,
+    brush_paint_bind_group: BindGroup,
+    brush_agents_pipeline: wgpu::ComputePipeline,
+    /// One per slice of the agent buffer, like `compute_bind_groups`.
+    brush_agents_bind_groups: Vec<(BindGroup, AgentChunk)>,
+    brush_param_buffer: wgpu::Buffer,
+    /// The agents [`crate::BrushTool::Relocate`] takes next.
+    relocations: Relocations,
+    shader_param_buffer: wgpu::Buffer,
+    species_param_buffer: wgpu::Buffer,
+    agent_buffer: wgpu::Buffer,
+    ping_texture: wgpu::Texture,
+    pong_texture: wgpu::Texture,
+    obstacle_texture: wgpu::Texture,
+    obstacle_colour: [f32; 4],
+    food_buffer: wgpu::Buffer,
+    food_params: FoodParams,
+    repellent_buffer: wgpu::Buffer,
+    shader_param_data: ShaderParams,
+    species_param_data: Vec<SpeciesSettings>,
+    width: u32,
+    height: u32,
+    num_agents: u32,
+}
+
+impl Simulation {
+    /// Device descriptor with the features the compute shaders rely on
+    /// (read-write `rgba32float` storage textures) and the best limits
+    /// `adapter` supports, so large grids and agent counts only fail if the
+    /// hardware really can't hold them. Check the [`SimulationConfig`] against
+    /// the resulting `Device::limits` with [`SimulationConfig::check_limits`].
+    pub fn device_descriptor(adapter: &wgpu::Adapter) -> wgpu::DeviceDescriptor<'static> {
+        wgpu::DeviceDescriptor {
+            required_features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+            required_limits: adapter.limits(),
+            memory_hints: wgpu::MemoryHints::Performance,
+            experimental_features: wgpu::ExperimentalFeatures::disabled(),
+            trace: wgpu::Trace::Off,
+            label: None,
+        }
+    }
+
+    /// Creates the simulation and places its agents with [`Simulation::respawn`].
+    /// `obstacles` is fixed for the lifetime of the simulation, while `food`
+    /// and `repellent` are only where they start and must cover a map of the
+    /// configured size.
+    pub fn new(
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        config: &Config,
+        spawner: &Spawner,
+        obstacles: &ObstacleMask,
+        food: &FoodMap,
+        repellent: &RepellentMap,
+    ) -> Self {
+        let SimulationConfig {
+            width,
+            height,
+            num_agents,
+            ..
+        } = config.simulation;
+        let num_species = config.species.len() as u32;
+        let ping_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Ping Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: num_species,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[wgpu::TextureFormat::Rgba32Float],
+        });
+        let pong_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Pong Texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: num_species,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[wgpu::TextureFormat::Rgba32Float],
+        });
+
+        let shader_param_data = ShaderParams {
+            seed: spawner.seed(),
+            ..ShaderParams::from_config(config)
+        };
+        let shader_param_slice = &[shader_param_data];
+        let shader_param_slice: &[u8] = bytemuck::cast_slice(shader_param_slice);
+
+        let shader_param_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Shader Parameter Buffer"),
+            contents: shader_param_slice,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST, // | wgpu::BufferUsages::MAP_WRITE,
+        });
+        let species_param_data = config.species.clone();
+        let species_param_slice: &[u8] = bytemuck::cast_slice(&species_param_data);
+
+        let species_param_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Species Parameter Buffer"),
+            contents: species_param_slice,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+        let compute_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    // Shader Parameter Buffer
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(shader_param_slice.len() as _),
+                        },
+                        count: None,
+                    },
+                    // Species Parameter Buffer
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(species_param_slice.len() as _),
+                        },
+                        count: None,
+                    },
+                    // Agents Buffer
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                std::mem::size_of::<Agent>() as _
+                            ),
+                        },
+                        count: None,
+                    },
+                    // Storage Texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::ReadWrite,
+                            format: wgpu::TextureFormat::Rgba32Float,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                        },
+                        count: None,
+                    },
+                    // Agent Chunk Buffer
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                std::mem::size_of::<AgentChunk>() as _,
+                            ),
+                        },
+                        count: None,
+                    },
+                    // Spawn Parameter Buffer, for respawning agents
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                std::mem::size_of::<SpawnParams>() as _,
+                            ),
+                        },
+                        count: None,
+                    },
+                    // Spawn Luminance Texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 6,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // Obstacle Texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 7,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // Repellent Buffer
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 8,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: true },
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(4),
+                        },
+                        count: None,
+                    },
+                ],
+                label: None,
+            });
+        let compute_diffuse_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[
+                    // Shader Parameter Buffer
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(shader_param_slice.len() as _),
+                        },
+                        count: None,
+                    },
+                    // Ping (Read) Texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 1,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::ReadOnly,
+                            format: wgpu::TextureFormat::Rgba32Float,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                        },
+                        count: None,
+                    },
+                    // Pong (Write) Texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 2,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format: wgpu::TextureFormat::Rgba32Float,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
+                        },
+                        count: None,
+                    },
+                    // Obstacle Texture
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 3,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        count: None,
+                    },
+                    // Food Parameter Buffer
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 4,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(
+                                std::mem::size_of::<FoodParams>() as _,
+                            ),
+                        },
+                        count: None,
+                    },
+                    // Food Buffer, eaten from by `consume`
+                    wgpu::BindGroupLayoutEntry {
+                        binding: 5,
+                        visibility: wgpu::ShaderStages::COMPUTE,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Storage { read_only: false },
+                            has_dynamic_offset: false,
+                            min_binding_size: wgpu::BufferSize::new(4),
+                        },
+                        count: None,
+                    },
+                ],
+                label: None,
+            });
+        let compute_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("compute"),
+                bind_group_layouts: &[&compute_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        let compute_diffuse_pipeline_layout =
+            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("compute diffuse"),
+                bind_group_layouts: &[&compute_diffuse_bind_group_layout],
+                push_constant_ranges: &[],
+            });
+        // // Compute shader pipeline
+        let update_source = include_str!("shaders/slime.wgsl").to_owned();
+        let place_source = PLACE_SHADER.to_owned();
+        let compute_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Compute Shader"),
+            source: wgpu::ShaderSource::Wgsl(with_placement(&update_source, &place_source).into()),
+        });
+        let compute_diffuse_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Compute Diffuse Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/diffuse.wgsl").into()),
+        });
+        let compute_pipeline = update_pipeline(&device, &compute_pipeline_layout, &compute_shader);
+        let (compute_diffuse_pipeline, compute_consume_pipeline) = diffuse_pipelines(
+            &device,
+            &compute_diffuse_pipeline_layout,
+            &compute_diffuse_shader,
+        );
+        let brush_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Brush Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("shaders/brush.wgsl").into()),
+        });
+        let brush_paint_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                cache: None,
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                label: Some("Brush Paint Pipeline"),
+                layout: None,
+                module: &brush_shader,
+                entry_point: Some("paint"),
+            });
+        let brush_agents_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                cache: None,
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                label: Some("Brush Agents Pipeline"),
+                layout: None,
+                module: &brush_shader,
+                entry_point: Some("moveAgents"),
+            });
+        let agent_buffer = Self::build_agent_buffer(&device, num_agents);
+        let (spawn_param_buffer, luminance_view) = spawn_resources(&device, &queue, spawner);
+        let obstacle_texture = obstacles.create_texture(&device, &queue);
+        let obstacle_view = obstacle_texture.create_view(&Default::default());
+        assert_eq!((food.width, food.height), (width, height));
+        let food_param_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Food Parameter Buffer"),
+            contents: bytemuck::bytes_of(&food.params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let food_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Food Buffer"),
+            contents: bytemuck::cast_slice(&food.food),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        });
+        assert_eq!((repellent.width, repellent.height), (width, height));
+        let repellent_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Repellent Buffer"),
+            contents: bytemuck::cast_slice(&repellent.values),
+            usage: wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+        });
+        let limits = device.limits();
+        let compute_bind_groups = agent_chunks(num_agents, &limits)
+            .map(|chunk| {
+                let agent_chunk_buffer =
+                    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Agent Chunk Buffer"),
+                        contents: bytemuck::bytes_of(&chunk),
+                        usage: wgpu::BufferUsages::UNIFORM,
+                    });
+                let agent_size = std::mem::size_of::<Agent>() as BufferAddress;
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &compute_pipeline.get_bind_group_layout(0),
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: shader_param_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: species_param_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                                buffer: &agent_buffer,
+                                offset: chunk.firstAgent as BufferAddress * agent_size,
+                                size: wgpu::BufferSize::new(
+                                    chunk.numAgents as BufferAddress * agent_size,
+                                ),
+                            }),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: wgpu::BindingResource::TextureView(&array_view(
+                                &ping_texture,
+                            )),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 4,
+                            resource: agent_chunk_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 5,
+                            resource: spawn_param_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 6,
+                            resource: wgpu::BindingResource::TextureView(&luminance_view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 7,
+                            resource: wgpu::BindingResource::TextureView(&obstacle_view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 8,
+                            resource: repellent_buffer.as_entire_binding(),
+                        },
+                    ],
+                    label: None,
+                });
+                (bind_group, chunk)
+            })
+            .collect();
+
+        let brush_param_buffer = device.create_buffer(&BufferDescriptor {
+            label: Some("Brush Param Buffer"),
+            size: std::mem::size_of::<BrushParams>() as BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let brush_paint_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &brush_paint_pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: brush_param_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&array_view(&ping_texture)),
+                },
+            ],
+            label: None,
+        });
+        let brush_agents_bind_groups = agent_chunks(num_agents, &limits)
+            .map(|chunk| {
+                let agent_chunk_buffer =
+                    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Agent Chunk Buffer"),
+                        contents: bytemuck::bytes_of(&chunk),
+                        usage: wgpu::BufferUsages::UNIFORM,
+                    });
+                let agent_size = std::mem::size_of::<Agent>() as BufferAddress;
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &brush_agents_pipeline.get_bind_group_layout(0),
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: brush_param_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                                buffer: &agent_buffer,
+                                offset: chunk.firstAgent as BufferAddress * agent_size,
+                                size: wgpu::BufferSize::new(
+                                    chunk.numAgents as BufferAddress * agent_size,
+                                ),
+                            }),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: agent_chunk_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 4,
+                            resource: wgpu::BindingResource::TextureView(&obstacle_view),
+                        },
+                    ],
+                    label: None,
+                });
+                (bind_group, chunk)
+            })
+            .collect();
+
+        let compute_diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &compute_diffuse_pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: shader_param_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&array_view(&ping_texture)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&array_view(&pong_texture)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(&obstacle_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: food_param_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: food_buffer.as_entire_binding(),
+                },
+            ],
+            label: None,
+        });
+
+        let simulation = Self {
+            device,
+            queue,
+            compute_pipeline,
+            compute_pipeline_layout,
+            update_source,
+            place_source,
+            compute_bind_groups,
+            max_workgroups_per_dimension: limits.max_compute_workgroups_per_dimension,
+            compute_diffuse_pipeline,
+            compute_diffuse_bind_group,
+            compute_consume_pipeline,
+            compute_diffuse_pipeline_layout,
+            brush_paint_pipeline,
+            brush_paint_bind_group,
+            brush_agents_pipeline,
+            brush_agents_bind_groups,
+            brush_param_buffer,
+            relocations: Relocations::default(),
+            shader_param_buffer,
+            species_param_buffer,
+            agent_buffer,
+            ping_texture,
+            pong_texture,
+            obstacle_texture,
+            obstacle_colour: obstacles.colour,
+            food_buffer,
+            food_params: food.params,
+            repellent_buffer,
+            shader_param_data,
+            species_param_data,
+            width,
+            height,
+            num_agents,
+        };
+        simulation.respawn(spawner);
+        simulation
+    }
+
+    /// Rebuilds the `update` pipeline from a new `slime.wgsl`. The agents and
+    /// trail map are untouched, and the old pipeline stays in use if `source`
+    /// doesn't compile or no longer fits the bindings.
+    pub fn reload_update_shader(&mut self, path: &Path, source: &str) -> Result<(), ShaderError> {
+        let update_source = with_placement(source, &self.place_source);
+        check_wgsl(path, &update_source)?;
+        self.rebuild_update_pipeline(&update_source)?;
+        self.update_source = source.to_owned();
+        Ok(())
+    }
+
+    /// Rebuilds the `update` pipeline with a new `place.wgsl`, which respawned
+    /// agents are placed by, like [`Simulation::reload_update_shader`].
+    /// [`Simulation::respawn`] uses it from then on too, so it has to build
+    /// as part of `spawn.wgsl` as well.
+    pub fn reload_place_shader(&mut self, path: &Path, source: &str) -> Result<(), ShaderError> {
+        check_placement(path, source, &self.update_source)?;
+        let spawn_module = create_shader_module(
+            &self.device,
+            "Spawn Shader",
+            &with_placement(source, SPAWN_SHADER),
+        )?;
+        catch_validation(&self.device, || spawn_pipeline(&self.device, &spawn_module))?;
+        self.rebuild_update_pipeline(&with_placement(source, &self.update_source))?;
+        self.place_source = source.to_owned();
+        Ok(())
+    }
+
+    /// `source` has passed [`check_wgsl`].
+    fn rebuild_update_pipeline(&mut self, source: &str) -> Result<(), ShaderError> {
+        let module = create_shader_module(&self.device, "Compute Shader", source)?;
+        self.compute_pipeline = catch_validation(&self.device, || {
+            update_pipeline(&self.device, &self.compute_pipeline_layout, &module)
+        })?;
+        Ok(())
+    }
+
+    /// Rebuilds the `diffuse` and `consume` pipelines from a new
+    /// `diffuse.wgsl`, like [`Simulation::reload_update_shader`].
+    pub fn reload_diffuse_shader(&mut self, path: &Path, source: &str) -> Result<(), ShaderError> {
+        check_wgsl(path, source)?;
+        let module = create_shader_module(&self.device, "Compute Diffuse Shader", source)?;
+        (self.compute_diffuse_pipeline, self.compute_consume_pipeline) =
+            catch_validation(&self.device, || {
+                diffuse_pipelines(&self.device, &self.compute_diffuse_pipeline_layout, &module)
+            })?;
+        Ok(())
+    }
+
+    /// Left uninitialised, `spawn.wgsl` fills it in.
+    fn build_agent_buffer(device: &Device, num_agents: u32) -> wgpu::Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some("Agent Buffer"),
+            size: num_agents as BufferAddress * std::mem::size_of::<Agent>() as BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX
+                | wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_DST
+                | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Replaces every agent with a fresh one placed by `spawn.wgsl`, exactly
+    /// where [`Spawner::spawn_agents`] would put it. The trail map is kept.
+    pub fn respawn(&self, spawner: &Spawner) {
+        let device = &self.device;
+        let spawn_shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Spawn Shader"),
+            source: wgpu::ShaderSource::Wgsl(
+                with_placement(SPAWN_SHADER, &self.place_source).into(),
+            ),
+        });
+        let spawn_pipeline = spawn_pipeline(device, &spawn_shader);
+        let (spawn_param_buffer, luminance_view) = spawn_resources(device, &self.queue, spawner);
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Spawn Encoder"),
+        });
+        {
+            let mut spawn_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Spawn Pass"),
+                ..Default::default()
+            });
+            spawn_pass.set_pipeline(&spawn_pipeline);
+            let agent_size = std::mem::size_of::<Agent>() as BufferAddress;
+            for (_, chunk) in &self.compute_bind_groups {
+                let agent_chunk_buffer =
+                    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some("Agent Chunk Buffer"),
+                        contents: bytemuck::bytes_of(chunk),
+                        usage: wgpu::BufferUsages::UNIFORM,
+                    });
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &spawn_pipeline.get_bind_group_layout(0),
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: spawn_param_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                                buffer: &self.agent_buffer,
+                                offset: chunk.firstAgent as BufferAddress * agent_size,
+                                size: wgpu::BufferSize::new(
+                                    chunk.numAgents as BufferAddress * agent_size,
+                                ),
+                            }),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: agent_chunk_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: wgpu::BindingResource::TextureView(&luminance_view),
+                        },
+                    ],
+                    label: None,
+                });
+                let (x, y) = agent_workgroups(chunk.numAgents, self.max_workgroups_per_dimension);
+                spawn_pass.set_bind_group(0, &bind_group, &[]);
+                spawn_pass.dispatch_workgroups(x, y, 1);
+            }
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+    pub fn device(&self) -> &wgpu::Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &wgpu::Queue {
+        &self.queue
+    }
+
+    /// The `Rgba32Float` trail map, with one array layer per species. The red
+    /// channel holds the trail intensity after the most recent
+    /// [`Simulation::step`].
+    pub fn trail_texture(&self) -> &wgpu::Texture {
+        &self.ping_texture
+    }
+
+    /// The `R8Unorm` [`ObstacleMask`] the simulation was created with.
+    pub fn obstacle_texture(&self) -> &wgpu::Texture {
+        &self.obstacle_texture
+    }
+
+    /// The colour the colour passes draw walls in, see [`ObstacleMask::colour`].
+    pub fn obstacle_colour(&self) -> [f32; 4] {
+        self.obstacle_colour
+    }
+
+    pub fn agent_buffer(&self) -> &wgpu::Buffer {
+        &self.agent_buffer
+    }
+
+    /// Replaces the agents on the GPU, e.g. to start from the same population
+    /// as a [`crate::cpu::CpuSimulation`]. `agents` must hold
+    /// [`Simulation::num_agents`] entries.
+    pub fn write_agents(&self, agents: &[Agent]) {
+        assert_eq!(agents.len(), self.num_agents as usize);
+        self.queue
+            .write_buffer(&self.agent_buffer, 0, bytemuck::cast_slice(agents));
+    }
+
+    /// Blocks until the agent buffer has been copied back to the CPU.
+    pub fn read_agents(&self) -> io::Result<Vec<Agent>> {
+        let size = self.agent_buffer.size();
+        let bytes = self.read_back(size, |encoder, staging| {
+            encoder.copy_buffer_to_buffer(&self.agent_buffer, 0, staging, 0, size);
+        })?;
+        Ok(bytemuck::cast_slice(&bytes).to_vec())
+    }
+
+    /// Blocks until every layer of the trail texture has been copied back to
+    /// the CPU, keeping the red (sensed) and blue (deposited) channels.
+    pub fn read_trail_map(&self) -> io::Result<TrailMap> {
+        let texels = self.read_trail_texels()?;
+        let mut trail_map = TrailMap::new(self.width, self.height, self.num_species());
+        trail_map.trail = texels.iter().map(|texel| texel[0]).collect();
+        trail_map.deposited = texels.iter().map(|texel| texel[2]).collect();
+        Ok(trail_map)
+    }
+
+    /// Blocks until the raw `Rgba32Float` trail texture has been copied back,
+    /// one layer after the other, without row padding.
+    pub fn read_trail_texels(&self) -> io::Result<Vec<[f32; 4]>> {
+        let bytes_per_row = padded_bytes_per_row(self.width * 16);
+        let layers = self.num_species();
+        let size = bytes_per_row as BufferAddress * (self.height * layers) as BufferAddress;
+        let bytes = self.read_back(size, |encoder, staging| {
+            encoder.copy_texture_to_buffer(
+                self.ping_texture.as_image_copy(),
+                wgpu::TexelCopyBufferInfo {
+                    buffer: staging,
+                    layout: wgpu::TexelCopyBufferLayout {
+                        offset: 0,
+                        bytes_per_row: Some(bytes_per_row),
+                        rows_per_image: Some(self.height),
+                    },
+                },
+                wgpu::Extent3d {
+                    width: self.width,
+                    height: self.height,
+                    depth_or_array_layers: layers,
+                },
+            );
+        })?;
+        // Layers follow each other in both, so rows can be counted straight through
+        Ok(bytes
+            .chunks(bytes_per_row as usize)
+            .flat_map(|row| bytemuck::cast_slice(&row[..self.width as usize * 16]))
+            .copied()
+            .collect())
+    }
+
+    /// Uploads `trail_map` into every layer of the trail texture, in the same
+    /// channels [`Simulation::read_trail_map`] reads.
+    pub fn write_trail_map(&self, trail_map: &TrailMap) {
+        assert_eq!(
+            (trail_map.width, trail_map.height, trail_map.layers),
+            (self.width, self.height, self.num_species())
+        );
+        let texels = trail_map.to_texels();
+        self.queue.write_texture(
+            self.ping_texture.as_image_copy(),
+            bytemuck::cast_slice(&texels),
+            wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(self.width * 16),
+                rows_per_image: Some(self.height),
+            },
+            wgpu::Extent3d {
+                width: self.width,
+                height: self.height,
+                depth_or_array_layers: self.num_species(),
+            },
+        );
+    }
+
+    /// Blocks until the food left on each texel has been copied back to the
+    /// CPU, as in [`FoodMap::food`].
+    pub fn read_food(&self) -> io::Result<Vec<f32>> {
+        let size = self.food_buffer.size();
+        let bytes = self.read_back(size, |encoder, staging| {
+            encoder.copy_buffer_to_buffer(&self.food_buffer, 0, staging, 0, size);
+        })?;
+        Ok(bytemuck::cast_slice(&bytes).to_vec())
+    }
+
+    /// Replaces the food left on each texel, `width * height` values row by
+    /// row.
+    pub fn write_food(&self, food: &[f32]) {
+        assert_eq!(food.len(), (self.width * self.height) as usize);
+        self.queue
+            .write_buffer(&self.food_buffer, 0, bytemuck::cast_slice(food));
+    }
+
+    /// Blocks until the repellent has been copied back to the CPU, as in
+    /// [`RepellentMap::values`].
+    pub fn read_repellent(&self) -> io::Result<Vec<f32>> {
+        let size = self.repellent_buffer.size();
+        let bytes = self.read_back(size, |encoder, staging| {
+            encoder.copy_buffer_to_buffer(&self.repellent_buffer, 0, staging, 0, size);
+        })?;
+        Ok(bytemuck::cast_slice(&bytes).to_vec())
+    }
+
+    /// Uploads `rows` of `repellent`, e.g. the ones [`RepellentMap::paint`]
+    /// changed.
+    pub fn write_repellent(&self, repellent: &RepellentMap, rows: Range<u32>) {
+        assert_eq!(
+            (repellent.width, repellent.height),
+            (self.width, self.height)
+        );
+        let texels = (rows.start * self.width) as usize..(rows.end * self.width) as usize;
+        self.queue.write_buffer(
+            &self.repellent_buffer,
+            texels.start as BufferAddress * 4,
+            bytemuck::cast_slice(&repellent.values[texels]),
+        );
+    }
+
+    /// Reads the agents, trail map, food and repellent back along with the
+    /// parameters.
+    pub fn checkpoint(&self) -> io::Result<Checkpoint> {
+        Ok(Checkpoint {
+            shader_params: self.shader_param_data,
+            species: self.species_param_data.clone(),
+            agents: self.read_agents()?,
+            trail_map: self.read_trail_map()?,
+            food: self.read_food()?,
+            repellent: self.read_repellent()?,
+        })
+    }
+
+    /// Puts the simulation back in the state `checkpoint` was taken in. It must
+    /// have the same size, agent count and number of species, see
+    /// [`Checkpoint::apply_to`].
+    pub fn restore(&mut self, checkpoint: &Checkpoint) {
+        assert_eq!(checkpoint.agents.len(), self.num_agents as usize);
+        self.write_agents(&checkpoint.agents);
+        self.write_trail_map(&checkpoint.trail_map);
+        self.write_food(&checkpoint.food);
+        assert_eq!(checkpoint.repellent.len(), checkpoint.food.len());
+        self.queue.write_buffer(
+            &self.repellent_buffer,
+            0,
+            bytemuck::cast_slice(&checkpoint.repellent),
+        );
+        self.set_species(&checkpoint.species);
+        self.shader_param_data = checkpoint.shader_params;
+        self.update_buffer(
+            &self.shader_param_buffer,
+            std::slice::from_ref(&self.shader_param_data),
+        );
+    }
+
+    fn read_back(
+        &self,
+        size: BufferAddress,
+        copy: impl FnOnce(&mut wgpu::CommandEncoder, &wgpu::Buffer),
+    ) -> io::Result<Vec<u8>> {
+        let staging_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: Some("Readback Staging Buffer"),
+            size,
+            usage: BufferUsages::COPY_DST | BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Readback Encoder"),
+            });
+        copy(&mut encoder, &staging_buffer);
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let buffer_slice = staging_buffer.slice(..);
+        map_read(&self.device, buffer_slice)?;
+        let bytes = buffer_slice.get_mapped_range().to_vec();
+        staging_buffer.unmap();
+        Ok(bytes)
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn num_agents(&self) -> u32 {
+        self.num_agents
+    }
+
+    pub fn species(&self) -> &[SpeciesSettings] {
+        &self.species_param_data
+    }
+
+    /// `species` must have as many entries as the simulation was created with,
+    /// as there is one trail layer per species.
+    pub fn set_species(&mut self, species: &[SpeciesSettings]) {
+        assert_eq!(species.len(), self.species_param_data.len());
+        self.species_param_data.copy_from_slice(species);
+        self.update_buffer(&self.species_param_buffer, &self.species_param_data);
+    }
+
+    /// The `array<SpeciesSettings>` storage buffer, for colour passes that
+    /// tint each trail layer.
+    pub fn species_buffer(&self) -> &wgpu::Buffer {
+        &self.species_param_buffer
+    }
+
+    pub fn num_species(&self) -> u32 {
+        self.species_param_data.len() as u32
+    }
+
+    pub fn diffuse(&self) -> DiffuseSettings {
+        DiffuseSettings {
+            diffuse_rate: self.shader_param_data.diffuseRate,
+            decay_rate: self.shader_param_data.decayRate,
+            deposit_intensity: self.shader_param_data.depositIntensity,
+        }
+    }
+
+    /// Takes effect on the next [`Simulation::step`], which uploads the
+    /// shader parameters anyway.
+    pub fn set_diffuse(&mut self, diffuse: DiffuseSettings) {
+        self.shader_param_data.diffuseRate = diffuse.diffuse_rate;
+        self.shader_param_data.decayRate = diffuse.decay_rate;
+        self.shader_param_data.depositIntensity = diffuse.deposit_intensity;
+    }
+
+    pub fn shader_params(&self) -> &ShaderParams {
+        &self.shader_param_data
+    }
+
+    /// Applies `brush` to the trail map or the agents straight away, between
+    /// two steps. Successive [`crate::BrushTool::Relocate`] brushes take agents
+    /// in turn, so the whole population is used before any is moved twice.
+    pub fn brush(&mut self, brush: &Brush) {
+        let params = brush.params(&self.shader_param_data, &mut self.relocations);
+        self.queue
+            .write_buffer(&self.brush_param_buffer, 0, bytemuck::bytes_of(&params));
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Brush Encoder"),
+            });
+        {
+            let mut brush_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Brush Pass"),
+                ..Default::default()
+            });
+            if brush.moves_agents() {
+                brush_pass.set_pipeline(&self.brush_agents_pipeline);
+                for (bind_group, chunk) in &self.brush_agents_bind_groups {
+                    let (x, y) =
+                        agent_workgroups(chunk.numAgents, self.max_workgroups_per_dimension);
+                    brush_pass.set_bind_group(0, bind_group, &[]);
+                    brush_pass.dispatch_workgroups(x, y, 1);
+                }
+            } else {
+                // The bounding box of the disk, see `texel_range` in food.rs
+                let size =
+                    ((2.0 * brush.radius.max(0.0) + 2.0) as u32).min(self.width.max(self.height));
+                let groups = size.div_ceil(DIFFUSE_TILE_SIZE);
+                brush_pass.set_pipeline(&self.brush_paint_pipeline);
+                brush_pass.set_bind_group(0, &self.brush_paint_bind_group, &[]);
+                brush_pass.dispatch_workgroups(groups, groups, self.num_species());
+            }
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Advances the simulation by `dt` seconds: one agent `update` pass, one
+    /// `consume` pass if food runs out, and one `diffuse` pass, with the result
+    /// copied back into the trail map.
+    pub fn step(&mut self, dt: f32) {
+        self.shader_param_data.delta = dt;
+        self.update_buffer(
+            &self.shader_param_buffer,
+            std::slice::from_ref(&self.shader_param_data),
+        );
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Command Encoder"),
+            });
+        self.encode_step(&mut encoder);
+        // submit will accept anything that implements IntoIter
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.shader_param_data.frame = self.shader_param_data.frame.wrapping_add(1);
+    }
+
+    fn encode_step(&self, encoder: &mut wgpu::CommandEncoder) {
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass"),
+                ..Default::default()
+            });
+            compute_pass.set_pipeline(&self.compute_pipeline);
+            for (bind_group, chunk) in &self.compute_bind_groups {
+                let (x, y) = agent_workgroups(chunk.numAgents, self.max_workgroups_per_dimension);
+                compute_pass.set_bind_group(0, bind_group, &[]);
+                compute_pass.dispatch_workgroups(x, y, 1);
+            }
+        }
+        if self.food_params.depletion > 0.0 {
+            let mut compute_consume_pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Compute Consume Pass"),
+                    ..Default::default()
+                });
+            compute_consume_pass.set_pipeline(&self.compute_consume_pipeline);
+            compute_consume_pass.set_bind_group(0, &self.compute_diffuse_bind_group, &[]);
+            compute_consume_pass.dispatch_workgroups(
+                self.width.div_ceil(DIFFUSE_TILE_SIZE),
+                self.height.div_ceil(DIFFUSE_TILE_SIZE),
+                1,
+            );
+        }
+        {
+            let mut compute_diffuse_pass =
+                encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Compute Diffuse Pass"),
+                    ..Default::default()
+                });
+            compute_diffuse_pass.set_pipeline(&self.compute_diffuse_pipeline);
+            compute_diffuse_pass.set_bind_group(0, &self.compute_diffuse_bind_group, &[]);
+            compute_diffuse_pass.dispatch_workgroups(
+                self.width.div_ceil(DIFFUSE_TILE_SIZE),
+                self.height.div_ceil(DIFFUSE_TILE_SIZE),
+                self.num_species(),
+            );
+        }
+        {
+            encoder.copy_texture_to_texture(
+                self.pong_texture.as_image_copy(),
+                self.ping_texture.as_image_copy(),
+                wgpu::Extent3d {
+                    width: self.width,
+                    height: self.height,
+                    depth_or_array_layers: self.num_species(),
+                },
+            );
+        }
+    }
+
+    fn update_buffer<T: bytemuck::Pod + Send + Sync + std::fmt::Debug>(
+        &self,
+        buffer: &wgpu::Buffer,
+        data: &[T],
+    ) {
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        // dbg!(&data);
+        // self.queue.write_buffer(buffer, 0, bytemuck::bytes_of(data));
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Command Encoder"),
+            });
+        let device_buffer = self.device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: bytes.len() as u64,
+            usage: BufferUsages::COPY_SRC,
+            mapped_at_creation: true,
+        });
+        let buffer_slice = device_buffer.slice(..);
+        let _ = self.device.poll(wgpu::PollType::wait_indefinitely());
+        buffer_slice.get_mapped_range_mut()[..bytes.len()].copy_from_slice(bytes);
+        device_buffer.unmap();
+        encoder.copy_buffer_to_buffer(&device_buffer, 0, buffer, 0, bytes.len() as BufferAddress); //A mutable borrow to the command encoder is needed here in order to update uniforms
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+/// `update` in `slime.wgsl`.
+fn update_pipeline(
+    device: &Device,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        cache: None,
+        compilation_options: wgpu::PipelineCompilationOptions::default(),
+        label: Some("Compute Pipeline"),
+        layout: Some(layout),
+        module,
+        entry_point: Some("update"),
+    })
+}
+
+/// Maps `slice` for reading and blocks until it is. A mapping that fails,
+/// e.g. because the device was lost, is an error rather than a panic.
+pub fn map_read(device: &Device, slice: wgpu::BufferSlice) -> io::Result<()> {
+    let (sender, receiver) = mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+    device
+        .poll(wgpu::PollType::wait_indefinitely())
+        .map_err(io::Error::other)?;
+    receiver
+        .recv()
+        .map_err(|_| io::Error::other("mapping never finished"))?
+        .map_err(|e| io::Error::other(format!("could not read back from the GPU: {}", e)))
+}
+
+/// `source` followed by `place`, the shared agent placement in `place.wgsl`.
+/// WGSL declarations may come after their uses, so the order only matters
+/// for the line numbers in naga's reports.
+pub fn with_placement(source: &str, place: &str) -> String {
+    format!("{}\n{}", source, place)
+}
+
+/// Checks a new `place.wgsl` with naga as part of both modules it is
+/// appended to: `slime.wgsl`, given as `update_source`, and `spawn.wgsl`.
+/// `place` goes first in each, so the line numbers in a report point into it.
+pub fn check_placement(path: &Path, place: &str, update_source: &str) -> Result<(), ShaderError> {
+    check_wgsl(path, &with_placement(place, update_source))?;
+    check_wgsl(path, &with_placement(place, SPAWN_SHADER))
+}
+
+/// `spawn` in `spawn.wgsl`, with the layout derived from the shader.
+fn spawn_pipeline(device: &Device, module: &wgpu::ShaderModule) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        cache: None,
+        compilation_options: wgpu::PipelineCompilationOptions::default(),
+        label: Some("Spawn Pipeline"),
+        layout: None,
+        module,
+        entry_point: Some("spawn"),
+    })
+}
+
+/// `diffuse` and `consume` in `diffuse.wgsl`, which share a layout.
+fn diffuse_pipelines(
+    device: &Device,
+    layout: &wgpu::PipelineLayout,
+    module: &wgpu::ShaderModule,
+) -> (wgpu::ComputePipeline, wgpu::ComputePipeline) {
+    let pipeline = |label, entry_point| {
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            cache: None,
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            label: Some(label),
+            layout: Some(layout),
+            module,
+            entry_point: Some(entry_point),
+        })
+    };
+    (
+        pipeline("Compute Diffuse Pipeline", "diffuse"),
+        pipeline("Compute Consume Pipeline", "consume"),
+    )
+}
+
+/// The spawn parameter buffer and luminance texture `spawn.wgsl` places
+/// agents with, also bound to `update` for [`crate::BoundaryMode::Respawn`].
+fn spawn_resources(
+    device: &Device,
+    queue: &wgpu::Queue,
+    spawner: &Spawner,
+) -> (wgpu::Buffer, wgpu::TextureView) {
+    let spawn_param_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Spawn Param Buffer"),
+        contents: bytemuck::bytes_of(spawner.params()),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    let luminance = spawner.luminance();
+    let luminance_size = wgpu::Extent3d {
+        width: luminance.width,
+        height: luminance.height,
+        depth_or_array_layers: 1,
+    };
+    let luminance_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Spawn Luminance Texture"),
+        size: luminance_size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::R32Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    queue.write_texture(
+        luminance_texture.as_image_copy(),
+        bytemuck::cast_slice(&luminance.values),
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(luminance.width * 4),
+            rows_per_image: None,
+        },
+        luminance_size,
+    );
+    let luminance_view = luminance_texture.create_view(&Default::default());
+    (spawn_param_buffer, luminance_view)
+}
+
+/// A view of every layer of `texture`, which stays an array view even when
+/// there is only one species.
+fn array_view(texture: &wgpu::Texture) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    })
+}
+
+/// Splits `num_agents` into slices that each fit in one storage buffer binding.
+/// Slices are whole workgroups of [`Agent`]s, which also keeps their byte
+/// offsets aligned to `min_storage_buffer_offset_alignment` (at most 256).
+fn agent_chunks(num_agents: u32, limits: &wgpu::Limits) -> impl Iterator<Item = AgentChunk> {
+    let max_agents = limits.max_storage_buffer_binding_size / std::mem::size_of::<Agent>() as u32;
+    let chunk_size = (max_agents / AGENTS_PER_GROUP).max(1) * AGENTS_PER_GROUP;
+    (0..num_agents)
+        .step_by(chunk_size as usize)
+        .map(move |first_agent| AgentChunk {
+            firstAgent: first_agent,
+            numAgents: chunk_size.min(num_agents - first_agent),
+        })
+}
+
+/// Workgroup counts covering `num_agents`, wrapping into y once x reaches
+/// `max_per_dimension`. `slime.wgsl` linearises the index again.
+fn agent_workgroups(num_agents: u32, max_per_dimension: u32) -> (u32, u32) {
+    let groups = num_agents.div_ceil(AGENTS_PER_GROUP);
+    if groups <= max_per_dimension {
+        (groups, 1)
+    } else {
+        (max_per_dimension, groups.div_ceil(max_per_dimension))
+    }
+}
+
+/// Rows in a texture-to-buffer copy must be aligned to 256 bytes.
+pub fn padded_bytes_per_row(unpadded_bytes_per_row: u32) -> u32 {
+    unpadded_bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+        * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT
+}
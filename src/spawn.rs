@@ -0,0 +1,231 @@
+//! Initial agent placement.
+//!
+//! [`Spawner`] resolves the `[spawn]` section of a [`Config`] (seed and
+//! luminance image included) once, so the GPU pass in `spawn.wgsl` and the CPU
+//! port below place exactly the same population.
+
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cpu::{scale_to_range01, triple32},
+    Agent, Config, ConfigError, SpawnParams,
+};
+
+/// Matches the `SPAWN_*` constants in `place.wgsl`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpawnMode {
+    /// Uniformly over a disk of `radius` around the centre, random headings
+    #[default]
+    Disk = 0,
+    /// On a circle of `radius`, heading for the centre
+    RingInward = 1,
+    /// On a circle of `radius`, heading away from the centre
+    RingOutward = 2,
+    /// Anywhere on the map, random headings
+    Random = 3,
+    /// All in the centre, random headings
+    Point = 4,
+    /// Anywhere on the map, more densely where `image` is brighter
+    Image = 5,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "camelCase")]
+pub struct SpawnSettings {
+    pub mode: SpawnMode,
+    /// Radius in trail map texels for [`SpawnMode::Disk`] and the rings.
+    pub radius: f32,
+    /// Image whose luminance is the spawn density for [`SpawnMode::Image`],
+    /// stretched over the whole map.
+    pub image: Option<PathBuf>,
+}
+
+impl Default for SpawnSettings {
+    fn default() -> Self {
+        Self {
+            mode: SpawnMode::Disk,
+            radius: 300.0,
+            image: None,
+        }
+    }
+}
+
+/// Luminance of an image in `[0, 1]`, row by row.
+#[derive(Clone, Debug)]
+pub struct LuminanceMap {
+    pub width: u32,
+    pub height: u32,
+    pub values: Vec<f32>,
+}
+
+impl LuminanceMap {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let image = image::open(path)
+            .map_err(|e| ConfigError::Image {
+                path: path.to_owned(),
+                error: e.to_string(),
+            })?
+            .into_luma8();
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            values: image.pixels().map(|p| p[0] as f32 / 255.0).collect(),
+        })
+    }
+
+    /// A 1x1 map for modes that do not read it, as the GPU pass still needs a
+    /// texture bound.
+    fn uniform() -> Self {
+        Self {
+            width: 1,
+            height: 1,
+            values: vec![1.0],
+        }
+    }
+
+    /// Mirrors `luminanceAt` in `place.wgsl`.
+    fn at(&self, params: &SpawnParams, x: f32, y: f32) -> f32 {
+        let u = x / params.width;
+        let v = y / params.height;
+        let texel_x = ((u * self.width as f32) as u32).min(self.width - 1);
+        let texel_y = ((v * self.height as f32) as u32).min(self.height - 1);
+        self.values[(texel_y * self.width + texel_x) as usize]
+    }
+}
+
+/// Candidate positions tried per agent by [`SpawnMode::Image`] before settling
+/// for the last one.
+const IMAGE_ATTEMPTS: u32 = 64;
+
+const PI: f32 = std::f32::consts::PI;
+const TWO_PI: f32 = std::f32::consts::TAU;
+
+/// Everything needed to place agents: the shader parameters with the seed
+/// resolved, and the luminance map.
+#[derive(Clone, Debug)]
+pub struct Spawner {
+    mode: SpawnMode,
+    params: SpawnParams,
+    num_agents: u32,
+    luminance: LuminanceMap,
+}
+
+impl Spawner {
+    /// Draws a fresh seed if the config has none, and loads the spawn image if
+    /// the mode needs it.
+    pub fn new(config: &Config) -> Result<Self, ConfigError> {
+        let spawn = &config.spawn;
+        let luminance = match (spawn.mode, &spawn.image) {
+            (SpawnMode::Image, Some(path)) => LuminanceMap::load(path)?,
+            _ => LuminanceMap::uniform(),
+        };
+        Ok(Self {
+            mode: spawn.mode,
+            params: SpawnParams {
+                mode: spawn.mode as u32,
+                seed: config.simulation.seed.unwrap_or_else(rand::random),
+                numSpecies: config.species.len() as u32,
+                radius: spawn.radius,
+                width: config.simulation.width as f32,
+                height: config.simulation.height as f32,
+            },
+            num_agents: config.simulation.num_agents,
+            luminance,
+        })
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.params.seed
+    }
+
+    pub fn params(&self) -> &SpawnParams {
+        &self.params
+    }
+
+    pub fn luminance(&self) -> &LuminanceMap {
+        &self.luminance
+    }
+
+    /// The whole population, placed on all cores.
+    pub fn spawn_agents(&self) -> Vec<Agent> {
+        (0..self.num_agents)
+            .into_par_iter()
+            .map(|id| self.spawn_agent(id))
+            .collect()
+    }
+
+    /// One invocation of `spawn.wgsl::spawn` for the agent at index `id`.
+    pub fn spawn_agent(&self, id: u32) -> Agent {
+        let params = &self.params;
+        self.place_agent(
+            triple32(id.wrapping_add(triple32(params.seed))),
+            id % params.numSpecies,
+        )
+    }
+
+    /// Mirrors `placeAgent` in `place.wgsl`: a new agent of species
+    /// `species_index` somewhere in the spawn region, drawn from the RNG
+    /// `state`. [`crate::BoundaryMode::Respawn`] uses it to bring agents back.
+    pub fn place_agent(&self, mut state: u32, species_index: u32) -> Agent {
+        let params = &self.params;
+        let mut random01 = || {
+            state = triple32(state);
+            scale_to_range01(state)
+        };
+        let centre_x = params.width / 2.0;
+        let centre_y = params.height / 2.0;
+
+        let (pos_x, pos_y, angle) = match self.mode {
+            SpawnMode::RingInward | SpawnMode::RingOutward => {
+                let theta = random01() * TWO_PI;
+                let angle = if self.mode == SpawnMode::RingInward {
+                    theta + PI
+                } else {
+                    theta
+                };
+                (
+                    centre_x + theta.cos() * params.radius,
+                    centre_y + theta.sin() * params.radius,
+                    angle,
+                )
+            }
+            SpawnMode::Random => {
+                let x = random01() * params.width;
+                let y = random01() * params.height;
+                (x, y, random01() * TWO_PI)
+            }
+            SpawnMode::Point => (centre_x, centre_y, random01() * TWO_PI),
+            SpawnMode::Image => {
+                let (mut x, mut y) = (centre_x, centre_y);
+                for _ in 0..IMAGE_ATTEMPTS {
+                    x = random01() * params.width;
+                    y = random01() * params.height;
+                    if random01() < self.luminance.at(params, x, y) {
+                        break;
+                    }
+                }
+                (x, y, random01() * TWO_PI)
+            }
+            SpawnMode::Disk => {
+                let r = params.radius * random01().sqrt();
+                let theta = random01() * TWO_PI;
+                (
+                    centre_x + theta.cos() * r,
+                    centre_y + theta.sin() * r,
+                    random01() * TWO_PI,
+                )
+            }
+        };
+
+        Agent {
+            posX: pos_x,
+            posY: pos_y,
+            angle,
+            speciesIndex: species_index,
+        }
+    }
+}
@@ -0,0 +1,177 @@
+//! Fixed-size simulation steps decoupled from the frame rate.
+//!
+//! Rendered frames take however long they take; [`FixedTimestep`] banks the
+//! elapsed time and pays it out as whole steps of `1 / stepRate` seconds, so
+//! agents never move more than `moveSpeed / stepRate` texels at once and a
+//! run depends only on the seed and the number of steps taken.
+
+use crate::SimulationConfig;
+
+/// Slowest speed multiplier a config or the speed keys can set.
+pub const MIN_SPEED: f32 = 1.0 / 64.0;
+/// Fastest speed multiplier a config or the speed keys can set.
+pub const MAX_SPEED: f32 = 64.0;
+
+#[derive(Clone, Debug)]
+pub struct FixedTimestep {
+    step_size: f32,
+    max_substeps: u32,
+    speed: f32,
+    accumulator: f32,
+    paused: bool,
+    /// Steps queued by [`FixedTimestep::single_step`] while paused.
+    pending_steps: u32,
+}
+
+impl FixedTimestep {
+    pub fn new(config: &SimulationConfig) -> Self {
+        Self {
+            step_size: 1.0 / config.step_rate,
+            max_substeps: config.max_substeps,
+            speed: config.speed,
+            accumulator: 0.0,
+            paused: false,
+            pending_steps: 0,
+        }
+    }
+
+    /// Wall-clock seconds between steps.
+    pub fn step_size(&self) -> f32 {
+        self.step_size
+    }
+
+    /// The `delta` each step feeds to the shaders: the step size scaled by the
+    /// speed multiplier, so speed never changes how often steps run.
+    pub fn delta(&self) -> f32 {
+        self.step_size * self.speed
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    /// Clamped to [`MIN_SPEED`]`..=`[`MAX_SPEED`].
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+        self.pending_steps = 0;
+    }
+
+    /// Pauses if running, and queues exactly one step for the next
+    /// [`FixedTimestep::advance`].
+    pub fn single_step(&mut self) {
+        self.paused = true;
+        self.pending_steps += 1;
+    }
+
+    /// Banks `elapsed` seconds and returns how many steps are now due, at most
+    /// `max_substeps`. Time beyond the cap is dropped rather than carried
+    /// over, so a long hitch slows the simulation down for one frame instead
+    /// of making every following frame catch up.
+    ///
+    /// While paused only the queued single steps are due, and no time is
+    /// banked, so resuming does not run a burst of steps.
+    pub fn advance(&mut self, elapsed: f32) -> u32 {
+        if self.paused {
+            self.accumulator = 0.0;
+            return std::mem::take(&mut self.pending_steps).min(self.max_substeps);
+        }
+        self.accumulator += elapsed;
+        let due = (self.accumulator / self.step_size) as u32;
+        if due > self.max_substeps {
+            self.accumulator = 0.0;
+            self.max_substeps
+        } else {
+            self.accumulator -= due as f32 * self.step_size;
+            due
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Quarter-second steps, which add up exactly in `f32`.
+    fn timestep(speed: f32) -> FixedTimestep {
+        FixedTimestep::new(&SimulationConfig {
+            step_rate: 4.0,
+            max_substeps: 3,
+            speed,
+            ..SimulationConfig::default()
+        })
+    }
+
+    #[test]
+    fn fractions_of_a_step_carry_over() {
+        let mut timestep = timestep(1.0);
+        assert_eq!(timestep.advance(0.125), 0);
+        assert_eq!(timestep.advance(0.125), 1);
+        assert_eq!(timestep.advance(0.375), 1);
+        assert_eq!(timestep.advance(0.125), 1);
+    }
+
+    #[test]
+    fn time_beyond_max_substeps_is_dropped() {
+        let mut timestep = timestep(1.0);
+        assert_eq!(timestep.advance(10.0), 3);
+        assert_eq!(timestep.advance(0.125), 0);
+        assert_eq!(timestep.advance(0.75), 3);
+    }
+
+    #[test]
+    fn speed_scales_delta_but_not_the_step_rate() {
+        let mut timestep = timestep(2.0);
+        assert_eq!(timestep.step_size(), 0.25);
+        assert_eq!(timestep.delta(), 0.5);
+        assert_eq!(timestep.advance(0.5), 2);
+
+        timestep.set_speed(0.5);
+        assert_eq!(timestep.delta(), 0.125);
+        assert_eq!(timestep.advance(0.5), 2);
+    }
+
+    #[test]
+    fn paused_timesteps_bank_no_time() {
+        let mut timestep = timestep(1.0);
+        timestep.set_paused(true);
+        assert!(timestep.is_paused());
+        assert_eq!(timestep.advance(10.0), 0);
+        timestep.set_paused(false);
+        assert_eq!(timestep.advance(0.125), 0);
+    }
+
+    #[test]
+    fn pausing_clears_the_accumulator() {
+        let mut timestep = timestep(1.0);
+        assert_eq!(timestep.advance(0.125), 0);
+        timestep.set_paused(true);
+        assert_eq!(timestep.advance(0.0), 0);
+        timestep.set_paused(false);
+        // Without the banked 0.125 s this is still short of a step
+        assert_eq!(timestep.advance(0.125), 0);
+        assert_eq!(timestep.advance(0.125), 1);
+    }
+
+    #[test]
+    fn single_steps_run_exactly_once_while_paused() {
+        let mut timestep = timestep(1.0);
+        timestep.single_step();
+        assert!(timestep.is_paused());
+        timestep.single_step();
+        assert_eq!(timestep.advance(10.0), 2);
+        assert_eq!(timestep.advance(10.0), 0);
+
+        // Queued steps are dropped by pausing or resuming
+        timestep.single_step();
+        timestep.set_paused(false);
+        assert_eq!(timestep.advance(0.0), 0);
+    }
+}
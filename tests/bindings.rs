@@ -0,0 +1,81 @@
+//! Key bindings load from files, and parameter keys step within the ranges a
+//! config file allows.
+
+use slime_webgpu::{Action, Bindings, ConfigError, DiffuseSettings, Parameter, SpeciesSettings};
+
+#[test]
+fn printed_bindings_load_again() {
+    let path = std::env::temp_dir().join("slime-bindings-test.toml");
+    let mut bindings = Bindings::default();
+    bindings.keys.insert("KeyK".to_owned(), Action::Pause);
+    bindings.steps.decay_rate = 0.125;
+    std::fs::write(&path, bindings.to_toml()).unwrap();
+    assert_eq!(Bindings::load(&path).unwrap(), bindings);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn keys_table_replaces_the_defaults() {
+    let path = std::env::temp_dir().join("slime-bindings-keys-test.toml");
+    std::fs::write(
+        &path,
+        "[keys]\nKeyK = \"next-preset\"\n\n[steps]\nmoveSpeed = 5.0\n",
+    )
+    .unwrap();
+    let bindings = Bindings::load(&path).unwrap();
+    assert_eq!(bindings.keys.len(), 1);
+    assert_eq!(bindings.action("KeyK"), Some(Action::NextPreset));
+    assert_eq!(bindings.action("KeyD"), None);
+    assert_eq!(bindings.steps.move_speed, 5.0);
+    assert_eq!(bindings.steps.turn_speed, 1.0);
+
+    std::fs::write(&path, "[keys]\nKeyK = \"explode\"\n").unwrap();
+    assert!(matches!(Bindings::load(&path), Err(ConfigError::Parse(_))));
+    std::fs::write(&path, "[steps]\nsensorSize = -1.0\n").unwrap();
+    assert!(matches!(
+        Bindings::load(&path),
+        Err(ConfigError::OutOfRange { .. })
+    ));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn adjustments_stay_in_range() {
+    let mut species = vec![
+        SpeciesSettings {
+            sensorAngleDegrees: 178.0,
+            ..SpeciesSettings::default()
+        },
+        SpeciesSettings {
+            sensorAngleDegrees: 20.0,
+            ..SpeciesSettings::default()
+        },
+    ];
+    let mut diffuse = DiffuseSettings::default();
+
+    let (parameter, sign) = Action::IncreaseSensorAngle.adjustment().unwrap();
+    assert_eq!(parameter, Parameter::SensorAngle);
+    assert_eq!(
+        parameter.adjust(sign * 5.0, &mut species, &mut diffuse),
+        180.0
+    );
+    assert_eq!(species[1].sensorAngleDegrees, 25.0);
+
+    let (parameter, sign) = Action::DecreaseDecayRate.adjustment().unwrap();
+    assert_eq!(
+        parameter.adjust(sign * 10.0, &mut species, &mut diffuse),
+        0.0
+    );
+    assert_eq!(diffuse.decay_rate, 0.0);
+
+    species[0].turnSpeed = -0.5;
+    let action = Bindings::default().action("KeyQ").unwrap();
+    let (parameter, sign) = action.adjustment().unwrap();
+    assert_eq!(parameter, Parameter::TurnSpeed);
+    assert_eq!(
+        parameter.adjust(sign * 1.0, &mut species, &mut diffuse),
+        0.0
+    );
+
+    assert_eq!(Action::Pause.adjustment(), None);
+}
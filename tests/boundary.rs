@@ -0,0 +1,157 @@
+//! What the CPU reference does with agents and trail at the edge of the map,
+//! for each boundary mode.
+
+mod common;
+
+use std::f32::consts::PI;
+
+use common::{HEIGHT, WIDTH};
+use slime_webgpu::{
+    cpu::{self, TrailMap},
+    Agent, BoundaryMode, Config, FoodMap, ObstacleMask, RepellentMap, SpawnMode, SpeciesSettings,
+};
+
+fn config(boundary: BoundaryMode) -> Config {
+    let species = SpeciesSettings {
+        moveSpeed: 10.0,
+        turnSpeed: 0.0,
+        sensorOffsetDst: 2.0,
+        ..SpeciesSettings::default()
+    };
+    common::config(7, species, |config| {
+        config.simulation.boundary = boundary;
+        config.spawn.mode = SpawnMode::Point;
+    })
+}
+
+/// Moves an agent heading `angle` from (`x`, `y`) for one step, 5 texels at
+/// the settings above.
+fn step_agent(boundary: BoundaryMode, x: f32, y: f32, angle: f32) -> Agent {
+    common::step_agent(&config(boundary), x, y, angle, &ObstacleMask::open())
+}
+
+fn on_map(agent: &Agent) -> bool {
+    (0.0..WIDTH as f32).contains(&agent.posX) && (0.0..HEIGHT as f32).contains(&agent.posY)
+}
+
+#[test]
+fn wrap_comes_back_on_the_other_side() {
+    let agent = step_agent(BoundaryMode::Wrap, 30.0, 8.0, 0.0);
+    assert!((agent.posX - 3.0).abs() < 1e-4, "{:?}", agent);
+    assert_eq!(agent.posY, 8.0);
+    assert_eq!(agent.angle, 0.0);
+
+    let agent = step_agent(BoundaryMode::Wrap, 4.0, 2.0, -PI / 2.0);
+    assert!((agent.posY - 13.0).abs() < 1e-4, "{:?}", agent);
+}
+
+#[test]
+fn wrap_senses_across_the_edge() {
+    let config = config(BoundaryMode::Wrap);
+    let settings = &config.species[0];
+    let repellent = RepellentMap::new(&config).unwrap();
+    let mut trail_map = TrailMap::new(WIDTH, HEIGHT, 1);
+    trail_map.trail[(8 * WIDTH) as usize] = 1.0;
+    let agent = common::agent(30.5, 8.5, 0.0);
+    assert_eq!(
+        cpu::sense(
+            &agent,
+            settings,
+            0.0,
+            BoundaryMode::Wrap,
+            &repellent,
+            &trail_map
+        ),
+        1.0
+    );
+    assert_eq!(
+        cpu::sense(
+            &agent,
+            settings,
+            0.0,
+            BoundaryMode::Clamp,
+            &repellent,
+            &trail_map
+        ),
+        0.0
+    );
+}
+
+#[test]
+fn wrap_diffuses_across_the_edge() {
+    for (boundary, leaks) in [(BoundaryMode::Wrap, true), (BoundaryMode::Reflect, false)] {
+        let params = common::params(&config(boundary));
+        let mut trail_map = TrailMap::new(WIDTH, HEIGHT, 1);
+        trail_map.deposited[(8 * WIDTH) as usize] = 9.0;
+        let food = FoodMap::new(&config(boundary)).unwrap();
+        cpu::diffuse(&params, &ObstacleMask::open(), &food, &mut trail_map);
+        let far_side = trail_map.trail[(8 * WIDTH + WIDTH - 1) as usize];
+        assert_eq!(far_side > 0.0, leaks, "{:?}", boundary);
+    }
+}
+
+#[test]
+fn reflect_bounces_off_the_walls() {
+    let agent = step_agent(BoundaryMode::Reflect, 30.0, 8.0, 0.0);
+    assert!((agent.posX - 29.0).abs() < 1e-4, "{:?}", agent);
+    assert!((agent.angle - PI).abs() < 1e-6);
+
+    let agent = step_agent(BoundaryMode::Reflect, 4.0, 2.0, -PI / 2.0);
+    assert!((agent.posY - 3.0).abs() < 1e-4, "{:?}", agent);
+    assert!((agent.angle - PI / 2.0).abs() < 1e-6);
+}
+
+#[test]
+fn clamp_stops_at_the_edge_with_a_new_heading() {
+    let agent = step_agent(BoundaryMode::Clamp, 30.0, 8.0, 0.0);
+    assert_eq!((agent.posX, agent.posY), (WIDTH as f32 - 1.0, 8.0));
+    assert_ne!(agent.angle, 0.0);
+    assert!((0.0..=2.0 * PI).contains(&agent.angle));
+}
+
+#[test]
+fn respawn_returns_to_the_spawn_region() {
+    let agent = step_agent(BoundaryMode::Respawn, 30.0, 8.0, 0.0);
+    // `SpawnMode::Point` spawns everyone in the centre
+    assert_eq!(
+        (agent.posX, agent.posY),
+        (WIDTH as f32 / 2.0, HEIGHT as f32 / 2.0)
+    );
+    assert_eq!(agent.speciesIndex, 0);
+}
+
+#[test]
+fn agents_stay_on_the_map() {
+    for boundary in [
+        BoundaryMode::Wrap,
+        BoundaryMode::Reflect,
+        BoundaryMode::Clamp,
+        BoundaryMode::Respawn,
+    ] {
+        for i in 0..16 {
+            let angle = i as f32 * PI / 8.0;
+            for (x, y) in [(0.5, 0.5), (31.5, 0.5), (0.5, 15.5), (31.5, 15.5)] {
+                let agent = step_agent(boundary, x, y, angle);
+                assert!(on_map(&agent), "{:?} left the map: {:?}", boundary, agent);
+            }
+        }
+    }
+}
+
+#[test]
+fn interior_agents_are_unaffected() {
+    let reference = step_agent(BoundaryMode::Wrap, 10.0, 8.0, 0.3);
+    for boundary in [
+        BoundaryMode::Reflect,
+        BoundaryMode::Clamp,
+        BoundaryMode::Respawn,
+    ] {
+        let agent = step_agent(boundary, 10.0, 8.0, 0.3);
+        assert_eq!(
+            bytemuck::bytes_of(&agent),
+            bytemuck::bytes_of(&reference),
+            "{:?}",
+            boundary
+        );
+    }
+}
@@ -0,0 +1,240 @@
+//! Mouse brushes paint the trail map and move agents between steps.
+
+use slime_webgpu::{
+    cpu::CpuSimulation, food::ALL_SPECIES, Agent, Brush, BrushTool, Config, FoodMap, ObstacleMask,
+    RepellentMap, Spawner, SpeciesSettings,
+};
+
+const WIDTH: u32 = 32;
+const HEIGHT: u32 = 24;
+
+/// Two species, with `agents` lined up along the middle row.
+fn simulation(agents: Vec<Agent>) -> CpuSimulation {
+    simulation_with_walls(agents, &ObstacleMask::open())
+}
+
+fn simulation_with_walls(agents: Vec<Agent>, obstacles: &ObstacleMask) -> CpuSimulation {
+    let mut config = Config::default();
+    config.simulation.width = WIDTH;
+    config.simulation.height = HEIGHT;
+    config.simulation.seed = Some(5);
+    config.species = vec![SpeciesSettings::default(); 2];
+    CpuSimulation::new(
+        agents,
+        &config,
+        &Spawner::new(&config).unwrap(),
+        obstacles,
+        &FoodMap::new(&config).unwrap(),
+        &RepellentMap::new(&config).unwrap(),
+    )
+}
+
+fn row_of_agents(count: u32) -> Vec<Agent> {
+    (0..count)
+        .map(|i| Agent {
+            posX: i as f32 + 0.5,
+            posY: 12.0,
+            angle: 0.0,
+            speciesIndex: i % 2,
+        })
+        .collect()
+}
+
+fn moved(before: &[Agent], after: &[Agent]) -> Vec<usize> {
+    (0..before.len())
+        .filter(|&i| (before[i].posX, before[i].posY) != (after[i].posX, after[i].posY))
+        .collect()
+}
+
+#[test]
+fn paint_adds_to_one_layer_inside_the_brush() {
+    let mut sim = simulation(Vec::new());
+    sim.brush(&Brush {
+        tool: BrushTool::Paint,
+        x: 10.0,
+        y: 10.0,
+        radius: 3.0,
+        amount: 2.0,
+        species: 1,
+    });
+    let trail_map = sim.trail_map();
+    let texels = (WIDTH * HEIGHT) as usize;
+    assert!(trail_map.trail[..texels].iter().all(|&trail| trail == 0.0));
+    let painted = &trail_map.trail[texels..];
+    assert_eq!(painted[(10 * WIDTH + 10) as usize], 2.0);
+    // Texel centres within 3 of (10, 10)
+    assert_eq!(painted.iter().filter(|&&trail| trail == 2.0).count(), 32);
+    assert_eq!(painted[(10 * WIDTH + 13) as usize], 0.0);
+    assert_eq!(trail_map.trail, trail_map.deposited);
+}
+
+#[test]
+fn erase_takes_a_share_of_every_layer() {
+    let mut sim = simulation(Vec::new());
+    let paint = Brush {
+        x: 16.0,
+        y: 12.0,
+        radius: 4.0,
+        amount: 4.0,
+        species: ALL_SPECIES,
+        ..Brush::default()
+    };
+    sim.brush(&paint);
+    sim.brush(&Brush {
+        tool: BrushTool::Erase,
+        radius: 2.0,
+        amount: 0.75,
+        ..paint
+    });
+    let trail_map = sim.trail_map();
+    for layer in 0..2 {
+        let layer_start = (layer * WIDTH * HEIGHT) as usize;
+        assert_eq!(
+            trail_map.trail[layer_start + (12 * WIDTH + 16) as usize],
+            1.0
+        );
+        assert_eq!(
+            trail_map.trail[layer_start + (12 * WIDTH + 19) as usize],
+            4.0
+        );
+    }
+
+    // Erasing never goes below nothing
+    sim.brush(&Brush {
+        tool: BrushTool::Erase,
+        amount: 3.0,
+        ..paint
+    });
+    assert!(sim.trail_map().trail.iter().all(|&trail| trail == 0.0));
+}
+
+#[test]
+fn relocate_takes_agents_in_turn() {
+    let agents = row_of_agents(24);
+    let mut sim = simulation(agents.clone());
+    let relocate = Brush {
+        tool: BrushTool::Relocate,
+        x: 20.0,
+        y: 4.0,
+        radius: 3.0,
+        amount: 10.0,
+        ..Brush::default()
+    };
+    sim.brush(&relocate);
+    assert_eq!(moved(&agents, sim.agents()), (0..10).collect::<Vec<_>>());
+    for agent in &sim.agents()[..10] {
+        let (dx, dy) = (agent.posX - 20.0, agent.posY - 4.0);
+        assert!(dx * dx + dy * dy <= 9.0 + 1e-4, "{:?}", agent);
+    }
+    // Species are kept, so each one keeps its share of the population
+    for (before, after) in agents.iter().zip(sim.agents()) {
+        assert_eq!(before.speciesIndex, after.speciesIndex);
+    }
+
+    // The next brushes carry on where the last one stopped and wrap around
+    sim.brush(&relocate);
+    sim.brush(&relocate);
+    assert_eq!(moved(&agents, sim.agents()).len(), 24);
+    let relocated = sim.agents().to_vec();
+    sim.brush(&Brush {
+        x: 8.0,
+        amount: 1.0,
+        ..relocate
+    });
+    assert_eq!(moved(&relocated, sim.agents()), [6]);
+
+    // Fractions of an agent add up over successive brushes
+    let relocated = sim.agents().to_vec();
+    for _ in 0..3 {
+        sim.brush(&Brush {
+            x: 4.0,
+            amount: 0.4,
+            ..relocate
+        });
+    }
+    assert_eq!(moved(&relocated, sim.agents()), [7]);
+}
+
+#[test]
+fn attract_pulls_agents_towards_the_centre() {
+    let agents = row_of_agents(32);
+    let mut sim = simulation(agents.clone());
+    sim.brush(&Brush {
+        tool: BrushTool::Attract,
+        x: 16.5,
+        y: 12.0,
+        radius: 8.0,
+        amount: 4.0,
+        ..Brush::default()
+    });
+    let after = sim.agents();
+    // Agents 9 to 24 are within reach, the one in the middle stays put
+    assert_eq!(
+        moved(&agents, after),
+        (9..24).filter(|&i| i != 16).collect::<Vec<_>>()
+    );
+    // Halfway out the pull is half as strong
+    assert!((after[12].posX - (12.5 + 2.0)).abs() < 1e-5);
+    // and it never overshoots the centre
+    assert!((after[15].posX - 16.5).abs() < 1e-5);
+    assert!(after.iter().all(|agent| agent.posY == 12.0));
+}
+
+#[test]
+fn attract_does_not_pull_agents_into_walls() {
+    // A wall over columns 14 to 17, drawn at half resolution
+    let (width, height) = (WIDTH / 2, HEIGHT / 2);
+    let obstacles = ObstacleMask {
+        width,
+        height,
+        walls: (0..width * height)
+            .map(|i| {
+                if (7..9).contains(&(i % width)) {
+                    255
+                } else {
+                    0
+                }
+            })
+            .collect(),
+        colour: [1.0; 4],
+    };
+    let agents = row_of_agents(32);
+    let mut sim = simulation_with_walls(agents.clone(), &obstacles);
+    sim.brush(&Brush {
+        tool: BrushTool::Attract,
+        x: 16.5,
+        y: 12.0,
+        radius: 8.0,
+        amount: 4.0,
+        ..Brush::default()
+    });
+    let after = sim.agents();
+    // Agents pulled into the wall stay put, those in it may still move
+    assert_eq!(after[12].posX, 12.5);
+    assert_eq!(after[13].posX, 13.5);
+    assert!((after[10].posX - (10.5 + 1.0)).abs() < 1e-5);
+    assert!(after[15].posX != 15.5);
+    assert_eq!(after[19].posX, 19.5);
+    assert!((after[20].posX - (20.5 - 2.0)).abs() < 1e-5);
+}
+
+#[test]
+fn brushes_leave_the_rest_alone() {
+    let agents = row_of_agents(8);
+    let mut sim = simulation(agents.clone());
+    sim.brush(&Brush {
+        x: 4.0,
+        y: 12.0,
+        ..Brush::default()
+    });
+    assert!(moved(&agents, sim.agents()).is_empty());
+
+    let trail_map = sim.trail_map().clone();
+    sim.brush(&Brush {
+        tool: BrushTool::Attract,
+        x: 4.0,
+        y: 12.0,
+        ..Brush::default()
+    });
+    assert_eq!(sim.trail_map(), &trail_map);
+}
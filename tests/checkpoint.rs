@@ -0,0 +1,120 @@
+//! A run resumed from a checkpoint must continue exactly as the original.
+
+use slime_webgpu::{
+    cpu::CpuSimulation, Checkpoint, CheckpointError, Config, FoodMap, FoodSource, ObstacleMask,
+    RepellentMap, Spawner,
+};
+
+#[test]
+fn resumed_run_matches_uninterrupted_run() {
+    let mut config = Config::default();
+    config.simulation.width = 64;
+    config.simulation.height = 48;
+    config.simulation.num_agents = 1000;
+    config.simulation.seed = Some(99);
+    config.food.depletion = 2.0;
+    config.food.sources = vec![FoodSource {
+        x: 32.0,
+        y: 24.0,
+        radius: 20.0,
+        ..FoodSource::default()
+    }];
+    let spawner = Spawner::new(&config).unwrap();
+    let mut original = CpuSimulation::new(
+        spawner.spawn_agents(),
+        &config,
+        &spawner,
+        &ObstacleMask::open(),
+        &FoodMap::new(&config).unwrap(),
+        &RepellentMap::new(&config).unwrap(),
+    );
+    for _ in 0..20 {
+        original.step(1.0 / 60.0);
+    }
+    original.paint_repellent(20.0, 20.0, 6.0, 0.5);
+
+    let mut bytes = Vec::new();
+    original.checkpoint().write_to(&mut bytes).unwrap();
+    let checkpoint = Checkpoint::read_from(bytes.as_slice(), bytes.len() as u64).unwrap();
+    // Where the food started comes from the config, like the obstacles
+    let mut resumed_config = Config {
+        food: config.food.clone(),
+        ..Config::default()
+    };
+    checkpoint.apply_to(&mut resumed_config);
+    let resumed_spawner = Spawner::new(&resumed_config).unwrap();
+    let mut resumed = CpuSimulation::new(
+        checkpoint.agents.clone(),
+        &resumed_config,
+        &resumed_spawner,
+        &ObstacleMask::open(),
+        &FoodMap::new(&resumed_config).unwrap(),
+        &RepellentMap::new(&resumed_config).unwrap(),
+    );
+    resumed.restore(&checkpoint);
+
+    for _ in 0..20 {
+        original.step(1.0 / 60.0);
+        resumed.step(1.0 / 60.0);
+    }
+    assert_eq!(original.trail_map(), resumed.trail_map());
+    assert_eq!(original.food(), resumed.food());
+    assert_eq!(original.repellent(), resumed.repellent());
+    assert_eq!(
+        bytemuck::cast_slice::<_, u8>(original.agents()),
+        bytemuck::cast_slice::<_, u8>(resumed.agents())
+    );
+}
+
+#[test]
+fn rejects_other_files() {
+    let bytes = b"not a checkpoint at all";
+    let result = Checkpoint::read_from(&bytes[..], bytes.len() as u64);
+    assert!(matches!(result, Err(CheckpointError::NotACheckpoint)));
+}
+
+fn small_checkpoint() -> Vec<u8> {
+    let mut config = Config::default();
+    config.simulation.width = 16;
+    config.simulation.height = 8;
+    config.simulation.num_agents = 10;
+    let spawner = Spawner::new(&config).unwrap();
+    let simulation = CpuSimulation::new(
+        spawner.spawn_agents(),
+        &config,
+        &spawner,
+        &ObstacleMask::open(),
+        &FoodMap::new(&config).unwrap(),
+        &RepellentMap::new(&config).unwrap(),
+    );
+    let mut bytes = Vec::new();
+    simulation.checkpoint().write_to(&mut bytes).unwrap();
+    bytes
+}
+
+#[test]
+fn rejects_truncated_files() {
+    let mut bytes = small_checkpoint();
+    bytes.truncate(bytes.len() - 4);
+    let result = Checkpoint::read_from(bytes.as_slice(), bytes.len() as u64);
+    assert!(
+        matches!(result, Err(CheckpointError::Corrupt(_))),
+        "{:?}",
+        result
+    );
+}
+
+#[test]
+fn rejects_headers_larger_than_the_file() {
+    let mut bytes = small_checkpoint();
+    // Width, height and agent count follow the magic and version
+    for (offset, value) in [(12, u32::MAX), (16, u32::MAX), (20, u32::MAX)] {
+        bytes[offset..offset + 4].copy_from_slice(&value.to_ne_bytes());
+    }
+    let result = Checkpoint::read_from(bytes.as_slice(), bytes.len() as u64);
+    assert!(
+        matches!(result, Err(CheckpointError::Corrupt(_))),
+        "{:?}",
+        result
+    );
+}
@@ -0,0 +1,69 @@
+//! Fixtures shared by the CPU reference tests: a small map and a single agent
+//! stepped across it.
+
+#![allow(dead_code)]
+
+use slime_webgpu::{
+    cpu::{self, Surroundings, TrailMap},
+    Agent, Config, ObstacleMask, RepellentMap, ShaderParams, Spawner, SpeciesSettings,
+};
+
+pub const WIDTH: u32 = 32;
+pub const HEIGHT: u32 = 16;
+/// The step length of [`params`].
+pub const DELTA: f32 = 0.5;
+
+/// One agent of `species` on a `WIDTH` by `HEIGHT` map, with whatever else
+/// `customise` changes, checked by [`Config::validate`]. Trail spreads fast
+/// and never decays, so it's easy to follow.
+pub fn config(seed: u32, species: SpeciesSettings, customise: impl FnOnce(&mut Config)) -> Config {
+    let mut config = Config::default();
+    config.simulation.width = WIDTH;
+    config.simulation.height = HEIGHT;
+    config.simulation.num_agents = 1;
+    config.simulation.seed = Some(seed);
+    config.diffuse.diffuse_rate = 10.0;
+    config.diffuse.decay_rate = 0.0;
+    config.diffuse.deposit_intensity = 1.0;
+    config.species = vec![species];
+    customise(&mut config);
+    config.validate().unwrap();
+    config
+}
+
+/// The parameters of `config` with a long step.
+pub fn params(config: &Config) -> ShaderParams {
+    ShaderParams {
+        delta: DELTA,
+        ..ShaderParams::from_config(config)
+    }
+}
+
+pub fn agent(x: f32, y: f32, angle: f32) -> Agent {
+    Agent {
+        posX: x,
+        posY: y,
+        angle,
+        speciesIndex: 0,
+    }
+}
+
+/// Moves an agent heading `angle` from (`x`, `y`) through one step of
+/// [`params`] on an empty trail map, with the spawner and repellent of
+/// `config`.
+pub fn step_agent(config: &Config, x: f32, y: f32, angle: f32, obstacles: &ObstacleMask) -> Agent {
+    let mut agent = agent(x, y, angle);
+    cpu::update_agent(
+        0,
+        &mut agent,
+        &config.species,
+        &params(config),
+        Surroundings {
+            spawner: &Spawner::new(config).unwrap(),
+            obstacles,
+            repellent: &RepellentMap::new(config).unwrap(),
+        },
+        &mut TrailMap::new(config.simulation.width, config.simulation.height, 1),
+    );
+    agent
+}
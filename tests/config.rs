@@ -0,0 +1,159 @@
+//! Config files are checked value by value before anything is created.
+
+use slime_webgpu::{
+    timestep::{MAX_SPEED, MIN_SPEED},
+    Config, ConfigError, FoodSource, ObstacleMask, SpawnMode, Spawner, SpeciesSettings,
+    MAX_SPECIES,
+};
+
+fn out_of_range_field(config: &Config) -> String {
+    match config.validate() {
+        Err(ConfigError::OutOfRange { field, .. }) => field,
+        other => panic!("expected OutOfRange, got {:?}", other),
+    }
+}
+
+#[test]
+fn speed_must_be_in_the_range_the_speed_keys_use() {
+    let mut config = Config::default();
+    for speed in [0.0, MIN_SPEED / 2.0, MAX_SPEED * 1.5] {
+        config.simulation.speed = speed;
+        assert_eq!(out_of_range_field(&config), "simulation.speed");
+    }
+    for speed in [MIN_SPEED, 0.5, MAX_SPEED] {
+        config.simulation.speed = speed;
+        config.validate().unwrap();
+    }
+    config.simulation.speed = 100.0;
+    let error = config.validate().unwrap_err().to_string();
+    assert!(error.ends_with("expected 1/64 to 64"), "{}", error);
+}
+
+#[test]
+fn spawn_images_too_large_for_a_texture_are_rejected() {
+    let path = std::env::temp_dir().join("slime-config-spawn-test.png");
+    image::GrayImage::new(65, 1).save(&path).unwrap();
+    let mut config = Config::default();
+    config.simulation.width = 64;
+    config.simulation.height = 32;
+    config.simulation.scale = 1.0;
+    config.spawn.mode = SpawnMode::Image;
+    config.spawn.image = Some(path.clone());
+    let spawner = Spawner::new(&config).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    let limits = wgpu::Limits {
+        max_texture_dimension_2d: 64,
+        ..wgpu::Limits::downlevel_defaults()
+    };
+    let obstacles = ObstacleMask::open();
+    config
+        .simulation
+        .check_texture_limits(&limits, &obstacles)
+        .unwrap();
+    assert!(matches!(
+        config
+            .simulation
+            .check_limits(&limits, &obstacles, &spawner),
+        Err(ConfigError::ExceedsLimit {
+            what: "spawn.image width",
+            value: 65,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn species_count_is_limited() {
+    let mut config = Config::default();
+    for count in [0, MAX_SPECIES + 1] {
+        config.species = vec![SpeciesSettings::default(); count];
+        assert_eq!(out_of_range_field(&config), "species");
+    }
+    let error = config.validate().unwrap_err().to_string();
+    assert!(
+        error.ends_with(&format!("expected 1 to {} entries", MAX_SPECIES)),
+        "{}",
+        error
+    );
+    config.species = vec![SpeciesSettings::default(); MAX_SPECIES];
+    config.validate().unwrap();
+}
+
+type Change = fn(&mut Config);
+
+#[test]
+fn out_of_range_values_name_their_field() {
+    let cases: [(Change, &str); 6] = [
+        (
+            |config| config.species[1].sensorAngleDegrees = 200.0,
+            "species[1].sensorAngleDegrees",
+        ),
+        (
+            |config| config.species[0].moveSpeed = f32::NAN,
+            "species[0].moveSpeed",
+        ),
+        (
+            |config| config.species[1].turnSpeed = f32::INFINITY,
+            "species[1].turnSpeed",
+        ),
+        (
+            |config| config.diffuse.decay_rate = -1.0,
+            "diffuse.decayRate",
+        ),
+        (
+            |config| config.obstacles.colour_a = 2.0,
+            "obstacles.colourA",
+        ),
+        (
+            |config| {
+                config.food.sources = vec![FoodSource {
+                    x: 1.0,
+                    y: 1.0,
+                    radius: -1.0,
+                    strength: 1.0,
+                }]
+            },
+            "food.sources[0].radius",
+        ),
+    ];
+    for (change, field) in cases {
+        let mut config = Config {
+            species: vec![SpeciesSettings::default(); 2],
+            ..Config::default()
+        };
+        config.validate().unwrap();
+        change(&mut config);
+        assert_eq!(out_of_range_field(&config), field);
+    }
+}
+
+#[test]
+fn files_that_do_not_parse_are_rejected() {
+    let dir = std::env::temp_dir().join("slime-config-parse-test");
+    std::fs::create_dir_all(&dir).unwrap();
+    for (name, text) in [
+        ("unknown.toml", "[simulation]\nwidht = 100\n"),
+        ("type.toml", "[simulation]\nwidth = \"wide\"\n"),
+        ("syntax.json", "{\"simulation\": {\"width\": 100,}}"),
+    ] {
+        let path = dir.join(name);
+        std::fs::write(&path, text).unwrap();
+        let result = Config::load(&path);
+        assert!(
+            matches!(result, Err(ConfigError::Parse(_))),
+            "{}: {:?}",
+            name,
+            result
+        );
+    }
+
+    // Values that parse are still checked
+    let path = dir.join("range.toml");
+    std::fs::write(&path, "[diffuse]\ndiffuseRate = -2.0\n").unwrap();
+    assert!(matches!(
+        Config::load(&path),
+        Err(ConfigError::OutOfRange { field, .. }) if field == "diffuse.diffuseRate"
+    ));
+    std::fs::remove_dir_all(dir).unwrap();
+}
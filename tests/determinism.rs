@@ -0,0 +1,69 @@
+//! Runs with the same seed, config and timesteps must be bit-identical.
+
+use slime_webgpu::{
+    cpu::CpuSimulation, Config, FixedTimestep, FoodMap, ObstacleMask, RepellentMap, SpawnMode,
+    Spawner, SpeciesSettings,
+};
+
+const STEPS: u32 = 50;
+
+fn config(seed: u32) -> Config {
+    let mut config = Config::default();
+    config.simulation.width = 128;
+    config.simulation.height = 96;
+    config.simulation.num_agents = 4000;
+    config.simulation.seed = Some(seed);
+    config.spawn.mode = SpawnMode::Random;
+    config.species = vec![
+        SpeciesSettings::default(),
+        SpeciesSettings {
+            sensorAngleDegrees: 45.0,
+            ..SpeciesSettings::default()
+        },
+    ];
+    config.validate().unwrap();
+    config
+}
+
+/// Trail map and agents after [`STEPS`] steps, as raw bits so that NaNs and
+/// signed zeros compare too.
+fn run(config: &Config) -> (Vec<u32>, Vec<u32>) {
+    let spawner = Spawner::new(config).unwrap();
+    let timestep = FixedTimestep::new(&config.simulation);
+    let mut simulation = CpuSimulation::new(
+        spawner.spawn_agents(),
+        config,
+        &spawner,
+        &ObstacleMask::open(),
+        &FoodMap::new(config).unwrap(),
+        &RepellentMap::new(config).unwrap(),
+    );
+    for _ in 0..STEPS {
+        simulation.step(timestep.delta());
+    }
+    let trail_map = simulation.trail_map();
+    let trail = trail_map
+        .trail
+        .iter()
+        .chain(&trail_map.deposited)
+        .map(|value| value.to_bits())
+        .collect();
+    let agents = bytemuck::cast_slice(simulation.agents()).to_vec();
+    (trail, agents)
+}
+
+#[test]
+fn same_seed_is_bit_identical() {
+    let config = config(1234);
+    let first = run(&config);
+    assert!(
+        first.0.iter().any(|&bits| bits != 0),
+        "nothing was deposited"
+    );
+    assert_eq!(first, run(&config));
+}
+
+#[test]
+fn different_seeds_diverge() {
+    assert_ne!(run(&config(1)).0, run(&config(2)).0);
+}
@@ -0,0 +1,85 @@
+//! Animations are only written in the formats `--export-gif` lists, loop
+//! without a seam and are scaled down evenly.
+
+use std::time::Duration;
+
+use image::{Rgba, RgbaImage};
+use slime_webgpu::export::{self, ExportError};
+
+#[test]
+fn webp_is_rejected_before_anything_is_written() {
+    let path = std::env::temp_dir().join("slime-export-test.webp");
+    let _ = std::fs::remove_file(&path);
+    let frames = [RgbaImage::new(4, 4), RgbaImage::new(4, 4)];
+    let error = export::save_animation(&path, &frames, Duration::from_millis(20)).unwrap_err();
+    assert!(matches!(&error, ExportError::UnsupportedFormat(extension) if extension == "webp"));
+    assert!(error.to_string().contains(".apng"), "{}", error);
+    assert!(!path.exists());
+}
+
+#[test]
+fn apng_loops_every_frame() {
+    let path = std::env::temp_dir().join("slime-export-test.apng");
+    let frames = [RgbaImage::new(4, 4), RgbaImage::new(4, 4)];
+    export::save_animation(&path, &frames, Duration::from_millis(20)).unwrap();
+    let decoder = png::Decoder::new(std::io::BufReader::new(std::fs::File::open(&path).unwrap()));
+    let reader = decoder.read_info().unwrap();
+    let animation = reader.info().animation_control.unwrap();
+    assert_eq!(animation.num_frames, 2);
+    assert_eq!(animation.num_plays, 0);
+    std::fs::remove_file(path).unwrap();
+}
+
+fn grey(value: u8) -> RgbaImage {
+    RgbaImage::from_pixel(2, 2, Rgba([value, value, value, 255]))
+}
+
+fn value(frame: &RgbaImage) -> u8 {
+    frame.get_pixel(1, 1).0[0]
+}
+
+#[test]
+fn crossfade_blends_the_intro_into_the_tail() {
+    let frames = (0..8).map(|i| grey(i * 10)).collect();
+    let looped = export::crossfade_loop(frames, 2);
+    // The first two frames are dropped, and fade in over the last two: a
+    // third of the way into frame 0, then two thirds into frame 1
+    let values: Vec<u8> = looped.iter().map(value).collect();
+    assert_eq!(values, [20, 30, 40, 50, 40, 30]);
+    assert!(looped.iter().all(|frame| frame.get_pixel(0, 0).0[3] == 255));
+
+    let frames: Vec<RgbaImage> = (0..3).map(|i| grey(i * 10)).collect();
+    assert_eq!(export::crossfade_loop(frames.clone(), 0), frames);
+}
+
+#[test]
+#[should_panic(expected = "too few frames")]
+fn crossfade_needs_twice_the_fade() {
+    export::crossfade_loop(vec![grey(0); 3], 2);
+}
+
+#[test]
+fn downsample_averages_each_block() {
+    // Every 2x2 block is half black and half white
+    let frame = RgbaImage::from_fn(8, 6, |x, y| {
+        let v = if (x + y) % 2 == 0 { 0 } else { 255 };
+        Rgba([v, v, v, 255])
+    });
+    let small = export::downsample(&frame, 4);
+    assert_eq!(small.dimensions(), (4, 3));
+    for pixel in small.pixels() {
+        assert_eq!(pixel.0[0], 128, "{:?}", pixel);
+        assert_eq!(pixel.0[3], 255);
+    }
+
+    // Blocks don't bleed into their neighbours
+    let frame = RgbaImage::from_fn(8, 2, |x, _| {
+        let v = if x < 4 { 0 } else { 255 };
+        Rgba([v, v, v, 255])
+    });
+    let small = export::downsample(&frame, 4);
+    let row: Vec<u8> = (0..4).map(|x| small.get_pixel(x, 0).0[0]).collect();
+    assert_eq!(row, [0, 0, 255, 255]);
+
+    assert_eq!(export::downsample(&frame, 16), frame);
+}
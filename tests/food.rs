@@ -0,0 +1,173 @@
+//! Food sources feed the trail every step and run out when eaten from.
+
+mod common;
+
+use common::{DELTA, HEIGHT, WIDTH};
+use slime_webgpu::{
+    cpu::{self, CpuSimulation, TrailMap},
+    Config, ConfigError, FoodMap, FoodSource, ObstacleMask, RepellentMap, SpawnMode, Spawner,
+    SpeciesSettings,
+};
+
+fn config(sources: &[FoodSource]) -> Config {
+    common::config(5, SpeciesSettings::default(), |config| {
+        config.simulation.num_agents = 100;
+        config.diffuse.diffuse_rate = 1.0;
+        config.diffuse.decay_rate = 0.2;
+        config.food.sources = sources.to_vec();
+    })
+}
+
+fn source(x: f32, y: f32, radius: f32, strength: f32) -> FoodSource {
+    FoodSource {
+        x,
+        y,
+        radius,
+        strength,
+    }
+}
+
+fn food_at(food: &FoodMap, x: u32, y: u32) -> f32 {
+    food.food[(y * WIDTH + x) as usize]
+}
+
+#[test]
+fn sources_cover_disks_of_texels() {
+    let food = FoodMap::new(&config(&[
+        source(4.5, 4.5, 1.5, 1.0),
+        source(5.5, 4.5, 0.0, 3.0),
+        source(31.0, 0.0, 2.0, 0.5),
+    ]))
+    .unwrap();
+    assert_eq!(food_at(&food, 4, 4), 1.0);
+    assert_eq!(food_at(&food, 5, 5), 1.0);
+    assert_eq!(food_at(&food, 6, 4), 0.0);
+    // Overlapping sources keep the most food
+    assert_eq!(food_at(&food, 5, 4), 3.0);
+    // Sources may hang off the map
+    assert_eq!(food_at(&food, 31, 0), 0.5);
+    assert_eq!(food.total(), 8.0 * 1.0 + 3.0 + 5.0 * 0.5);
+}
+
+#[test]
+fn image_is_stretched_over_the_map() {
+    let path = std::env::temp_dir().join("slime-food-test.png");
+    image::GrayImage::from_fn(2, 1, |x, _| image::Luma([x as u8 * 255]))
+        .save(&path)
+        .unwrap();
+    let mut config = config(&[]);
+    config.food.image = Some(path.clone());
+    let food = FoodMap::new(&config).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(food_at(&food, WIDTH / 2 - 1, HEIGHT - 1), 0.0);
+    assert_eq!(food_at(&food, WIDTH / 2, 0), 1.0);
+    assert_eq!(food.total(), (WIDTH * HEIGHT / 2) as f32);
+}
+
+#[test]
+fn food_feeds_the_trail() {
+    let mut config = config(&[source(3.5, 3.5, 0.0, 2.0)]);
+    config.species = vec![SpeciesSettings::default(); 2];
+    config.food.species = Some(1);
+    config.food.amount = 0.1;
+    let food = FoodMap::new(&config).unwrap();
+    let mut trail_map = TrailMap::new(WIDTH, HEIGHT, 2);
+    cpu::diffuse(
+        &common::params(&config),
+        &ObstacleMask::open(),
+        &food,
+        &mut trail_map,
+    );
+
+    let texels = (WIDTH * HEIGHT) as usize;
+    let fed = (3 * WIDTH + 3) as usize;
+    // Like an agent depositing `depositIntensity = 2 * amount`
+    assert!((trail_map.trail[texels + fed] - 2.0 * 0.1 * DELTA * 10.0).abs() < 1e-6);
+    assert_eq!(
+        trail_map.trail[texels..].iter().sum::<f32>(),
+        trail_map.trail[texels + fed]
+    );
+    // Other species' layers are left alone
+    assert!(trail_map.trail[..texels].iter().all(|&trail| trail == 0.0));
+}
+
+#[test]
+fn fed_trail_settles_into_a_steady_state() {
+    let config = config(&[source(8.0, 4.0, 2.0, 1.0)]);
+    let food = FoodMap::new(&config).unwrap();
+    let mut trail_map = TrailMap::new(WIDTH, HEIGHT, 1);
+    let mut previous = 0.0;
+    for _ in 0..400 {
+        previous = trail_map.trail.iter().sum::<f32>();
+        cpu::diffuse(
+            &common::params(&config),
+            &ObstacleMask::open(),
+            &food,
+            &mut trail_map,
+        );
+    }
+    let total: f32 = trail_map.trail.iter().sum();
+    assert!(total > 0.0);
+    assert!(
+        (total - previous).abs() < 1e-4 * total,
+        "{} {}",
+        previous,
+        total
+    );
+}
+
+#[test]
+fn agents_eat_the_food_they_deposit_on() {
+    let mut config = config(&[source(8.0, 4.0, 8.0, 1.0)]);
+    config.food.depletion = 0.5;
+    let mut food = FoodMap::new(&config).unwrap();
+    let mut trail_map = TrailMap::new(WIDTH, HEIGHT, 1);
+    let visited = (4 * WIDTH + 8) as usize;
+    trail_map.deposited[visited] = 0.1;
+    trail_map.trail[1] = 0.1;
+    trail_map.deposited[1] = 0.1;
+
+    cpu::consume(&common::params(&config), &mut food, &trail_map);
+    assert_eq!(food_at(&food, 8, 4), 1.0 - 0.5 * DELTA);
+    // Trail alone does not eat
+    assert_eq!(food_at(&food, 1, 0), 1.0);
+
+    for _ in 0..10 {
+        cpu::consume(&common::params(&config), &mut food, &trail_map);
+    }
+    assert_eq!(food_at(&food, 8, 4), 0.0);
+}
+
+#[test]
+fn food_only_runs_out_with_depletion() {
+    for (depletion, runs_out) in [(0.0, false), (1.0, true)] {
+        let mut config = config(&[source(16.0, 8.0, 3.0, 1.0)]);
+        config.spawn.mode = SpawnMode::Point;
+        config.food.depletion = depletion;
+        let spawner = Spawner::new(&config).unwrap();
+        let food = FoodMap::new(&config).unwrap();
+        let mut simulation = CpuSimulation::new(
+            spawner.spawn_agents(),
+            &config,
+            &spawner,
+            &ObstacleMask::open(),
+            &food,
+            &RepellentMap::new(&config).unwrap(),
+        );
+        for _ in 0..10 {
+            simulation.step(1.0 / 60.0);
+        }
+        assert_eq!(simulation.food().total() < food.total(), runs_out);
+    }
+}
+
+#[test]
+fn rejects_food_for_a_missing_species() {
+    let mut config = config(&[]);
+    config.food.species = Some(1);
+    assert!(matches!(
+        config.validate(),
+        Err(ConfigError::OutOfRange { field, .. }) if field == "food.species"
+    ));
+}
@@ -0,0 +1,94 @@
+//! The CPU reference and the shaders agree on a small map. Needs a GPU or
+//! lavapipe, so it only runs with `cargo test -- --ignored`.
+
+use slime_webgpu::{
+    cpu::CpuSimulation, headless, Config, FixedTimestep, FoodMap, ObstacleMask, RepellentMap,
+    Simulation, SpawnMode, Spawner, SpeciesSettings,
+};
+
+const STEPS: u32 = 4;
+
+fn config() -> Config {
+    let mut config = Config::default();
+    config.simulation.width = 64;
+    config.simulation.height = 48;
+    config.simulation.num_agents = 500;
+    config.simulation.seed = Some(17);
+    config.spawn.mode = SpawnMode::Random;
+    config.species = vec![
+        SpeciesSettings {
+            sensorOffsetDst: 8.0,
+            ..SpeciesSettings::default()
+        },
+        SpeciesSettings {
+            sensorOffsetDst: 8.0,
+            sensorSize: 1.0,
+            ..SpeciesSettings::default()
+        },
+    ];
+    config.validate().unwrap();
+    config
+}
+
+#[test]
+#[ignore = "needs a Vulkan adapter, run with --ignored (CI uses lavapipe)"]
+fn update_and_diffuse_match_the_shaders() {
+    let (device, queue) = pollster::block_on(headless::request_device());
+    let config = config();
+    let spawner = Spawner::new(&config).unwrap();
+    let obstacles = ObstacleMask::open();
+    let food = FoodMap::new(&config).unwrap();
+    let repellent = RepellentMap::new(&config).unwrap();
+    let agents = spawner.spawn_agents();
+    let mut gpu = Simulation::new(
+        device, queue, &config, &spawner, &obstacles, &food, &repellent,
+    );
+    gpu.write_agents(&agents);
+    let mut cpu = CpuSimulation::new(agents, &config, &spawner, &obstacles, &food, &repellent);
+
+    let delta = FixedTimestep::new(&config.simulation).delta();
+    for _ in 0..STEPS {
+        gpu.step(delta);
+        cpu.step(delta);
+    }
+
+    // Transcendentals differ in the last bits, so allow the odd agent to have
+    // taken a different turn
+    let gpu_agents = gpu.read_agents().unwrap();
+    let close = gpu_agents
+        .iter()
+        .zip(cpu.agents())
+        .filter(|(gpu, cpu)| {
+            (gpu.posX - cpu.posX).abs() < 1e-2 && (gpu.posY - cpu.posY).abs() < 1e-2
+        })
+        .count();
+    assert!(
+        close >= gpu_agents.len() * 99 / 100,
+        "{} agents match",
+        close
+    );
+
+    let gpu_trail = gpu.read_trail_map().unwrap();
+    let cpu_trail = cpu.trail_map();
+    let differing = gpu_trail
+        .trail
+        .iter()
+        .zip(&cpu_trail.trail)
+        .filter(|(gpu, cpu)| (*gpu - *cpu).abs() > 1e-3 * cpu.abs().max(1.0))
+        .count();
+    assert!(
+        differing <= gpu_trail.trail.len() / 100,
+        "{} of {} texels differ",
+        differing,
+        gpu_trail.trail.len()
+    );
+    let total = |trail: &[f32]| trail.iter().sum::<f32>();
+    let (gpu_total, cpu_total) = (total(&gpu_trail.trail), total(&cpu_trail.trail));
+    assert!(cpu_total > 0.0);
+    assert!(
+        (gpu_total - cpu_total).abs() < 1e-2 * cpu_total,
+        "{} {}",
+        gpu_total,
+        cpu_total
+    );
+}
@@ -0,0 +1,100 @@
+//! Shaders edited under `--shader-dir` are checked by naga before they replace
+//! the running ones, and only reloaded when they change.
+
+use std::path::Path;
+
+use slime_webgpu::{
+    hot_reload::{check_wgsl, ShaderError, ShaderWatcher, WATCHED_SHADERS},
+    simulation::{check_placement, with_placement, PLACE_SHADER},
+};
+
+fn bundled(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/shaders")
+        .join(name);
+    std::fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn bundled_shaders_pass_the_check() {
+    for name in WATCHED_SHADERS {
+        // `place.wgsl` only compiles as part of the modules it's appended to
+        let source = match name {
+            "slime.wgsl" => with_placement(&bundled(name), PLACE_SHADER),
+            "place.wgsl" => with_placement(&bundled(name), &bundled("slime.wgsl")),
+            _ => bundled(name),
+        };
+        check_wgsl(Path::new(name), &source).unwrap();
+    }
+    check_wgsl(
+        Path::new("spawn.wgsl"),
+        &with_placement(&bundled("spawn.wgsl"), PLACE_SHADER),
+    )
+    .unwrap();
+}
+
+#[test]
+fn placement_must_build_in_the_spawn_shader_too() {
+    let slime = bundled("slime.wgsl");
+    let path = Path::new("place.wgsl");
+    check_placement(path, PLACE_SHADER, &slime).unwrap();
+
+    // `isWall` only exists in `slime.wgsl`
+    let place = format!(
+        "{}
+fn blocked() -> bool {{
+    return isWall(vec2<i32>(0));
+}}
+",
+        PLACE_SHADER
+    );
+    check_wgsl(path, &with_placement(&place, &slime)).unwrap();
+    let Err(ShaderError::Invalid(report)) = check_placement(path, &place, &slime) else {
+        panic!("placement that only builds in slime.wgsl accepted");
+    };
+    assert!(report.contains("isWall"), "{}", report);
+}
+
+#[test]
+fn errors_point_at_the_offending_line() {
+    let path = Path::new("broken.wgsl");
+    let Err(ShaderError::Invalid(report)) = check_wgsl(path, "fn main() {\n    let x = ;\n}\n")
+    else {
+        panic!("syntax error accepted");
+    };
+    assert!(report.contains("broken.wgsl:2"), "{}", report);
+
+    let Err(ShaderError::Invalid(report)) = check_wgsl(
+        path,
+        "fn main() -> f32 {\n    return 1.0;\n}\n\nfn other() -> u32 {\n    return main();\n}\n",
+    ) else {
+        panic!("type error accepted");
+    };
+    assert!(report.contains("broken.wgsl:5"), "{}", report);
+    assert!(report.contains("6 │"), "{}", report);
+}
+
+#[test]
+fn watcher_reports_each_change_once() {
+    let dir = std::env::temp_dir().join("slime-hot-reload-test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut watcher = ShaderWatcher::new(&dir);
+    assert!(watcher.poll().is_empty());
+
+    std::fs::write(watcher.path("diffuse.wgsl"), "// first").unwrap();
+    let changed = watcher.poll();
+    assert_eq!(changed.len(), 1);
+    assert_eq!(changed[0].0, "diffuse.wgsl");
+    assert_eq!(changed[0].1.as_ref().unwrap(), "// first");
+    assert!(watcher.poll().is_empty());
+
+    let file = std::fs::File::options()
+        .write(true)
+        .open(watcher.path("diffuse.wgsl"))
+        .unwrap();
+    file.set_modified(std::time::SystemTime::now() + std::time::Duration::from_secs(1))
+        .unwrap();
+    assert_eq!(watcher.poll().len(), 1);
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,136 @@
+//! Walls from an obstacle mask keep agents and trail out.
+
+mod common;
+
+use common::{HEIGHT, WIDTH};
+use slime_webgpu::{
+    cpu::{self, TrailMap},
+    Config, ConfigError, FoodMap, ObstacleMask, ObstacleSettings, SpeciesSettings,
+};
+
+/// A wall filling columns 16 to 23 of the map, drawn at half resolution.
+fn mask() -> ObstacleMask {
+    let (width, height) = (WIDTH / 2, HEIGHT / 2);
+    ObstacleMask {
+        width,
+        height,
+        walls: (0..width * height)
+            .map(|i| {
+                if (8..12).contains(&(i % width)) {
+                    255
+                } else {
+                    0
+                }
+            })
+            .collect(),
+        colour: [1.0; 4],
+    }
+}
+
+fn config() -> Config {
+    let species = SpeciesSettings {
+        moveSpeed: 10.0,
+        turnSpeed: 0.0,
+        ..SpeciesSettings::default()
+    };
+    common::config(3, species, |_| {})
+}
+
+#[test]
+fn mask_is_stretched_over_the_map() {
+    let mask = mask();
+    assert!(!mask.is_wall(15, 0, WIDTH, HEIGHT));
+    assert!(mask.is_wall(16, 0, WIDTH, HEIGHT));
+    assert!(mask.is_wall(23, 15, WIDTH, HEIGHT));
+    assert!(!mask.is_wall(24, 15, WIDTH, HEIGHT));
+    assert!(!mask.is_wall(-1, 0, WIDTH, HEIGHT));
+}
+
+#[test]
+fn agents_turn_away_from_walls() {
+    let agent = common::step_agent(&config(), 13.0, 8.0, 0.0, &mask());
+    assert_eq!((agent.posX, agent.posY), (13.0, 8.0));
+    assert_ne!(agent.angle, 0.0);
+
+    // Heading away from the wall is not affected
+    let agent = common::step_agent(&config(), 13.0, 8.0, std::f32::consts::PI, &mask());
+    assert!(agent.posX < 13.0);
+}
+
+#[test]
+fn agents_inside_walls_can_leave() {
+    let agent = common::step_agent(&config(), 22.0, 8.0, 0.0, &mask());
+    assert!((agent.posX - 27.0).abs() < 1e-4, "{:?}", agent);
+}
+
+#[test]
+fn trail_does_not_diffuse_into_walls() {
+    let mut trail_map = TrailMap::new(WIDTH, HEIGHT, 1);
+    for y in 0..HEIGHT {
+        trail_map.deposited[(y * WIDTH + 15) as usize] = 9.0;
+        trail_map.deposited[(y * WIDTH + 20) as usize] = 9.0;
+    }
+    let total_before: f32 = trail_map.deposited.iter().sum();
+    let config = config();
+    let food = FoodMap::new(&config).unwrap();
+    cpu::diffuse(&common::params(&config), &mask(), &food, &mut trail_map);
+
+    for y in 0..HEIGHT {
+        for x in 16..24 {
+            assert_eq!(trail_map.trail[(y * WIDTH + x) as usize], 0.0);
+        }
+        assert!(trail_map.trail[(y * WIDTH + 14) as usize] > 0.0);
+    }
+    // The trail next to the wall is reflected rather than lost, while the
+    // trail deposited inside the wall is gone
+    let total_after: f32 = trail_map.trail.iter().sum();
+    assert!((total_after - total_before / 2.0).abs() < 1e-3);
+}
+
+#[test]
+fn loads_bright_pixels_as_walls() {
+    let path = std::env::temp_dir().join("slime-obstacles-test.png");
+    image::GrayImage::from_fn(4, 2, |x, _| image::Luma([if x < 2 { 0 } else { 255 }]))
+        .save(&path)
+        .unwrap();
+
+    let mut settings = ObstacleSettings {
+        image: Some(path.clone()),
+        ..ObstacleSettings::default()
+    };
+    let mask = ObstacleMask::new(&settings).unwrap();
+    assert_eq!((mask.width, mask.height), (4, 2));
+    assert_eq!(mask.walls, [0, 0, 255, 255, 0, 0, 255, 255]);
+
+    settings.invert = true;
+    let inverted = ObstacleMask::new(&settings).unwrap();
+    assert_eq!(inverted.walls, [255, 255, 0, 0, 255, 255, 0, 0]);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn masks_too_large_for_a_texture_are_rejected() {
+    let limits = wgpu::Limits {
+        max_texture_dimension_2d: 64,
+        ..wgpu::Limits::downlevel_defaults()
+    };
+    let config = config();
+    config
+        .simulation
+        .check_texture_limits(&limits, &mask())
+        .unwrap();
+    let tall = ObstacleMask {
+        width: 1,
+        height: 65,
+        walls: vec![0; 65],
+        colour: [1.0; 4],
+    };
+    assert!(matches!(
+        config.simulation.check_texture_limits(&limits, &tall),
+        Err(ConfigError::ExceedsLimit {
+            what: "obstacles.image height",
+            value: 65,
+            ..
+        })
+    ));
+}
@@ -0,0 +1,113 @@
+//! Presets round-trip through files, fit any number of species and morph
+//! smoothly into each other.
+
+use slime_webgpu::{
+    preset::{self, Transition},
+    ConfigError, DiffuseSettings, Preset, SpeciesSettings,
+};
+
+fn preset() -> Preset {
+    Preset {
+        species: vec![
+            SpeciesSettings {
+                moveSpeed: 40.0,
+                sensorSize: 2.0,
+                ..SpeciesSettings::default()
+            },
+            SpeciesSettings {
+                sensorAngleDegrees: 30.0,
+                repellentSensitivity: -1.5,
+                ..SpeciesSettings::default()
+            },
+        ],
+        diffuse: DiffuseSettings {
+            diffuse_rate: 3.0,
+            decay_rate: 1.25,
+            deposit_intensity: 0.02,
+        },
+    }
+}
+
+#[test]
+fn saved_presets_load_again() {
+    for extension in ["toml", "json"] {
+        let path = std::env::temp_dir().join(format!("slime-preset-test.{}", extension));
+        preset().save(&path).unwrap();
+        assert_eq!(Preset::load(&path).unwrap(), preset());
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn loads_from_a_whole_config_and_validates_it() {
+    let path = std::env::temp_dir().join("slime-preset-config-test.toml");
+    std::fs::write(
+        &path,
+        "[simulation]\nwidth = 64\n\n[[species]]\nmoveSpeed = 7.0\n\n[diffuse]\ndecayRate = 2.0\n",
+    )
+    .unwrap();
+    let loaded = Preset::load(&path).unwrap();
+    assert_eq!(loaded.species.len(), 1);
+    assert_eq!(loaded.species[0].moveSpeed, 7.0);
+    assert_eq!(loaded.diffuse.decay_rate, 2.0);
+
+    std::fs::write(&path, "[[species]]\nmoveSpeed = -1.0\n").unwrap();
+    assert!(matches!(
+        Preset::load(&path),
+        Err(ConfigError::OutOfRange { .. })
+    ));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn species_are_repeated_or_dropped_to_fit() {
+    let preset = preset();
+    assert_eq!(preset.species_for(1), &preset.species[..1]);
+    assert_eq!(
+        preset.species_for(3),
+        [preset.species[0], preset.species[1], preset.species[1]]
+    );
+}
+
+#[test]
+fn library_lists_preset_files_by_name() {
+    let dir = std::env::temp_dir().join("slime-preset-library-test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("nested.toml")).unwrap();
+    for name in ["waves.toml", "notes.txt", "coral.json", "mesh.toml"] {
+        std::fs::write(dir.join(name), "").unwrap();
+    }
+    let names: Vec<_> = preset::library(&dir)
+        .unwrap()
+        .iter()
+        .map(|path| path.file_name().unwrap().to_owned())
+        .collect();
+    assert_eq!(names, ["coral.json", "mesh.toml", "waves.toml"]);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn transitions_pass_through_the_midpoint() {
+    let from = preset();
+    let mut to = preset();
+    to.species[0].moveSpeed = 60.0;
+    to.species[0].sensorSize = 3.0;
+    to.species[1].colourG = 0.0;
+    to.diffuse.decay_rate = 2.25;
+
+    let mut transition = Transition::new(from.clone(), to.clone(), 2.0);
+    let halfway = transition.advance(1.0);
+    assert!(!transition.is_finished());
+    assert_eq!(halfway.species[0].moveSpeed, 50.0);
+    assert_eq!(halfway.species[0].sensorSize, 3.0);
+    assert_eq!(halfway.species[1].colourG, 0.5);
+    assert_eq!(halfway.diffuse.decay_rate, 1.75);
+    assert_eq!(halfway.diffuse.diffuse_rate, from.diffuse.diffuse_rate);
+
+    assert_eq!(transition.advance(1.5), to);
+    assert!(transition.is_finished());
+
+    let mut instant = Transition::new(from, to.clone(), 0.0);
+    assert_eq!(instant.advance(0.0), to);
+    assert!(instant.is_finished());
+}
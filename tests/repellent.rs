@@ -0,0 +1,121 @@
+//! The repellent layer pushes agents away in proportion to each species'
+//! sensitivity.
+
+mod common;
+
+use common::{HEIGHT, WIDTH};
+use slime_webgpu::{
+    cpu::{self, TrailMap},
+    BoundaryMode, Config, ObstacleMask, RepellentCircle, RepellentMap, SpeciesSettings,
+};
+
+fn config(sensitivity: f32, circles: &[RepellentCircle]) -> Config {
+    let species = SpeciesSettings {
+        moveSpeed: 0.0,
+        turnSpeed: 1.0,
+        sensorAngleDegrees: 90.0,
+        sensorOffsetDst: 4.0,
+        repellentSensitivity: sensitivity,
+        ..SpeciesSettings::default()
+    };
+    common::config(11, species, |config| {
+        config.simulation.boundary = BoundaryMode::Clamp;
+        config.repellent.circles = circles.to_vec();
+    })
+}
+
+fn circle(x: f32, y: f32, radius: f32, strength: f32) -> RepellentCircle {
+    RepellentCircle {
+        x,
+        y,
+        radius,
+        strength,
+    }
+}
+
+/// How far an agent heading along +x from the middle of the map turns in one
+/// step, with a patch of repellent under its left sensor.
+fn turn(sensitivity: f32) -> f32 {
+    let config = config(sensitivity, &[circle(16.5, 12.5, 1.0, 1.0)]);
+    common::step_agent(&config, 16.5, 8.5, 0.0, &ObstacleMask::open()).angle
+}
+
+#[test]
+fn circles_add_up_and_may_attract() {
+    let repellent = RepellentMap::new(&config(
+        1.0,
+        &[
+            circle(4.0, 4.0, 2.0, 1.0),
+            circle(5.0, 4.0, 2.0, 0.5),
+            circle(20.0, 8.0, 3.0, -2.0),
+        ],
+    ))
+    .unwrap();
+    assert_eq!(repellent.at(2, 3), 1.0);
+    assert_eq!(repellent.at(4, 3), 1.5);
+    assert_eq!(repellent.at(6, 3), 0.5);
+    assert_eq!(repellent.at(20, 8), -2.0);
+    assert_eq!(repellent.at(12, 12), 0.0);
+    assert_eq!(repellent.at(-1, 3), 0.0);
+}
+
+#[test]
+fn image_is_scaled_by_strength() {
+    let path = std::env::temp_dir().join("slime-repellent-test.png");
+    image::GrayImage::from_fn(1, 2, |_, y| image::Luma([y as u8 * 255]))
+        .save(&path)
+        .unwrap();
+    let mut config = config(1.0, &[]);
+    config.repellent.image = Some(path.clone());
+    config.repellent.strength = -0.5;
+    let repellent = RepellentMap::new(&config).unwrap();
+    std::fs::remove_file(path).unwrap();
+
+    assert_eq!(repellent.at(0, HEIGHT as i32 / 2 - 1), 0.0);
+    assert_eq!(repellent.at(WIDTH as i32 - 1, HEIGHT as i32 / 2), -0.5);
+}
+
+#[test]
+fn sense_subtracts_the_repellent() {
+    for sensitivity in [0.0, 1.0, 2.5, -1.0] {
+        let config = config(sensitivity, &[circle(20.5, 8.5, 0.0, 0.8)]);
+        let repellent = RepellentMap::new(&config).unwrap();
+        let weight = cpu::sense(
+            &common::agent(16.5, 8.5, 0.0),
+            &config.species[0],
+            0.0,
+            BoundaryMode::Clamp,
+            &repellent,
+            &TrailMap::new(WIDTH, HEIGHT, 1),
+        );
+        assert_eq!(weight, -sensitivity * 0.8);
+    }
+}
+
+#[test]
+fn agents_turn_away_unless_attracted() {
+    // Positive angles turn left, towards the repellent
+    assert!(turn(1.0) < 0.0);
+    assert_eq!(turn(0.0), 0.0);
+    assert!(turn(-1.0) > 0.0);
+}
+
+#[test]
+fn painting_reports_the_rows_it_changed() {
+    let mut repellent = RepellentMap::new(&config(1.0, &[])).unwrap();
+    assert_eq!(repellent.paint(10.0, 5.0, 2.0, 0.25), 3..7);
+    assert_eq!(repellent.at(10, 5), 0.25);
+    repellent.paint(10.0, 5.0, 2.0, -1.0);
+    assert_eq!(repellent.at(10, 5), -0.75);
+    assert_eq!(repellent.paint(-10.0, 5.0, 2.0, 1.0), 0..0);
+}
+
+#[test]
+fn sensitivity_defaults_to_one() {
+    let config: Config = toml::from_str(
+        "[[species]]\nmoveSpeed = 50.0\n\n[[species]]\nrepellentSensitivity = -0.5\n",
+    )
+    .unwrap();
+    assert_eq!(config.species[0].repellentSensitivity, 1.0);
+    assert_eq!(config.species[1].repellentSensitivity, -0.5);
+}
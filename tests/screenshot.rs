@@ -0,0 +1,81 @@
+//! Screenshots carry the parameters they were made with.
+
+use slime_webgpu::{
+    screenshot::{self, ScreenshotError, ScreenshotInfo},
+    Config, SpeciesSettings,
+};
+
+#[test]
+fn png_metadata_round_trips() {
+    let dir = std::env::temp_dir().join("slime-screenshot-test");
+    let _ = std::fs::remove_dir_all(&dir);
+    let info = ScreenshotInfo {
+        species: vec![
+            SpeciesSettings::default(),
+            SpeciesSettings {
+                sensorAngleDegrees: 22.5,
+                repellentSensitivity: -0.25,
+                ..SpeciesSettings::default()
+            },
+        ],
+        seed: 0xdead_beef,
+        frame: 1234,
+    };
+    let image = image::RgbaImage::from_fn(3, 2, |x, y| image::Rgba([x as u8, y as u8, 7, 255]));
+    let texels = vec![[0.5, 0.0, 0.25, 1.0]; 2 * 3 * 2];
+    let paths = screenshot::save(&dir, &image, &texels, &info).unwrap();
+    assert_eq!(paths.len(), 3);
+    assert_eq!(paths[0], dir.join("screenshot-001234.png"));
+
+    assert_eq!(ScreenshotInfo::read_png(&paths[0]).unwrap(), info);
+    assert_eq!(image::open(&paths[0]).unwrap().to_rgba8(), image);
+    let mut config = Config::default();
+    info.apply_to(&mut config);
+    assert_eq!(config.species, info.species);
+    assert_eq!(config.simulation.seed, Some(0xdead_beef));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn screenshots_of_the_same_frame_are_kept() {
+    let dir = std::env::temp_dir().join("slime-screenshot-repeat-test");
+    let _ = std::fs::remove_dir_all(&dir);
+    let info = ScreenshotInfo {
+        species: vec![SpeciesSettings::default()],
+        seed: 1,
+        frame: 7,
+    };
+    let image = image::RgbaImage::new(2, 2);
+    let texels = vec![[0.0; 4]; 4];
+    let names = |paths: Vec<std::path::PathBuf>| -> Vec<String> {
+        paths
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect()
+    };
+    let save = || names(screenshot::save(&dir, &image, &texels, &info).unwrap());
+    assert_eq!(save(), ["screenshot-000007.png", "screenshot-000007.exr"]);
+    assert_eq!(
+        save(),
+        ["screenshot-000007-2.png", "screenshot-000007-2.exr"]
+    );
+    assert_eq!(
+        save(),
+        ["screenshot-000007-3.png", "screenshot-000007-3.exr"]
+    );
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 6);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn plain_png_has_no_metadata() {
+    let path = std::env::temp_dir().join("slime-screenshot-plain-test.png");
+    image::RgbaImage::new(2, 2).save(&path).unwrap();
+    let error = ScreenshotInfo::read_png(&path).unwrap_err();
+    std::fs::remove_file(path).unwrap();
+    assert!(
+        matches!(error, ScreenshotError::Metadata { key, .. } if key == "slime:species"),
+        "{}",
+        error
+    );
+}